@@ -14,6 +14,8 @@ pub struct Transpiler<'a> {
     files: FileDescriptorSet,
     service_generator: MergedServiceGenerator,
     preprocessors: Vec<Box<dyn Preprocessor + 'a>>,
+    #[cfg(feature = "json")]
+    json: bool,
 }
 
 impl<'a> Transpiler<'a> {
@@ -30,6 +32,8 @@ impl<'a> Transpiler<'a> {
             files: protox::compile(files, includes)?,
             service_generator: MergedServiceGenerator::empty(),
             preprocessors: Vec::new(),
+            #[cfg(feature = "json")]
+            json: false,
         })
     }
 
@@ -72,6 +76,15 @@ impl<'a> Transpiler<'a> {
         self
     }
 
+    /// Generate serde implementations of the proto3 JSON mapping for every message, for use with `nrpc::JsonCodec`.
+    ///
+    /// The generated code uses the `serde` and `pbjson` crates, which must be dependencies of the crate including it.
+    #[cfg(feature = "json")]
+    pub fn generate_json(mut self) -> Self {
+        self.json = true;
+        self
+    }
+
     /// Actually generate code
     pub fn transpile(mut self) -> std::io::Result<()> {
         let mut files = self.files;
@@ -84,10 +97,38 @@ impl<'a> Transpiler<'a> {
                 generated_str: generated,
             });
 
+        #[cfg(feature = "json")]
+        let json_files = if self.json { Some(files.clone()) } else { None };
+
         self.prost_config
             .service_generator(Box::new(self.service_generator))
-            .compile_fds(files)
+            .compile_fds(files)?;
+
+        #[cfg(feature = "json")]
+        if let Some(files) = json_files {
+            generate_json(&files, &std::env::var("OUT_DIR").unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+/// Append serde impls to the modules prost generated, so they are included along with the messages
+#[cfg(feature = "json")]
+fn generate_json(files: &FileDescriptorSet, out_dir: &str) -> std::io::Result<()> {
+    use prost::Message;
+    let out_dir = Path::new(out_dir);
+    let writers = pbjson_build::Builder::new()
+        .register_descriptors(&files.encode_to_vec())?
+        .generate(&["."], |package| {
+            std::fs::OpenOptions::new()
+                .append(true)
+                .open(out_dir.join(format!("{}.rs", package)))
+                .map(std::io::BufWriter::new)
+        })?;
+    for (_, mut writer) in writers {
+        std::io::Write::flush(&mut writer)?;
     }
+    Ok(())
 }
 
 struct PreprocessedCodeGenInjector {
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use prost_build::{Service, ServiceGenerator};
 use quote::quote;
@@ -20,7 +20,7 @@ impl ProtobufServiceGenerator {
             client_reexports: Vec::new(),
             server_reexports: Vec::new(),
             modules: Vec::new(),
-            out_dir: out_dir,
+            out_dir,
         }
     }
 
@@ -31,7 +31,7 @@ impl ProtobufServiceGenerator {
             client_reexports: Vec::new(),
             server_reexports: Vec::new(),
             modules: Vec::new(),
-            out_dir: out_dir,
+            out_dir,
         }
     }
 
@@ -42,7 +42,7 @@ impl ProtobufServiceGenerator {
             client_reexports: Vec::new(),
             server_reexports: Vec::new(),
             modules: Vec::new(),
-            out_dir: out_dir,
+            out_dir,
         }
     }
 }
@@ -65,31 +65,100 @@ fn stream_client_type(item_type: &syn::Ident) -> proc_macro2::TokenStream {
     }
 }*/
 
-fn trait_methods_server(descriptors: &Vec<prost_build::Method>) -> proc_macro2::TokenStream {
+fn method_kind(descriptor: &prost_build::Method) -> proc_macro2::TokenStream {
+    match (descriptor.client_streaming, descriptor.server_streaming) {
+        (false, false) => quote! { ::nrpc::MethodKind::Unary },
+        (true, false) => quote! { ::nrpc::MethodKind::ClientStreaming },
+        (false, true) => quote! { ::nrpc::MethodKind::ServerStreaming },
+        (true, true) => quote! { ::nrpc::MethodKind::BidiStreaming },
+    }
+}
+
+/// Bounds on the codec of a service, which has to handle every message type of its methods
+fn codec_bounds(descriptors: &[prost_build::Method]) -> proc_macro2::TokenStream {
+    let mut message_types: Vec<&String> = Vec::new();
+    for descriptor in descriptors {
+        for message_type in [&descriptor.input_type, &descriptor.output_type] {
+            if !message_types.contains(&message_type) {
+                message_types.push(message_type);
+            }
+        }
+    }
+    let message_types = message_types.into_iter().map(|message_type| quote::format_ident!("{}", message_type));
+    quote! {
+        #(::nrpc::Codec<#message_types> +)* Send + Sync + 'static
+    }
+}
+
+fn trait_methods_server(descriptors: &[prost_build::Method]) -> proc_macro2::TokenStream {
     let mut gen_methods = Vec::with_capacity(descriptors.len());
-    let mut gen_method_match_arms = Vec::with_capacity(descriptors.len());
     for descriptor in descriptors {
         let input_ty = quote::format_ident!("{}", descriptor.input_type);
         let output_ty = quote::format_ident!("{}", descriptor.output_type);
         let fn_name = quote::format_ident!("{}", descriptor.name);
-        let method_name = &descriptor.name;
         match (descriptor.client_streaming, descriptor.server_streaming) {
             (false, false) => {
                 // no streaming; 1->1
                 gen_methods.push(
                     quote! {
-                        async fn #fn_name(&mut self, input: #input_ty) -> Result<#output_ty, Box<dyn std::error::Error + Send>>;
+                        async fn #fn_name(&mut self, ctx: &::nrpc::CallContext, input: #input_ty) -> Result<#output_ty, Box<dyn std::error::Error + Send>>;
+                    }
+                );
+            }
+            (false, true) => {
+                // client streaming; 1 -> many
+                let stream_out_ty = stream_server_type(&output_ty);
+                gen_methods.push(
+                    quote! {
+                        async fn #fn_name<'a: 'b>(&mut self, ctx: &::nrpc::CallContext, input: #input_ty) -> Result<#stream_out_ty, Box<dyn std::error::Error + Send>>;
+                    }
+                );
+            }
+            (true, false) => {
+                // server streaming; many -> 1
+                let stream_in_ty = stream_server_type(&input_ty);
+                gen_methods.push(
+                    quote! {
+                        async fn #fn_name<'a: 'b>(&mut self, ctx: &::nrpc::CallContext, input: #stream_in_ty) -> Result<#output_ty, Box<dyn std::error::Error + Send>>;
                     }
                 );
+            }
+            (true, true) => {
+                // all streaming; many -> many
+                let stream_in_ty = stream_server_type(&input_ty);
+                let stream_out_ty = stream_server_type(&output_ty);
+                gen_methods.push(
+                    quote! {
+                        async fn #fn_name<'a: 'b>(&mut self, ctx: &::nrpc::CallContext, input: #stream_in_ty) -> Result<#stream_out_ty, Box<dyn std::error::Error + Send>>;
+                    }
+                );
+            }
+        }
+    }
+
+    quote! {
+        #(#gen_methods)*
+    }
+}
 
+fn dispatch_server(descriptors: &[prost_build::Method]) -> proc_macro2::TokenStream {
+    let mut gen_method_match_arms = Vec::with_capacity(descriptors.len());
+    for descriptor in descriptors {
+        let input_ty = quote::format_ident!("{}", descriptor.input_type);
+        let output_ty = quote::format_ident!("{}", descriptor.output_type);
+        let fn_name = quote::format_ident!("{}", descriptor.name);
+        let method_name = &descriptor.name;
+        match (descriptor.client_streaming, descriptor.server_streaming) {
+            (false, false) => {
+                // no streaming; 1->1
                 gen_method_match_arms.push(quote! {
                     #method_name => {
                         if let Some(item1_payload) = stream_in.next().await {
-                            let item = #input_ty::decode(item1_payload?)?;
+                            let item: #input_ty = ::nrpc::Codec::decode(&*self.codec, ctx, item1_payload?)?;
                             // TODO does it need to be enforced that there are no more items in the stream?
-                            let mut buffer = ::nrpc::_helpers::bytes::BytesMut::new();
-                            self.#fn_name(item).await?.encode(&mut buffer)?;
-                            Ok(Box::new(::nrpc::OnceStream::once(Ok(buffer.freeze()))))
+                            let output = self.inner.#fn_name(ctx, item).await?;
+                            let buffer = ::nrpc::Codec::encode(&*self.codec, ctx, &output)?;
+                            Ok(Box::new(::nrpc::OnceStream::once(Ok(buffer))))
                         } else {
                             Err(::nrpc::ServiceError::StreamLength { want: 1, got: 0 })
                         }
@@ -98,28 +167,17 @@ fn trait_methods_server(descriptors: &Vec<prost_build::Method>) -> proc_macro2::
             }
             (false, true) => {
                 // client streaming; 1 -> many
-                //let stream_out_ty = stream_type_static_lifetime(&output_ty);
-                let stream_out_ty = stream_server_type(&output_ty);
-                gen_methods.push(
-                    quote! {
-                        async fn #fn_name<'a: 'b>(&mut self, input: #input_ty) -> Result<#stream_out_ty, Box<dyn std::error::Error + Send>>;
-                    }
-                );
-
                 gen_method_match_arms.push(quote! {
                     #method_name => {
                         if let Some(item1_payload) = stream_in.next().await {
-                            let item = #input_ty::decode(item1_payload?)?;
+                            let item: #input_ty = ::nrpc::Codec::decode(&*self.codec, ctx, item1_payload?)?;
                             // TODO does it need to be enforced that there are no more items in the stream?
-                            let result = self.#fn_name(item).await?;
+                            let result = self.inner.#fn_name(ctx, item).await?;
+                            let codec = self.codec.clone();
+                            let call_ctx = ctx.clone();
                             Ok(Box::new(
                                 result.map(
-                                    |item_result| item_result.and_then(|item| {
-                                        let mut buffer = ::nrpc::_helpers::bytes::BytesMut::new();
-                                        item.encode(&mut buffer)
-                                            .map(|_| buffer.freeze())
-                                            .map_err(|e| ::nrpc::ServiceError::from(e))
-                                    })
+                                    move |item_result| item_result.and_then(|item| ::nrpc::Codec::<#output_ty>::encode(&*codec, &call_ctx, &item))
                                 )
                             ))
                         } else {
@@ -130,50 +188,34 @@ fn trait_methods_server(descriptors: &Vec<prost_build::Method>) -> proc_macro2::
             }
             (true, false) => {
                 // server streaming; many -> 1
-                let stream_in_ty = stream_server_type(&input_ty);
-                gen_methods.push(
-                    quote! {
-                        async fn #fn_name<'a: 'b>(&mut self, input: #stream_in_ty) -> Result<#output_ty, Box<dyn std::error::Error + Send>>;
-                    }
-                );
-
                 gen_method_match_arms.push(quote! {
                     #method_name => {
-                        let item_stream = stream_in.map(|item_result| item_result.and_then(|item1_payload| {
-                            #input_ty::decode(item1_payload)
-                                .map_err(|e| ::nrpc::ServiceError::from(e))
+                        let codec = self.codec.clone();
+                        let call_ctx = ctx.clone();
+                        let item_stream = stream_in.map(move |item_result| item_result.and_then(|item1_payload| {
+                            ::nrpc::Codec::<#input_ty>::decode(&*codec, &call_ctx, item1_payload)
                         }));
-                        let mut buffer = ::nrpc::_helpers::bytes::BytesMut::new();
-                        self.#fn_name(Box::new(item_stream)).await?.encode(&mut buffer)?;
-                        Ok(Box::new(::nrpc::OnceStream::once(Ok(buffer.freeze()))))
+                        let output = self.inner.#fn_name(ctx, Box::new(item_stream)).await?;
+                        let buffer = ::nrpc::Codec::encode(&*self.codec, ctx, &output)?;
+                        Ok(Box::new(::nrpc::OnceStream::once(Ok(buffer))))
                     }
                 });
             }
             (true, true) => {
                 // all streaming; many -> many
-                let stream_in_ty = stream_server_type(&input_ty);
-                let stream_out_ty = stream_server_type(&output_ty);
-                gen_methods.push(
-                    quote! {
-                        async fn #fn_name<'a: 'b>(&mut self, input: #stream_in_ty) -> Result<#stream_out_ty, Box<dyn std::error::Error + Send>>;
-                    }
-                );
-
                 gen_method_match_arms.push(quote! {
                     #method_name => {
-                        let item_stream = stream_in.map(|item_result| item_result.and_then(|item1_payload| {
-                            #input_ty::decode(item1_payload)
-                                .map_err(|e| ::nrpc::ServiceError::from(e))
+                        let codec = self.codec.clone();
+                        let call_ctx = ctx.clone();
+                        let item_stream = stream_in.map(move |item_result| item_result.and_then(|item1_payload| {
+                            ::nrpc::Codec::<#input_ty>::decode(&*codec, &call_ctx, item1_payload)
                         }));
-                        let result = self.#fn_name(Box::new(item_stream)).await?;
+                        let result = self.inner.#fn_name(ctx, Box::new(item_stream)).await?;
+                        let codec = self.codec.clone();
+                        let call_ctx = ctx.clone();
                         Ok(Box::new(
                             result.map(
-                                |item_result| item_result.and_then(|item| {
-                                    let mut buffer = ::nrpc::_helpers::bytes::BytesMut::new();
-                                    item.encode(&mut buffer)
-                                        .map(|_| buffer.freeze())
-                                        .map_err(|e| ::nrpc::ServiceError::from(e))
-                                })
+                                move |item_result| item_result.and_then(|item| ::nrpc::Codec::<#output_ty>::encode(&*codec, &call_ctx, &item))
                             )
                         ))
                     }
@@ -183,18 +225,10 @@ fn trait_methods_server(descriptors: &Vec<prost_build::Method>) -> proc_macro2::
     }
 
     quote! {
-        #(#gen_methods)*
-
-        /*async fn call(&mut self, method: &str, payload: ::nrpc::_helpers::bytes::Bytes, buffer: &mut ::nrpc::_helpers::bytes::BytesMut) -> Result<(), ::nrpc::ServiceError> {
-            match method {
-                #(#gen_method_match_arms)*
-                _ => Err(::nrpc::ServiceError::MethodNotFound)
-            }
-        }*/
-
-        async fn call<'a: 'b>(
+        async fn dispatch<'a: 'b>(
             &mut self,
             method: &str,
+            ctx: &::nrpc::CallContext,
             mut stream_in: ::nrpc::ServiceServerStream<'a, ::nrpc::_helpers::bytes::Bytes>,
         ) -> Result<::nrpc::ServiceServerStream<'a, ::nrpc::_helpers::bytes::Bytes>, ::nrpc::ServiceError> {
             match method {
@@ -208,26 +242,41 @@ fn trait_methods_server(descriptors: &Vec<prost_build::Method>) -> proc_macro2::
 fn struct_methods_client(
     package_name: &str,
     service_name: &str,
-    descriptors: &Vec<prost_build::Method>,
+    descriptors: &[prost_build::Method],
 ) -> proc_macro2::TokenStream {
     let mut gen_methods = Vec::with_capacity(descriptors.len());
     for descriptor in descriptors {
         let input_ty = quote::format_ident!("{}", descriptor.input_type);
         let output_ty = quote::format_ident!("{}", descriptor.output_type);
         let fn_name = quote::format_ident!("{}", descriptor.name);
+        let fn_name_ctx = quote::format_ident!("{}_with_context", descriptor.name);
         let method_name = &descriptor.name;
+        let kind = method_kind(descriptor);
         match (descriptor.client_streaming, descriptor.server_streaming) {
             (false, false) => {
                 // no streaming; 1->1
                 gen_methods.push(
                     quote! {
                         pub async fn #fn_name(&self, input: #input_ty) -> Result<#output_ty, ::nrpc::ServiceError> {
-                            let mut in_buf = ::nrpc::_helpers::bytes::BytesMut::new();
-                            input.encode(&mut in_buf)?;
-                            let in_stream = ::nrpc::OnceStream::once(Ok(in_buf.freeze()));
-                            let mut result_stream = self.inner.call(#package_name, #service_name, #method_name, Box::new( in_stream)).await?;
+                            self.#fn_name_ctx(&::nrpc::CallContext::default(), input).await
+                        }
+
+                        pub async fn #fn_name_ctx(&self, ctx: &::nrpc::CallContext, input: #input_ty) -> Result<#output_ty, ::nrpc::ServiceError> {
+                            // cancel the call if this future is dropped before it completes
+                            let _cancel_guard = ctx.cancel_on_drop();
+                            let span = ::nrpc::CallSpan::client(#package_name, #service_name, #method_name, #kind);
+                            // let the transport know the shape of the call
+                            let ctx = &ctx.clone().with_method_kind(#kind);
+                            let in_buf = ::nrpc::Codec::encode(&*self.codec, ctx, &input)?;
+                            let in_stream = ::nrpc::OnceStream::once(Ok(in_buf));
+                            let mut result_stream = span.received(span.instrument(self.inner.call(#package_name, #service_name, #method_name, ctx, Box::new(span.sent(in_stream)))).await?);
                             if let Some(out_result) = result_stream.next().await {
-                                Ok(#output_ty::decode(out_result?)?)
+                                let output: #output_ty = ::nrpc::Codec::decode(&*self.codec, ctx, out_result?)?;
+                                // drain the response, so that the call is complete
+                                while let Some(out_result) = result_stream.next().await {
+                                    out_result?;
+                                }
+                                Ok(output)
                             } else {
                                 Err(::nrpc::ServiceError::StreamLength { want: 1, got: 0 })
                             }
@@ -242,16 +291,24 @@ fn struct_methods_client(
                 gen_methods.push(
                     quote! {
                         pub async fn #fn_name<'a: 'b>(&self, input: #input_ty) -> Result<#stream_out_ty, ::nrpc::ServiceError> {
-                            let mut in_buf = ::nrpc::_helpers::bytes::BytesMut::new();
-                            input.encode(&mut in_buf)?;
-                            let in_stream = ::nrpc::OnceStream::once(Ok(in_buf.freeze()));
-                            let result_stream = self.inner.call(#package_name, #service_name, #method_name, Box::new(in_stream)).await?;
-                            let item_stream = result_stream.map(|out_result|
-                                out_result.and_then(|out_buf| #output_ty::decode(out_buf)
-                                    .map_err(|e| ::nrpc::ServiceError::from(e))
-                                )
+                            self.#fn_name_ctx(&::nrpc::CallContext::default(), input).await
+                        }
+
+                        pub async fn #fn_name_ctx<'a: 'b>(&self, ctx: &::nrpc::CallContext, input: #input_ty) -> Result<#stream_out_ty, ::nrpc::ServiceError> {
+                            // cancel the call if this future or the response stream is dropped before it completes
+                            let cancel_guard = ctx.cancel_on_drop();
+                            let span = ::nrpc::CallSpan::client(#package_name, #service_name, #method_name, #kind);
+                            // let the transport know the shape of the call
+                            let ctx = &ctx.clone().with_method_kind(#kind);
+                            let in_buf = ::nrpc::Codec::encode(&*self.codec, ctx, &input)?;
+                            let in_stream = ::nrpc::OnceStream::once(Ok(in_buf));
+                            let result_stream = span.received(span.instrument(self.inner.call(#package_name, #service_name, #method_name, ctx, Box::new(span.sent(in_stream)))).await?);
+                            let codec = self.codec.clone();
+                            let call_ctx = ctx.clone();
+                            let item_stream = result_stream.map(move |out_result|
+                                out_result.and_then(|out_buf| ::nrpc::Codec::<#output_ty>::decode(&*codec, &call_ctx, out_buf))
                             );
-                            Ok(Box::new(item_stream))
+                            Ok(Box::new(cancel_guard.wrap(item_stream)))
                         }
                     }
                 );
@@ -262,16 +319,28 @@ fn struct_methods_client(
                 gen_methods.push(
                     quote! {
                         pub async fn #fn_name<'a: 'b>(&self, input: #stream_in_ty) -> Result<#output_ty, ::nrpc::ServiceError> {
-                            let in_stream = input.map(|item_result| {
-                                let mut in_buf = ::nrpc::_helpers::bytes::BytesMut::new();
-                                item_result.and_then(|item| item.encode(&mut in_buf)
-                                    .map(|_| in_buf.freeze())
-                                    .map_err(|e| ::nrpc::ServiceError::from(e))
-                                )
+                            self.#fn_name_ctx(&::nrpc::CallContext::default(), input).await
+                        }
+
+                        pub async fn #fn_name_ctx<'a: 'b>(&self, ctx: &::nrpc::CallContext, input: #stream_in_ty) -> Result<#output_ty, ::nrpc::ServiceError> {
+                            // cancel the call if this future is dropped before it completes
+                            let _cancel_guard = ctx.cancel_on_drop();
+                            let span = ::nrpc::CallSpan::client(#package_name, #service_name, #method_name, #kind);
+                            // let the transport know the shape of the call
+                            let ctx = &ctx.clone().with_method_kind(#kind);
+                            let codec = self.codec.clone();
+                            let call_ctx = ctx.clone();
+                            let in_stream = input.map(move |item_result| {
+                                item_result.and_then(|item| ::nrpc::Codec::encode(&*codec, &call_ctx, &item))
                             });
-                            let mut result_stream = self.inner.call(#package_name, #service_name, #method_name, Box::new(in_stream)).await?;
+                            let mut result_stream = span.received(span.instrument(self.inner.call(#package_name, #service_name, #method_name, ctx, Box::new(span.sent(in_stream)))).await?);
                             if let Some(out_result) = result_stream.next().await {
-                                Ok(#output_ty::decode(out_result?)?)
+                                let output: #output_ty = ::nrpc::Codec::decode(&*self.codec, ctx, out_result?)?;
+                                // drain the response, so that the call is complete
+                                while let Some(out_result) = result_stream.next().await {
+                                    out_result?;
+                                }
+                                Ok(output)
                             } else {
                                 Err(::nrpc::ServiceError::StreamLength { want: 1, got: 0 })
                             }
@@ -287,20 +356,27 @@ fn struct_methods_client(
                 gen_methods.push(
                     quote! {
                         pub async fn #fn_name<'a: 'b>(&self, input: #stream_in_ty) -> Result<#stream_out_ty, ::nrpc::ServiceError> {
-                            let in_stream = input.map(|item_result| {
-                                let mut in_buf = ::nrpc::_helpers::bytes::BytesMut::new();
-                                item_result.and_then(|item| item.encode(&mut in_buf)
-                                    .map(|_| in_buf.freeze())
-                                    .map_err(|e| ::nrpc::ServiceError::from(e))
-                                )
+                            self.#fn_name_ctx(&::nrpc::CallContext::default(), input).await
+                        }
+
+                        pub async fn #fn_name_ctx<'a: 'b>(&self, ctx: &::nrpc::CallContext, input: #stream_in_ty) -> Result<#stream_out_ty, ::nrpc::ServiceError> {
+                            // cancel the call if this future or the response stream is dropped before it completes
+                            let cancel_guard = ctx.cancel_on_drop();
+                            let span = ::nrpc::CallSpan::client(#package_name, #service_name, #method_name, #kind);
+                            // let the transport know the shape of the call
+                            let ctx = &ctx.clone().with_method_kind(#kind);
+                            let codec = self.codec.clone();
+                            let call_ctx = ctx.clone();
+                            let in_stream = input.map(move |item_result| {
+                                item_result.and_then(|item| ::nrpc::Codec::encode(&*codec, &call_ctx, &item))
                             });
-                            let result_stream = self.inner.call(#package_name, #service_name, #method_name, Box::new(in_stream)).await?;
-                            let item_stream = result_stream.map(|out_result|
-                                out_result.and_then(|out_buf| #output_ty::decode(out_buf)
-                                    .map_err(|e| ::nrpc::ServiceError::from(e))
-                                )
+                            let result_stream = span.received(span.instrument(self.inner.call(#package_name, #service_name, #method_name, ctx, Box::new(span.sent(in_stream)))).await?);
+                            let codec = self.codec.clone();
+                            let call_ctx = ctx.clone();
+                            let item_stream = result_stream.map(move |out_result|
+                                out_result.and_then(|out_buf| ::nrpc::Codec::<#output_ty>::decode(&*codec, &call_ctx, out_buf))
                             );
-                            Ok(Box::new(item_stream))
+                            Ok(Box::new(cancel_guard.wrap(item_stream)))
 
                         }
                     }
@@ -314,7 +390,7 @@ fn struct_methods_client(
     }
 }
 
-fn generate_mod_rs(module_names: &Vec<String>, out_dir: &PathBuf) {
+fn generate_mod_rs(module_names: &[String], out_dir: &Path) {
     // generate mod.rs
     let modules = module_names.iter().map(|m| {
         let mod_ident = quote::format_ident!("{}", m);
@@ -336,15 +412,24 @@ impl ServiceGenerator for ProtobufServiceGenerator {
                 quote::format_ident!("{}_mod_server", service.name.to_lowercase());
             let service_trait_name = quote::format_ident!("{}Service", service.name);
             let service_trait_methods = trait_methods_server(&service.methods);
+            let service_dispatch = dispatch_server(&service.methods);
+            let codec_bounds = codec_bounds(&service.methods);
             let service_struct_name = quote::format_ident!("{}ServiceImpl", service.name);
             let descriptor_str = format!("{}.{}", service.package, service.name);
             let service_struct_rename = quote::format_ident!("{}Server", service.name);
             let service_trait_rename = quote::format_ident!("I{}", service.name);
+            let package_name = &service.package;
+            let service_name = &service.name;
+            let method_names = service.methods.iter().map(|descriptor| &descriptor.name);
+            let method_kind_arms = service.methods.iter().map(|descriptor| {
+                let method_name = &descriptor.name;
+                let kind = method_kind(descriptor);
+                quote! { #method_name => #kind, }
+            });
             let gen_service = quote! {
                 mod #service_mod_name {
                     use super::*;
                     use ::nrpc::_helpers::async_trait::async_trait;
-                    use ::nrpc::_helpers::prost::Message;
                     use ::nrpc::_helpers::futures::StreamExt;
 
                     #[async_trait]
@@ -352,32 +437,70 @@ impl ServiceGenerator for ProtobufServiceGenerator {
                         #service_trait_methods
                     }
 
-                    pub struct #service_struct_name<'b, T: #service_trait_name<'b>> {
+                    pub struct #service_struct_name<'b, T: #service_trait_name<'b>, C = ::nrpc::ProstCodec> {
                         inner: T,
+                        codec: std::sync::Arc<C>,
                         _idc: std::marker::PhantomData<&'b ()>,
                     }
 
                     impl <'b, T: #service_trait_name<'b>> #service_struct_name<'b, T> {
                         pub fn new(inner: T) -> Self {
+                            Self::with_codec(inner, ::nrpc::ProstCodec)
+                        }
+                    }
+
+                    impl <'b, T: #service_trait_name<'b>, C> #service_struct_name<'b, T, C> {
+                        /// Encode and decode messages with `codec` instead of protobuf binary
+                        pub fn with_codec(inner: T, codec: C) -> Self {
                             Self {
                                 inner,
+                                codec: std::sync::Arc::new(codec),
+                                _idc: Default::default(),
+                            }
+                        }
+                    }
+
+                    impl <'b, T: #service_trait_name<'b> + Clone, C> Clone for #service_struct_name<'b, T, C> {
+                        fn clone(&self) -> Self {
+                            Self {
+                                inner: self.inner.clone(),
+                                codec: self.codec.clone(),
                                 _idc: Default::default(),
                             }
                         }
                     }
 
+                    impl<'b, T: #service_trait_name<'b>, C: #codec_bounds> #service_struct_name<'b, T, C> {
+                        #service_dispatch
+                    }
+
                     #[async_trait]
-                    impl<'b, T: #service_trait_name<'b>> ::nrpc::ServerService<'b> for #service_struct_name<'b, T> {
+                    impl<'b, T: #service_trait_name<'b>, C: #codec_bounds> ::nrpc::ServerService<'b> for #service_struct_name<'b, T, C> {
                         fn descriptor(&self) -> &'static str {
                             #descriptor_str
                         }
 
+                        fn method_kind(&self, method: &str) -> Option<::nrpc::MethodKind> {
+                            Some(match method {
+                                #(#method_kind_arms)*
+                                _ => return None,
+                            })
+                        }
+
+                        fn methods(&self) -> &'static [&'static str] {
+                            &[#(#method_names),*]
+                        }
+
                         async fn call<'a: 'b>(
                             &mut self,
                             method: &str,
+                            ctx: &::nrpc::CallContext,
                             input: ::nrpc::ServiceServerStream<'a, ::nrpc::_helpers::bytes::Bytes>,
                         ) -> Result<::nrpc::ServiceServerStream<'a, ::nrpc::_helpers::bytes::Bytes>, ::nrpc::ServiceError> {
-                            self.inner.call(method, input).await
+                            let kind = ::nrpc::ServerService::method_kind(self, method).ok_or(::nrpc::ServiceError::MethodNotFound)?;
+                            let span = ::nrpc::CallSpan::server(#package_name, #service_name, method, kind);
+                            let output = span.instrument(self.dispatch(method, ctx, Box::new(span.received(input)))).await?;
+                            Ok(Box::new(span.sent(output)))
                         }
                     }
                 }
@@ -398,22 +521,23 @@ impl ServiceGenerator for ProtobufServiceGenerator {
                 quote::format_ident!("{}_mod_client", service.name.to_lowercase());
             let service_methods =
                 struct_methods_client(&service.package, &service.name, &service.methods);
+            let codec_bounds = codec_bounds(&service.methods);
             let service_struct_name = quote::format_ident!("{}Service", service.name);
             let descriptor_str = format!("{}.{}", service.package, service.name);
             let service_rename = quote::format_ident!("{}Client", service.name);
             let gen_client = quote! {
                 mod #service_mod_name {
                     use super::*;
-                    use ::nrpc::_helpers::prost::Message;
                     use ::nrpc::_helpers::futures::StreamExt;
 
                     //#[derive(core::any::Any)]
-                    pub struct #service_struct_name<'b, T: ::nrpc::ClientHandler<'b>> {
+                    pub struct #service_struct_name<'b, T: ::nrpc::ClientHandler<'b>, C = ::nrpc::ProstCodec> {
                         inner: T,
+                        codec: std::sync::Arc<C>,
                         _idc: std::marker::PhantomData<&'b ()>,
                     }
 
-                    impl <'b, T: ::nrpc::ClientHandler<'b>> ::nrpc::ClientService for #service_struct_name<'b, T> {
+                    impl <'b, T: ::nrpc::ClientHandler<'b>, C> ::nrpc::ClientService for #service_struct_name<'b, T, C> {
                         fn descriptor(&self) -> &'static str {
                             #descriptor_str
                         }
@@ -421,12 +545,22 @@ impl ServiceGenerator for ProtobufServiceGenerator {
 
                     impl <'b, T: ::nrpc::ClientHandler<'b>> #service_struct_name<'b, T> {
                         pub fn new(inner: T) -> Self {
+                            Self::with_codec(inner, ::nrpc::ProstCodec)
+                        }
+                    }
+
+                    impl <'b, T: ::nrpc::ClientHandler<'b>, C> #service_struct_name<'b, T, C> {
+                        /// Encode and decode messages with `codec` instead of protobuf binary
+                        pub fn with_codec(inner: T, codec: C) -> Self {
                             Self {
                                 inner,
+                                codec: std::sync::Arc::new(codec),
                                 _idc: Default::default(),
                             }
                         }
+                    }
 
+                    impl <'b, T: ::nrpc::ClientHandler<'b>, C: #codec_bounds> #service_struct_name<'b, T, C> {
                         #service_methods
                     }
                 }
@@ -1,3 +1,17 @@
 fn main() {
-    nrpc_build::compile(["./proto/helloworld.proto"], ["."])
+    nrpc_build::Transpiler::new(["./proto/helloworld.proto"], ["."])
+        .unwrap()
+        .generate_all()
+        .generate_json()
+        .transpile()
+        .unwrap();
+
+    // tonic's code for the same service, in its own directory
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("tonic");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    prost_build::Config::new()
+        .out_dir(&out_dir)
+        .service_generator(tonic_build::configure().service_generator())
+        .compile_fds(protox::compile(["./proto/helloworld.proto"], ["."]).unwrap())
+        .unwrap();
 }
@@ -1,7 +1,10 @@
 use std::error::Error;
 use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
-use nrpc::_helpers::futures::StreamExt;
+use nrpc::_helpers::futures;
+use nrpc::_helpers::futures::{SinkExt, StreamExt};
 use nrpc::{ServerService, ServiceError};
 use prost::Message;
 
@@ -11,11 +14,16 @@ pub mod generated {
 
 pub use generated::*;
 
+/// tonic's code for the same service, to check that nRPC interoperates with other gRPC implementations
+pub mod tonic_helloworld {
+    include!(concat!(env!("OUT_DIR"), "/tonic/helloworld.rs"));
+}
+
 #[tokio::main]
 async fn main() {
     // NOTE: This doesn't test network functionality
     // it just checks generated code for correctness (compile-time)
-    // and tests mock client & server traits implementations
+    // and tests generated clients against generated servers through a loopback
     let req = helloworld::HelloRequest {
         name: "World".into(),
     };
@@ -23,6 +31,7 @@ async fn main() {
         message: "Hello World".into(),
     };
     let original_resp = resp.clone();
+    let ctx = nrpc::CallContext::new();
     // server
     let mut service_impl = helloworld::GreeterServer::new(GreeterService);
 
@@ -32,7 +41,7 @@ async fn main() {
     req.clone().encode(&mut input_buf).unwrap();
     let stream_in = nrpc::OnceStream::once(Ok(input_buf.into()));
     let mut output_stream = service_impl
-        .call("say_hello", Box::new(stream_in))
+        .call("say_hello", &ctx, Box::new(stream_in))
         .await
         .unwrap();
     let output_buf = output_stream.next().await.unwrap().unwrap();
@@ -40,10 +49,18 @@ async fn main() {
     assert_eq!(resp, actual_resp);
 
     // client one to one
-    let client_impl = helloworld::GreeterClient::new(ClientHandler);
+    let client_impl = helloworld::GreeterClient::new(nrpc::LoopbackClientHandler::from_service(
+        helloworld::GreeterServer::new(GreeterService),
+    ));
     let resp = client_impl.say_hello(req.clone()).await.unwrap();
     assert_eq!(resp, actual_resp);
 
+    // client one to one with metadata
+    let mut greeting_ctx = nrpc::CallContext::new();
+    greeting_ctx.metadata_mut().insert("greeting", "Howdy").unwrap();
+    let resp = client_impl.say_hello_with_context(&greeting_ctx, req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Howdy World");
+
     // server many to one
     let resp = helloworld::HelloReply {
         message: "Hello World0, World1, World2".into(),
@@ -54,7 +71,7 @@ async fn main() {
         Ok(input_buf.freeze())
     }));
     let mut output_stream = service_impl
-        .call("say_hello_many_to_one", Box::new(stream_in))
+        .call("say_hello_many_to_one", &ctx, Box::new(stream_in))
         .await
         .unwrap();
     let output_buf = output_stream.next().await.unwrap().unwrap();
@@ -62,11 +79,10 @@ async fn main() {
     assert_eq!(resp, actual_resp);
 
     // client many to one
-    let client_impl = helloworld::GreeterClient::new(ClientHandler);
     let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
         Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
     let resp = client_impl.say_hello_many_to_one(Box::new(stream_in)).await.unwrap();
-    assert_eq!(resp, original_resp);
+    assert_eq!(resp, actual_resp);
 
     // server one to many
     let resp = vec![
@@ -85,16 +101,40 @@ async fn main() {
     req.clone().encode(&mut input_buf).unwrap();
     let stream_in = nrpc::OnceStream::once(Ok(input_buf.into()));
     let output_stream = service_impl
-        .call("say_hello_one_to_many", Box::new(stream_in))
+        .call("say_hello_one_to_many", &ctx, Box::new(stream_in))
         .await
         .unwrap();
     let actual_resp: Vec<_> = output_stream.map(|buf_result| helloworld::HelloReply::decode(buf_result.unwrap()).unwrap()).collect().await;
     assert_eq!(resp, actual_resp);
 
     // client one to many
-    let client_impl = helloworld::GreeterClient::new(ClientHandler);
     let resp: Vec<_> = client_impl.say_hello_one_to_many(req.clone()).await.unwrap().map(|item_result| item_result.unwrap()).collect().await;
-    assert_eq!(resp, vec![original_resp.clone()]);
+    assert_eq!(resp, actual_resp);
+
+    // client one to many with trailer
+    let trailer_ctx = nrpc::CallContext::new();
+    let resp: Vec<_> = client_impl.say_hello_one_to_many_with_context(&trailer_ctx, req.clone()).await.unwrap().map(|item_result| item_result.unwrap()).collect().await;
+    assert_eq!(resp, actual_resp);
+    let trailer = trailer_ctx.trailer().await;
+    assert!(trailer.is_ok());
+    assert_eq!(trailer.metadata.get("greeting-count"), Some("3"));
+
+    // client status error
+    let trailer_ctx = nrpc::CallContext::new();
+    let result = client_impl.say_hello_with_context(&trailer_ctx, helloworld::HelloRequest::default()).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::InvalidArgument));
+    assert_eq!(trailer_ctx.trailer().await.status.code(), nrpc::Code::InvalidArgument);
+
+    // client one to many cancelled by dropping the response stream
+    let endless_req = helloworld::HelloRequest { name: "Endless".into() };
+    let cancel_ctx = nrpc::CallContext::new();
+    let mut resp = client_impl.say_hello_one_to_many_with_context(&cancel_ctx, endless_req.clone()).await.unwrap();
+    resp.next().await.unwrap().unwrap();
+    resp.next().await.unwrap().unwrap();
+    drop(resp);
+    wait_for_cancelled_calls(1).await;
+    assert!(cancel_ctx.is_cancelled());
+    assert_eq!(cancel_ctx.trailer().await.status.code(), nrpc::Code::Cancelled);
 
     // server many to many
     let resp = vec![
@@ -114,30 +154,1511 @@ async fn main() {
         Ok(input_buf.freeze())
     }));
     let output_stream = service_impl
-        .call("say_hello_many_to_many", Box::new(stream_in))
+        .call("say_hello_many_to_many", &ctx, Box::new(stream_in))
         .await
         .unwrap();
     let actual_resp: Vec<_> = output_stream.map(|buf_result| helloworld::HelloReply::decode(buf_result.unwrap()).unwrap()).collect().await;
     assert_eq!(resp, actual_resp);
 
     // client many to many
-    let client_impl = helloworld::GreeterClient::new(ClientHandler);
     let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
         Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
     let resp: Vec<_> = client_impl.say_hello_many_to_many(Box::new(stream_in)).await.unwrap().map(|item_result| item_result.unwrap()).collect().await;
-    assert_eq!(resp, vec![original_resp.clone(); 3]);
+    assert_eq!(resp, actual_resp);
+
+    // registry dispatch
+    let registry = nrpc::ServiceRegistry::new()
+        .with(helloworld::GreeterServer::new(GreeterService));
+    let mut input_buf = bytes::BytesMut::new();
+    req.clone().encode(&mut input_buf).unwrap();
+    let stream_in = nrpc::OnceStream::once(Ok(input_buf.freeze()));
+    let mut output_stream = registry
+        .call("helloworld", "Greeter", "say_hello", &ctx, Box::new(stream_in))
+        .await
+        .unwrap();
+    let output_buf = output_stream.next().await.unwrap().unwrap();
+    let actual_resp = helloworld::HelloReply::decode(output_buf).unwrap();
+    assert_eq!(original_resp, actual_resp);
+    let result = registry
+        .call("helloworld", "Greeter", "say_goodbye", &ctx, Box::new(nrpc::EmptyStream::default()))
+        .await;
+    assert!(matches!(result, Err(ServiceError::MethodNotFound)));
+    let result = registry
+        .call("helloworld", "Farewell", "say_hello", &ctx, Box::new(nrpc::EmptyStream::default()))
+        .await;
+    assert!(matches!(result, Err(ServiceError::ServiceNotFound)));
+
+    // loopback many to one waiting for its input, alongside a unary call to the same cloned service
+    {
+        let client_impl = helloworld::GreeterClient::new(nrpc::LoopbackClientHandler::new(
+            nrpc::ServiceRegistry::new().with_cloned(helloworld::GreeterServer::new(GreeterService)),
+        ));
+        let (requests, stream_in) = futures::channel::mpsc::unbounded();
+        requests.unbounded_send(helloworld::HelloRequest { name: "Slow".into() }).unwrap();
+        let mut stalled = Box::pin(client_impl.say_hello_many_to_one(Box::new(stream_in.map(Ok))));
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(100), &mut stalled).await.is_err());
+        let unary = client_impl.say_hello(helloworld::HelloRequest { name: "Fast".into() });
+        let resp = tokio::time::timeout(std::time::Duration::from_secs(5), unary)
+            .await
+            .expect("Unary call was held up by a stalled call");
+        assert_eq!(resp.unwrap().message, "Hello Fast");
+        drop(requests);
+        assert_eq!(stalled.await.unwrap().message, "Hello Slow");
+    }
+
+    // wire framing round trip
+    let mut request_writer = nrpc::wire::FrameWriter::new(futures::io::Cursor::new(Vec::new()));
+    request_writer
+        .send(nrpc::wire::Frame::call(0, nrpc::wire::CallHeader::new("helloworld", "Greeter", "say_hello_many_to_many")))
+        .await
+        .unwrap();
+    let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)| {
+        let mut input_buf = bytes::BytesMut::new();
+        helloworld::HelloRequest { name: format!("World{}", i) }.encode(&mut input_buf).expect("Protobuf encoding error");
+        Ok(input_buf.freeze())
+    }));
+    request_writer
+        .send_all(&mut nrpc::wire::encode_messages(0, stream_in).map(Ok))
+        .await
+        .unwrap();
+    let request_bytes = request_writer.into_inner().into_inner();
+    let mut request_frames = nrpc::wire::FrameReader::new(futures::io::Cursor::new(request_bytes));
+    let header = match request_frames.next().await.unwrap().unwrap().body {
+        nrpc::wire::FrameBody::Call(header) => header,
+        body => panic!("Expected call frame, got {:?}", body),
+    };
+    let output = registry
+        .call(&header.package, &header.service, &header.method, &ctx, Box::new(nrpc::wire::decode_messages(request_frames)))
+        .await;
+    let mut response_writer = nrpc::wire::FrameWriter::new(futures::io::Cursor::new(Vec::new()));
+    nrpc::wire::respond(&mut response_writer, 0, &ctx, output).await.unwrap();
+    response_writer.flush().await.unwrap();
+    let response_bytes = response_writer.into_inner().into_inner();
+    let response_frames = nrpc::wire::FrameReader::new(futures::io::Cursor::new(response_bytes));
+    let actual_resp: Vec<_> = nrpc::wire::decode_messages(response_frames)
+        .map(|buf_result| helloworld::HelloReply::decode(buf_result.unwrap()).unwrap())
+        .collect()
+        .await;
+    assert_eq!(resp, actual_resp);
+
+    interceptors(req.clone()).await;
+    tower_middleware(req.clone()).await;
+    tracing_spans(req.clone()).await;
+    metrics_middleware(req.clone()).await;
+    retries(req.clone()).await;
+    compression(req.clone()).await;
+    custom_codec(req.clone()).await;
+    json_codec(req.clone()).await;
+    websocket_transport(req.clone()).await;
+    grpc_web_transport(req.clone()).await;
+    connect_transport(req.clone()).await;
+    twirp_transport(req.clone()).await;
+    h2_transport(req.clone()).await;
+    socket_transport(req).await;
+}
+
+async fn interceptors(req: helloworld::HelloRequest) {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let server = nrpc::InterceptedService::new(helloworld::GreeterServer::new(GreeterService))
+        .with(RequireAuth);
+    let client_impl = helloworld::GreeterClient::new(
+        nrpc::InterceptedClientHandler::new(nrpc::LoopbackClientHandler::from_service(server))
+            .with(RecordCalls(log.clone()))
+            .with(DenyMethod("say_hello_many_to_many"))
+            .with(AddAuth("Bearer secret")),
+    );
+
+    // interceptors run in order, and see the result of the call
+    let resp = client_impl.say_hello(req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Hello World");
+    assert_eq!(*log.lock().unwrap(), vec!["say_hello start", "say_hello ok"]);
+
+    // short-circuited call
+    let stream_in = nrpc::VecStream::from_iter(std::iter::once(Ok(req.clone())));
+    let result = client_impl.say_hello_many_to_many(Box::new(stream_in)).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::PermissionDenied));
+    assert_eq!(log.lock().unwrap()[3], "say_hello_many_to_many error");
+
+    // server interceptor rejecting a call without the right metadata
+    let server = nrpc::InterceptedService::new(helloworld::GreeterServer::new(GreeterService))
+        .with(RequireAuth);
+    let client_impl = helloworld::GreeterClient::new(
+        nrpc::InterceptedClientHandler::new(nrpc::LoopbackClientHandler::from_service(server))
+            .with(AddAuth("Bearer wrong")),
+    );
+    let result = client_impl.say_hello(req).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::Unauthenticated));
+}
+
+async fn tower_middleware(req: helloworld::HelloRequest) {
+    // server service behind a tower layer
+    let server = nrpc::TowerServerService::layered(
+        helloworld::GreeterServer::new(GreeterService),
+        tower::limit::ConcurrencyLimitLayer::new(1),
+    );
+    assert_eq!(server.method_kind("say_hello_many_to_one"), Some(nrpc::MethodKind::ClientStreaming));
+    assert_eq!(server.method_kind("say_goodbye"), None);
+    let client_impl = helloworld::GreeterClient::new(nrpc::LoopbackClientHandler::from_service(server));
+    let resp = client_impl.say_hello(req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Hello World");
+
+    // client handler behind a tower layer
+    let client_impl = helloworld::GreeterClient::new(nrpc::TowerClientHandler::layered(
+        nrpc::LoopbackClientHandler::from_service(helloworld::GreeterServer::new(GreeterService)),
+        tower::timeout::TimeoutLayer::new(std::time::Duration::from_millis(100)),
+    ));
+    let resp = client_impl.say_hello(req).await.unwrap();
+    assert_eq!(resp.message, "Hello World");
+    let result = client_impl.say_hello(helloworld::HelloRequest { name: "Sleepy".into() }).await;
+    assert!(matches!(result, Err(ServiceError::Method(ref e)) if e.to_string() == "request timed out"));
+
+    // errors from the wrapped handler come through unchanged
+    let result = client_impl.say_hello(helloworld::HelloRequest::default()).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::InvalidArgument));
+}
+
+async fn tracing_spans(req: helloworld::HelloRequest) {
+    let subscriber = RecordSpans::default();
+    let spans = subscriber.spans.clone();
+    let _default = tracing::subscriber::set_default(subscriber);
+    let client_impl = helloworld::GreeterClient::new(nrpc::LoopbackClientHandler::from_service(
+        helloworld::GreeterServer::new(GreeterService),
+    ));
+
+    let resp: Vec<_> = client_impl.say_hello_one_to_many(req).await.unwrap().map(|item_result| item_result.unwrap()).collect().await;
+    assert_eq!(resp.len(), 3);
+    let _ = client_impl.say_hello(helloworld::HelloRequest::default()).await;
+
+    let spans = spans.lock().unwrap();
+    let find = |name: &str, method: &str| {
+        spans
+            .iter()
+            .find(|(span_name, fields)| *span_name == name && fields.get("rpc.method").map(String::as_str) == Some(method))
+            .map(|(_, fields)| fields.clone())
+            .unwrap_or_else(|| panic!("No {} span for {}", name, method))
+    };
+    for name in ["nrpc.client", "nrpc.server"] {
+        let fields = find(name, "say_hello_one_to_many");
+        assert_eq!(fields["rpc.package"], "helloworld");
+        assert_eq!(fields["rpc.service"], "Greeter");
+        assert_eq!(fields["rpc.kind"], "server_streaming");
+        assert_eq!(fields["rpc.outcome"], "ok");
+        let fields = find(name, "say_hello");
+        assert_eq!(fields["rpc.kind"], "unary");
+        assert_eq!(fields["rpc.outcome"], "error");
+        assert_eq!(fields["rpc.code"], "InvalidArgument");
+    }
+}
+
+type SpanFields = std::collections::HashMap<String, String>;
+
+/// Subscriber which keeps the name and fields of every span
+#[derive(Default)]
+struct RecordSpans {
+    spans: Arc<Mutex<Vec<(&'static str, SpanFields)>>>,
+}
+
+struct RecordFields<'a>(&'a mut SpanFields);
+
+impl tracing::field::Visit for RecordFields<'_> {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        self.0.insert(field.name().to_owned(), value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_owned(), format!("{:?}", value));
+    }
+}
+
+impl tracing::Subscriber for RecordSpans {
+    fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        let mut spans = self.spans.lock().unwrap();
+        let mut fields = SpanFields::new();
+        attrs.record(&mut RecordFields(&mut fields));
+        spans.push((attrs.metadata().name(), fields));
+        tracing::span::Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, span: &tracing::span::Id, values: &tracing::span::Record<'_>) {
+        let mut spans = self.spans.lock().unwrap();
+        values.record(&mut RecordFields(&mut spans[span.into_u64() as usize - 1].1));
+    }
+
+    fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+    fn event(&self, _event: &tracing::Event<'_>) {}
+
+    fn enter(&self, _span: &tracing::span::Id) {}
+
+    fn exit(&self, _span: &tracing::span::Id) {}
+}
+
+async fn metrics_middleware(req: helloworld::HelloRequest) {
+    let recorder = RecordMetrics::default();
+    let counters = recorder.counters.clone();
+    let histograms = recorder.histograms.clone();
+    metrics::set_global_recorder(recorder).unwrap();
+    let client_impl = helloworld::GreeterClient::new(nrpc::MetricsClientHandler::new(
+        nrpc::LoopbackClientHandler::from_service(nrpc::MetricsService::new(helloworld::GreeterServer::new(GreeterService))),
+    ));
+
+    client_impl.say_hello(req.clone()).await.unwrap();
+    let _ = client_impl.say_hello(helloworld::HelloRequest::default()).await;
+    let resp: Vec<_> = client_impl.say_hello_one_to_many(req).await.unwrap().map(|item_result| item_result.unwrap()).collect().await;
+    assert_eq!(resp.len(), 3);
+
+    let counter = |key: &str| {
+        counters
+            .lock()
+            .unwrap()
+            .get(key)
+            .map(|value| value.load(Ordering::SeqCst))
+            .unwrap_or(0)
+    };
+    let histogram_count = |key: &str| histograms.lock().unwrap().get(key).map(|samples| samples.0.lock().unwrap().len()).unwrap_or(0);
+    for side in ["client", "server"] {
+        let unary = "method=helloworld.Greeter/say_hello";
+        let streaming = "method=helloworld.Greeter/say_hello_one_to_many";
+        assert_eq!(counter(&format!("nrpc_{}_started_total{{{}}}", side, unary)), 2);
+        assert_eq!(counter(&format!("nrpc_{}_handled_total{{{},code=Ok}}", side, unary)), 1);
+        assert_eq!(counter(&format!("nrpc_{}_handled_total{{{},code=InvalidArgument}}", side, unary)), 1);
+        assert_eq!(histogram_count(&format!("nrpc_{}_handling_seconds{{{}}}", side, unary)), 2);
+        assert_eq!(counter(&format!("nrpc_{}_handled_total{{{},code=Ok}}", side, streaming)), 1);
+        assert_eq!(histogram_count(&format!("nrpc_{}_handling_seconds{{{}}}", side, streaming)), 1);
+    }
+    assert_eq!(counter("nrpc_client_msg_sent_total{method=helloworld.Greeter/say_hello_one_to_many}"), 1);
+    assert_eq!(counter("nrpc_server_msg_received_total{method=helloworld.Greeter/say_hello_one_to_many}"), 1);
+    assert_eq!(counter("nrpc_server_msg_sent_total{method=helloworld.Greeter/say_hello_one_to_many}"), 3);
+    assert_eq!(counter("nrpc_client_msg_received_total{method=helloworld.Greeter/say_hello_one_to_many}"), 3);
+    assert_eq!(histogram_count("nrpc_client_msg_received_bytes{method=helloworld.Greeter/say_hello_one_to_many}"), 3);
+}
+
+async fn retries(req: helloworld::HelloRequest) {
+    let policy = nrpc::RetryPolicy::new()
+        .with_max_attempts(3)
+        .with_backoff(std::time::Duration::from_millis(1), std::time::Duration::from_millis(10));
+    let flaky = |failures: usize| {
+        let handler = FlakyHandler::new(
+            nrpc::LoopbackClientHandler::from_service(helloworld::GreeterServer::new(GreeterService)),
+            failures,
+        );
+        let attempts = handler.attempts.clone();
+        let client_impl = helloworld::GreeterClient::new(
+            nrpc::RetryClientHandler::new(handler, nrpc::TokioTimer).with_policy(policy.clone()),
+        );
+        (attempts, client_impl)
+    };
+
+    // transient failures are retried, telling the server about previous attempts
+    let (attempts, client_impl) = flaky(2);
+    let trailer_ctx = nrpc::CallContext::new();
+    let resp = client_impl.say_hello_with_context(&trailer_ctx, req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Hello World");
+    assert_eq!(*attempts.lock().unwrap(), vec![None, Some("1".to_owned()), Some("2".to_owned())]);
+    assert!(trailer_ctx.trailer().await.is_ok());
+
+    // server streaming calls are retried too
+    let (attempts, client_impl) = flaky(1);
+    let resp: Vec<_> = client_impl.say_hello_one_to_many(req.clone()).await.unwrap().map(|item_result| item_result.unwrap()).collect().await;
+    assert_eq!(resp.len(), 3);
+    assert_eq!(attempts.lock().unwrap().len(), 2);
+
+    // until the attempts run out
+    let (attempts, client_impl) = flaky(5);
+    let trailer_ctx = nrpc::CallContext::new();
+    let result = client_impl.say_hello_with_context(&trailer_ctx, req.clone()).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::Unavailable));
+    assert_eq!(attempts.lock().unwrap().len(), 3);
+    assert_eq!(trailer_ctx.trailer().await.status.code(), nrpc::Code::Unavailable);
+
+    // other failures are not retried
+    let (attempts, client_impl) = flaky(0);
+    let result = client_impl.say_hello(helloworld::HelloRequest::default()).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::InvalidArgument));
+    assert_eq!(attempts.lock().unwrap().len(), 1);
+
+    // streamed requests cannot be replayed
+    let (attempts, client_impl) = flaky(1);
+    let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+        Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+    let result = client_impl.say_hello_many_to_one(Box::new(stream_in)).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::Unavailable));
+    assert_eq!(attempts.lock().unwrap().len(), 1);
+
+    // even when they happen to hold a single request
+    let (attempts, client_impl) = flaky(1);
+    let stream_in = nrpc::OnceStream::once(Ok(req.clone()));
+    let result = client_impl.say_hello_many_to_one(Box::new(stream_in)).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::Unavailable));
+    assert_eq!(attempts.lock().unwrap().len(), 1);
+
+    // nor is a call retried when the backoff would pass its deadline
+    let handler = FlakyHandler::new(
+        nrpc::LoopbackClientHandler::from_service(helloworld::GreeterServer::new(GreeterService)),
+        1,
+    );
+    let attempts = handler.attempts.clone();
+    let client_impl = helloworld::GreeterClient::new(nrpc::RetryClientHandler::new(handler, nrpc::TokioTimer).with_policy(
+        policy.with_backoff(std::time::Duration::from_secs(1), std::time::Duration::from_secs(1)).with_jitter(0.0),
+    ));
+    let deadline_ctx = nrpc::CallContext::new().with_timeout(std::time::Duration::from_millis(100));
+    let result = client_impl.say_hello_with_context(&deadline_ctx, req).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::Unavailable));
+    assert_eq!(attempts.lock().unwrap().len(), 1);
+}
+
+async fn compression(req: helloworld::HelloRequest) {
+    // repetitive messages get smaller
+    let message = "Hello World ".repeat(100);
+    for compression in nrpc::Compression::SUPPORTED {
+        let compressed = compression.compress(message.as_bytes()).unwrap();
+        assert!(compressed.len() < message.len() / 4, "{} did not compress", compression);
+        assert_eq!(compression.decompress(&compressed).unwrap(), message.as_bytes());
+    }
+    assert_eq!(nrpc::Compression::from_name("gzip"), Some(nrpc::Compression::Gzip));
+    assert_eq!(nrpc::Compression::from_name("br"), None);
+
+    // calls with every algorithm, in both directions
+    for compression in nrpc::Compression::SUPPORTED.iter().chain([&nrpc::Compression::None]) {
+        let client_impl = helloworld::GreeterClient::new(nrpc::CompressionClientHandler::new(
+            nrpc::LoopbackClientHandler::from_service(nrpc::CompressionService::new(helloworld::GreeterServer::new(GreeterService))),
+            *compression,
+        ));
+        let resp = client_impl.say_hello(req.clone()).await.unwrap();
+        assert_eq!(resp.message, "Hello World");
+        let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+            Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+        let resp: Vec<_> = client_impl.say_hello_many_to_many(Box::new(stream_in)).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+        assert_eq!(resp, vec!["Hello World0", "Hello World1", "Hello World2"]);
+    }
+
+    // servers reject encodings they do not accept, saying which they do
+    let client_impl = helloworld::GreeterClient::new(nrpc::CompressionClientHandler::new(
+        nrpc::LoopbackClientHandler::from_service(
+            nrpc::CompressionService::new(helloworld::GreeterServer::new(GreeterService)).with_accepted([nrpc::Compression::Gzip]),
+        ),
+        nrpc::Compression::Zstd,
+    ));
+    let trailer_ctx = nrpc::CallContext::new();
+    let result = client_impl.say_hello_with_context(&trailer_ctx, req).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::Unimplemented));
+    assert_eq!(trailer_ctx.trailer().await.metadata.get(nrpc::ACCEPT_ENCODING_KEY), Some("gzip"));
+}
+
+async fn custom_codec(req: helloworld::HelloRequest) {
+    let client_impl = helloworld::GreeterClient::with_codec(
+        nrpc::LoopbackClientHandler::from_service(helloworld::GreeterServer::with_codec(GreeterService, XorCodec(0x5a))),
+        XorCodec(0x5a),
+    );
+    let resp = client_impl.say_hello(req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Hello World");
+    let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+        Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+    let resp: Vec<_> = client_impl.say_hello_many_to_many(Box::new(stream_in)).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+    assert_eq!(resp, vec!["Hello World0", "Hello World1", "Hello World2"]);
+
+    // both ends have to agree on the codec
+    let client_impl = helloworld::GreeterClient::with_codec(
+        nrpc::LoopbackClientHandler::from_service(helloworld::GreeterServer::new(GreeterService)),
+        XorCodec(0x5a),
+    );
+    let result = client_impl.say_hello(req).await;
+    assert!(matches!(result, Err(ServiceError::Decode(_))));
+}
+
+async fn json_codec(req: helloworld::HelloRequest) {
+    let json_ctx = || {
+        let mut ctx = nrpc::CallContext::new();
+        ctx.metadata_mut().insert(nrpc::CONTENT_TYPE_KEY, "application/json; charset=utf-8").unwrap();
+        ctx
+    };
+
+    // the same server speaks JSON to a JSON client, and protobuf binary to everyone else
+    let mut server = helloworld::GreeterServer::with_codec(GreeterService, nrpc::ContentTypeCodec);
+    let stream_in = nrpc::OnceStream::once(Ok(bytes::Bytes::from_static(br#"{"name":"World"}"#)));
+    let mut output_stream = server.call("say_hello", &json_ctx(), Box::new(stream_in)).await.unwrap();
+    let output_buf = output_stream.next().await.unwrap().unwrap();
+    assert_eq!(&output_buf[..], br#"{"message":"Hello World"}"#);
+    drop(output_stream);
+    let stream_in = nrpc::OnceStream::once(Ok(req.encode_to_vec().into()));
+    let mut output_stream = server.call("say_hello", &nrpc::CallContext::new(), Box::new(stream_in)).await.unwrap();
+    let output_buf = output_stream.next().await.unwrap().unwrap();
+    assert_eq!(helloworld::HelloReply::decode(output_buf).unwrap().message, "Hello World");
+    drop(output_stream);
+    let stream_in = nrpc::OnceStream::once(Ok(bytes::Bytes::from_static(b"{\"name\":")));
+    let result = server.call("say_hello", &json_ctx(), Box::new(stream_in)).await;
+    assert_eq!(result.err().unwrap().code(), nrpc::Code::InvalidArgument);
+
+    // generated clients pick the encoding per call
+    let client_impl = helloworld::GreeterClient::with_codec(
+        nrpc::LoopbackClientHandler::from_service(helloworld::GreeterServer::with_codec(GreeterService, nrpc::ContentTypeCodec)),
+        nrpc::ContentTypeCodec,
+    );
+    let resp = client_impl.say_hello_with_context(&json_ctx(), req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Hello World");
+    let resp = client_impl.say_hello(req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Hello World");
+    let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+        Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+    let resp: Vec<_> = client_impl.say_hello_many_to_many_with_context(&json_ctx(), Box::new(stream_in)).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+    assert_eq!(resp, vec!["Hello World0", "Hello World1", "Hello World2"]);
+
+    // a JSON only client of a binary only server
+    let client_impl = helloworld::GreeterClient::with_codec(
+        nrpc::LoopbackClientHandler::from_service(helloworld::GreeterServer::new(GreeterService)),
+        nrpc::JsonCodec,
+    );
+    let result = client_impl.say_hello(req).await;
+    assert!(matches!(result, Err(ServiceError::Decode(_))));
+}
+
+/// Protobuf binary with every byte scrambled
+struct XorCodec(u8);
+
+impl<M: prost::Message + Default> nrpc::Codec<M> for XorCodec {
+    fn encode(&self, ctx: &nrpc::CallContext, message: &M) -> Result<bytes::Bytes, ServiceError> {
+        let buf = nrpc::Codec::encode(&nrpc::ProstCodec, ctx, message)?;
+        Ok(buf.iter().map(|b| b ^ self.0).collect())
+    }
+
+    fn decode(&self, ctx: &nrpc::CallContext, buf: bytes::Bytes) -> Result<M, ServiceError> {
+        nrpc::Codec::decode(&nrpc::ProstCodec, ctx, buf.iter().map(|b| b ^ self.0).collect())
+    }
+}
+
+/// Client handler which fails the first calls made through it as unavailable
+struct FlakyHandler<H> {
+    inner: H,
+    failures: AtomicUsize,
+    /// Previous attempts header of each call
+    attempts: Arc<Mutex<Vec<Option<String>>>>,
+}
+
+impl<H> FlakyHandler<H> {
+    fn new(inner: H, failures: usize) -> Self {
+        Self {
+            inner,
+            failures: AtomicUsize::new(failures),
+            attempts: Default::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<'b, H: nrpc::ClientHandler<'b> + Sync> nrpc::ClientHandler<'b> for FlakyHandler<H> {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &nrpc::CallContext,
+        input: nrpc::ServiceClientStream<'a, bytes::Bytes>,
+    ) -> Result<nrpc::ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        let previous_attempts = ctx.metadata().get(nrpc::PREVIOUS_ATTEMPTS_KEY).map(str::to_owned);
+        self.attempts.lock().unwrap().push(previous_attempts);
+        let failing = self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |failures| failures.checked_sub(1))
+            .is_ok();
+        if failing {
+            Err(nrpc::Status::unavailable("Connection lost").into())
+        } else {
+            self.inner.call(package, service, method, ctx, input).await
+        }
+    }
+}
+
+type MetricMap<T> = Arc<Mutex<std::collections::HashMap<String, Arc<T>>>>;
+
+/// Recorder which keeps counter values and histogram samples, keyed like `name{label=value,...}`
+#[derive(Default)]
+struct RecordMetrics {
+    counters: MetricMap<std::sync::atomic::AtomicU64>,
+    histograms: MetricMap<Samples>,
+}
+
+#[derive(Default)]
+struct Samples(Mutex<Vec<f64>>);
+
+impl metrics::HistogramFn for Samples {
+    fn record(&self, value: f64) {
+        self.0.lock().unwrap().push(value);
+    }
+}
+
+fn render_key(key: &metrics::Key) -> String {
+    let labels: Vec<_> = key.labels().map(|label| format!("{}={}", label.key(), label.value())).collect();
+    format!("{}{{{}}}", key.name(), labels.join(","))
+}
+
+impl metrics::Recorder for RecordMetrics {
+    fn describe_counter(&self, _key: metrics::KeyName, _unit: Option<metrics::Unit>, _description: metrics::SharedString) {}
+
+    fn describe_gauge(&self, _key: metrics::KeyName, _unit: Option<metrics::Unit>, _description: metrics::SharedString) {}
+
+    fn describe_histogram(&self, _key: metrics::KeyName, _unit: Option<metrics::Unit>, _description: metrics::SharedString) {}
+
+    fn register_counter(&self, key: &metrics::Key, _metadata: &metrics::Metadata<'_>) -> metrics::Counter {
+        let counter = self.counters.lock().unwrap().entry(render_key(key)).or_default().clone();
+        metrics::Counter::from_arc(counter)
+    }
+
+    fn register_gauge(&self, _key: &metrics::Key, _metadata: &metrics::Metadata<'_>) -> metrics::Gauge {
+        metrics::Gauge::noop()
+    }
+
+    fn register_histogram(&self, key: &metrics::Key, _metadata: &metrics::Metadata<'_>) -> metrics::Histogram {
+        let samples = self.histograms.lock().unwrap().entry(render_key(key)).or_default().clone();
+        metrics::Histogram::from_arc(samples)
+    }
+}
+
+struct AddAuth(&'static str);
+
+#[async_trait::async_trait]
+impl<'b> nrpc::ClientInterceptor<'b> for AddAuth {
+    async fn intercept<'a: 'b>(
+        &self,
+        mut request: nrpc::ClientRequest<'a>,
+        next: nrpc::ClientNext<'_, 'b>,
+    ) -> Result<nrpc::ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        request.ctx.metadata_mut().insert("authorization", self.0).unwrap();
+        next.run(request).await
+    }
+}
+
+struct DenyMethod(&'static str);
+
+#[async_trait::async_trait]
+impl<'b> nrpc::ClientInterceptor<'b> for DenyMethod {
+    async fn intercept<'a: 'b>(
+        &self,
+        request: nrpc::ClientRequest<'a>,
+        next: nrpc::ClientNext<'_, 'b>,
+    ) -> Result<nrpc::ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        if request.method == self.0 {
+            return Err(nrpc::Status::permission_denied("Method not allowed").into());
+        }
+        next.run(request).await
+    }
+}
+
+struct RecordCalls(Arc<Mutex<Vec<String>>>);
+
+#[async_trait::async_trait]
+impl<'b> nrpc::ClientInterceptor<'b> for RecordCalls {
+    async fn intercept<'a: 'b>(
+        &self,
+        request: nrpc::ClientRequest<'a>,
+        next: nrpc::ClientNext<'_, 'b>,
+    ) -> Result<nrpc::ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        let method = request.method.clone();
+        self.0.lock().unwrap().push(format!("{} start", method));
+        let result = next.run(request).await;
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        self.0.lock().unwrap().push(format!("{} {}", method, outcome));
+        result
+    }
+}
+
+struct RequireAuth;
+
+#[async_trait::async_trait]
+impl<'b> nrpc::ServerInterceptor<'b> for RequireAuth {
+    async fn intercept<'a: 'b>(
+        &self,
+        request: nrpc::ServerRequest<'a>,
+        next: nrpc::ServerNext<'_, 'b>,
+    ) -> Result<nrpc::ServiceServerStream<'a, bytes::Bytes>, ServiceError> {
+        if request.ctx.metadata().get("authorization") != Some("Bearer secret") {
+            return Err(nrpc::Status::unauthenticated("Missing credentials").into());
+        }
+        next.run(request).await
+    }
+}
+
+async fn websocket_transport(req: helloworld::HelloRequest) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let server = nrpc_ws::WebSocketServer::new(
+        nrpc::ServiceRegistry::new().with_cloned(helloworld::GreeterServer::new(GreeterService)),
+    );
+    tokio::spawn(async move { server.serve(listener).await });
+    let client_impl = helloworld::GreeterClient::new(nrpc_ws::WebSocketClientHandler::new(url.clone()));
+
+    // websocket one to one
+    let resp = client_impl.say_hello(req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Hello World");
+
+    // websocket one to one with metadata
+    let mut greeting_ctx = nrpc::CallContext::new();
+    greeting_ctx.metadata_mut().insert("greeting", "Howdy").unwrap();
+    greeting_ctx.metadata_mut().insert_bin("trace-bin", vec![0u8, 1, 2, 255]).unwrap();
+    let resp = client_impl.say_hello_with_context(&greeting_ctx, req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Howdy World");
+
+    // websocket many to one
+    let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+        Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+    let resp = client_impl.say_hello_many_to_one(Box::new(stream_in)).await.unwrap();
+    assert_eq!(resp.message, "Hello World0, World1, World2");
+
+    // websocket one to many
+    let resp: Vec<_> = client_impl.say_hello_one_to_many(req.clone()).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+    assert_eq!(resp, vec!["Hello World"; 3]);
+
+    // websocket one to many with trailer
+    let trailer_ctx = nrpc::CallContext::new();
+    let resp: Vec<_> = client_impl.say_hello_one_to_many_with_context(&trailer_ctx, req.clone()).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+    assert_eq!(resp, vec!["Hello World"; 3]);
+    let trailer = trailer_ctx.trailer().await;
+    assert!(trailer.is_ok());
+    assert_eq!(trailer.metadata.get("greeting-count"), Some("3"));
+
+    // websocket unary trailer
+    let trailer_ctx = nrpc::CallContext::new();
+    client_impl.say_hello_with_context(&trailer_ctx, req.clone()).await.unwrap();
+    assert!(trailer_ctx.trailer().await.is_ok());
+
+    // websocket many to many
+    let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+        Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+    let resp: Vec<_> = client_impl.say_hello_many_to_many(Box::new(stream_in)).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+    assert_eq!(resp, vec!["Hello World0", "Hello World1", "Hello World2"]);
+
+    // websocket unknown method
+    let trailer_ctx = nrpc::CallContext::new();
+    let result = nrpc::ClientHandler::call(
+        &nrpc_ws::WebSocketClientHandler::new(url.clone()),
+        "helloworld", "Greeter", "say_goodbye", &trailer_ctx, Box::new(nrpc::EmptyStream::default()),
+    ).await.unwrap().next().await;
+    assert!(matches!(result, Some(Err(ServiceError::Status(ref status))) if status.code() == nrpc::Code::Unimplemented));
+    assert!(!trailer_ctx.trailer().await.is_ok());
+
+    // websocket status error
+    let trailer_ctx = nrpc::CallContext::new();
+    let result = client_impl.say_hello_with_context(&trailer_ctx, helloworld::HelloRequest::default()).await;
+    match result {
+        Err(ServiceError::Status(status)) => {
+            assert_eq!(status.code(), nrpc::Code::InvalidArgument);
+            assert_eq!(status.message(), "name is required");
+            let bad_request: nrpc::error_details::BadRequest = status.get_detail().unwrap();
+            assert_eq!(bad_request.field_violations[0].field, "name");
+            let retry_info: nrpc::error_details::RetryInfo = status.get_detail().unwrap();
+            assert_eq!(retry_info.retry_delay(), Some(std::time::Duration::from_millis(1500)));
+            assert!(status.get_detail::<nrpc::error_details::QuotaFailure>().is_none());
+        }
+        other => panic!("Expected status error, got {:?}", other),
+    }
+    assert_eq!(trailer_ctx.trailer().await.status.code(), nrpc::Code::InvalidArgument);
+
+    // websocket one to many cancelled by dropping the response stream
+    let endless_req = helloworld::HelloRequest { name: "Endless".into() };
+    let mut resp = client_impl.say_hello_one_to_many(endless_req.clone()).await.unwrap();
+    resp.next().await.unwrap().unwrap();
+    drop(resp);
+    wait_for_cancelled_calls(2).await;
+
+    // websocket one to many cancelled explicitly
+    let cancel_ctx = nrpc::CallContext::new();
+    let mut resp = client_impl.say_hello_one_to_many_with_context(&cancel_ctx, endless_req).await.unwrap();
+    resp.next().await.unwrap().unwrap();
+    cancel_ctx.cancel();
+    let mut results: Vec<_> = resp.collect().await;
+    assert!(matches!(results.pop(), Some(Err(ServiceError::Status(ref status))) if status.code() == nrpc::Code::Cancelled));
+    wait_for_cancelled_calls(3).await;
+
+    // websocket calls multiplexed over one connection
+    let mux_handler = nrpc_ws::WebSocketClientHandler::new(url.clone()).connect_multiplexed().await.unwrap();
+    let mux_client = helloworld::GreeterClient::new(mux_handler.clone());
+    let (requests, stream_in) = futures::channel::mpsc::unbounded();
+    let mut many_to_many = mux_client.say_hello_many_to_many(Box::new(stream_in.map(Ok))).await.unwrap();
+    let trailer_ctx = nrpc::CallContext::new();
+    let mut one_to_many = mux_client.say_hello_one_to_many_with_context(&trailer_ctx, req.clone()).await.unwrap();
+    requests.unbounded_send(helloworld::HelloRequest { name: "World0".into() }).unwrap();
+    assert_eq!(one_to_many.next().await.unwrap().unwrap().message, "Hello World");
+    assert_eq!(many_to_many.next().await.unwrap().unwrap().message, "Hello World0");
+    let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+        Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+    let resp = mux_client.say_hello_many_to_one(Box::new(stream_in)).await.unwrap();
+    assert_eq!(resp.message, "Hello World0, World1, World2");
+    // closing the input of one call leaves the others running
+    requests.unbounded_send(helloworld::HelloRequest { name: "World1".into() }).unwrap();
+    drop(requests);
+    let resp: Vec<_> = many_to_many.map(|item_result| item_result.unwrap().message).collect().await;
+    assert_eq!(resp, vec!["Hello World1"]);
+    let resp: Vec<_> = one_to_many.map(|item_result| item_result.unwrap().message).collect().await;
+    assert_eq!(resp, vec!["Hello World"; 2]);
+    assert_eq!(trailer_ctx.trailer().await.metadata.get("greeting-count"), Some("3"));
+    let resps = futures::future::join_all((0..8).map(|i|
+        mux_client.say_hello(helloworld::HelloRequest { name: format!("Concurrent{}", i) }))).await;
+    for (i, resp) in resps.into_iter().enumerate() {
+        assert_eq!(resp.unwrap().message, format!("Hello Concurrent{}", i));
+    }
+    let result = nrpc::ClientHandler::call(
+        &mux_handler, "helloworld", "Greeter", "say_goodbye", &nrpc::CallContext::new(), Box::new(nrpc::EmptyStream::default()),
+    ).await.unwrap().next().await;
+    assert!(matches!(result, Some(Err(ServiceError::Status(ref status))) if status.code() == nrpc::Code::Unimplemented));
+    assert!(!mux_handler.is_closed());
+
+    // websocket deadlines
+    assert_eq!(nrpc::encode_timeout(std::time::Duration::from_millis(1500)), "1500000u");
+    assert_eq!(nrpc::decode_timeout("1500m"), Some(std::time::Duration::from_millis(1500)));
+    assert_eq!(nrpc::decode_timeout("15x"), None);
+    let client_impl = helloworld::GreeterClient::new(
+        nrpc::DeadlineClientHandler::new(nrpc_ws::WebSocketClientHandler::new(url), nrpc::TokioTimer)
+            .with_default_timeout(std::time::Duration::from_millis(100)),
+    );
+    let trailer_ctx = nrpc::CallContext::new();
+    let resp = client_impl.say_hello_with_context(&trailer_ctx, req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Hello World");
+    assert_eq!(trailer_ctx.trailer().await.metadata.get("has-deadline"), Some("true"));
+
+    let sleepy_req = helloworld::HelloRequest { name: "Sleepy".into() };
+    let trailer_ctx = nrpc::CallContext::new();
+    let result = client_impl.say_hello_with_context(&trailer_ctx, sleepy_req.clone()).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::DeadlineExceeded));
+    assert_eq!(trailer_ctx.trailer().await.status.code(), nrpc::Code::DeadlineExceeded);
+
+    // per-call deadline overrides the client default
+    let ctx = nrpc::CallContext::new().with_timeout(std::time::Duration::from_secs(5));
+    let resp = client_impl.say_hello_with_context(&ctx, sleepy_req).await.unwrap();
+    assert_eq!(resp.message, "Hello Sleepy");
+}
+
+static CANCELLED_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+async fn grpc_web_transport(req: helloworld::HelloRequest) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = nrpc_http::grpc_web::GrpcWebServer::new(
+        nrpc::ServiceRegistry::new().with_cloned(helloworld::GreeterServer::new(GreeterService)),
+    )
+    .with_allowed_origins(["http://example.com"])
+    .with_allowed_headers(["greeting"]);
+    tokio::spawn(async move { server.serve(listener).await });
+
+    for handler in [
+        nrpc_http::grpc_web::GrpcWebClientHandler::new(url.clone()),
+        nrpc_http::grpc_web::GrpcWebClientHandler::new(url.clone()).with_text_encoding(),
+    ] {
+        let client_impl = helloworld::GreeterClient::new(handler.clone());
+
+        // grpc-web one to one with metadata
+        let resp = client_impl.say_hello(req.clone()).await.unwrap();
+        assert_eq!(resp.message, "Hello World");
+        let mut greeting_ctx = nrpc::CallContext::new();
+        greeting_ctx.metadata_mut().insert("greeting", "Howdy").unwrap();
+        greeting_ctx.metadata_mut().insert_bin("trace-bin", vec![0u8, 1, 2, 255]).unwrap();
+        let resp = client_impl.say_hello_with_context(&greeting_ctx, req.clone()).await.unwrap();
+        assert_eq!(resp.message, "Howdy World");
+
+        // grpc-web many to one
+        let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+            Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+        let resp = client_impl.say_hello_many_to_one(Box::new(stream_in)).await.unwrap();
+        assert_eq!(resp.message, "Hello World0, World1, World2");
+
+        // grpc-web one to many with trailer
+        let trailer_ctx = nrpc::CallContext::new();
+        let resp: Vec<_> = client_impl.say_hello_one_to_many_with_context(&trailer_ctx, req.clone()).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+        assert_eq!(resp, vec!["Hello World"; 3]);
+        let trailer = trailer_ctx.trailer().await;
+        assert!(trailer.is_ok());
+        assert_eq!(trailer.metadata.get("greeting-count"), Some("3"));
+
+        // grpc-web many to many
+        let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+            Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+        let resp: Vec<_> = client_impl.say_hello_many_to_many(Box::new(stream_in)).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+        assert_eq!(resp, vec!["Hello World0", "Hello World1", "Hello World2"]);
+
+        // grpc-web status error, with its details
+        let trailer_ctx = nrpc::CallContext::new();
+        let result = client_impl.say_hello_with_context(&trailer_ctx, helloworld::HelloRequest::default()).await;
+        match result {
+            Err(ServiceError::Status(status)) => {
+                assert_eq!(status.code(), nrpc::Code::InvalidArgument);
+                assert_eq!(status.message(), "name is required");
+                let bad_request: nrpc::error_details::BadRequest = status.get_detail().unwrap();
+                assert_eq!(bad_request.field_violations[0].field, "name");
+            }
+            other => panic!("Expected status error, got {:?}", other),
+        }
+        assert_eq!(trailer_ctx.trailer().await.status.code(), nrpc::Code::InvalidArgument);
+
+        // grpc-web unknown method
+        let result = nrpc::ClientHandler::call(
+            &handler,
+            "helloworld", "Greeter", "say_goodbye", &nrpc::CallContext::new(), Box::new(nrpc::EmptyStream::default()),
+        ).await.unwrap().next().await;
+        assert!(matches!(result, Some(Err(ServiceError::Status(ref status))) if status.code() == nrpc::Code::Unimplemented));
+    }
+
+    // grpc-web one to many cancelled by dropping the response stream
+    let client_impl = helloworld::GreeterClient::new(nrpc_http::grpc_web::GrpcWebClientHandler::new(url.clone()));
+    let mut resp = client_impl.say_hello_one_to_many(helloworld::HelloRequest { name: "Endless".into() }).await.unwrap();
+    resp.next().await.unwrap().unwrap();
+    drop(resp);
+    wait_for_cancelled_calls(4).await;
+
+    // what a stock grpc-web client sends and receives
+    let http = hyper::Client::new();
+    let preflight = hyper::Request::options(format!("{}/helloworld.Greeter/say_hello", url))
+        .header("origin", "http://example.com")
+        .header("access-control-request-method", "POST")
+        .header("access-control-request-headers", "content-type,x-grpc-web,x-user-agent")
+        .body(hyper::Body::empty())
+        .unwrap();
+    let response = http.request(preflight).await.unwrap();
+    assert!(response.status().is_success());
+    assert_eq!(response.headers()["access-control-allow-origin"], "http://example.com");
+    assert_eq!(response.headers()["access-control-allow-headers"], "content-type, x-grpc-web, x-user-agent, grpc-timeout, greeting");
+    assert_eq!(response.headers()["vary"], "Origin");
+
+    // other origins are not allowed
+    let preflight = hyper::Request::options(format!("{}/helloworld.Greeter/say_hello", url))
+        .header("origin", "http://attacker.example")
+        .header("access-control-request-method", "POST")
+        .body(hyper::Body::empty())
+        .unwrap();
+    let response = http.request(preflight).await.unwrap();
+    assert!(!response.headers().contains_key("access-control-allow-origin"));
+    assert!(!response.headers().contains_key("access-control-allow-headers"));
+    assert_eq!(response.headers()["vary"], "Origin");
+
+    let message = req.encode_to_vec();
+    let mut body = vec![0u8];
+    body.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    body.extend_from_slice(&message);
+    let request = hyper::Request::post(format!("{}/helloworld.Greeter/say_hello", url))
+        .header("content-type", "application/grpc-web+proto")
+        .header("x-grpc-web", "1")
+        .body(hyper::Body::from(body))
+        .unwrap();
+    let response = http.request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/grpc-web+proto");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(body[0], 0x00);
+    let length = u32::from_be_bytes(body[1..5].try_into().unwrap()) as usize;
+    let reply = helloworld::HelloReply::decode(&body[5..5 + length]).unwrap();
+    assert_eq!(reply.message, "Hello World");
+    let trailer = &body[5 + length..];
+    assert_eq!(trailer[0], 0x80);
+    assert_eq!(&trailer[5..], b"grpc-status: 0\r\n");
+}
+
+async fn connect_transport(req: helloworld::HelloRequest) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = nrpc_http::connect::ConnectServer::new(
+        nrpc::ServiceRegistry::new().with(helloworld::GreeterServer::with_codec(GreeterService, nrpc::ContentTypeCodec)),
+    );
+    tokio::spawn(async move { server.serve(listener).await });
+    let handler = nrpc_http::connect::ConnectClientHandler::new(url.clone());
+
+    for content_type in [None, Some("application/json")] {
+        let new_ctx = || {
+            let mut ctx = nrpc::CallContext::new();
+            if let Some(content_type) = content_type {
+                ctx.metadata_mut().insert(nrpc::CONTENT_TYPE_KEY, content_type).unwrap();
+            }
+            ctx
+        };
+        let client_impl = helloworld::GreeterClient::with_codec(handler.clone(), nrpc::ContentTypeCodec);
+
+        // connect one to one with metadata and a deadline
+        let resp = client_impl.say_hello_with_context(&new_ctx(), req.clone()).await.unwrap();
+        assert_eq!(resp.message, "Hello World");
+        let mut greeting_ctx = new_ctx().with_timeout(std::time::Duration::from_secs(5));
+        greeting_ctx.metadata_mut().insert("greeting", "Howdy").unwrap();
+        greeting_ctx.metadata_mut().insert_bin("trace-bin", vec![0u8, 1, 2, 255]).unwrap();
+        let resp = client_impl.say_hello_with_context(&greeting_ctx, req.clone()).await.unwrap();
+        assert_eq!(resp.message, "Howdy World");
+        assert_eq!(greeting_ctx.trailer().await.metadata.get("has-deadline"), Some("true"));
+
+        // connect many to one
+        let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+            Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+        let resp = client_impl.say_hello_many_to_one_with_context(&new_ctx(), Box::new(stream_in)).await.unwrap();
+        assert_eq!(resp.message, "Hello World0, World1, World2");
+
+        // connect one to many with trailer
+        let trailer_ctx = new_ctx();
+        let resp: Vec<_> = client_impl.say_hello_one_to_many_with_context(&trailer_ctx, req.clone()).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+        assert_eq!(resp, vec!["Hello World"; 3]);
+        let trailer = trailer_ctx.trailer().await;
+        assert!(trailer.is_ok());
+        assert_eq!(trailer.metadata.get("greeting-count"), Some("3"));
+
+        // connect many to many
+        let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+            Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+        let resp: Vec<_> = client_impl.say_hello_many_to_many_with_context(&new_ctx(), Box::new(stream_in)).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+        assert_eq!(resp, vec!["Hello World0", "Hello World1", "Hello World2"]);
+
+        // connect status error, with its details
+        let trailer_ctx = new_ctx();
+        let result = client_impl.say_hello_with_context(&trailer_ctx, helloworld::HelloRequest::default()).await;
+        match result {
+            Err(ServiceError::Status(status)) => {
+                assert_eq!(status.code(), nrpc::Code::InvalidArgument);
+                assert_eq!(status.message(), "name is required");
+                let bad_request: nrpc::error_details::BadRequest = status.get_detail().unwrap();
+                assert_eq!(bad_request.field_violations[0].field, "name");
+            }
+            other => panic!("Expected status error, got {:?}", other),
+        }
+        assert_eq!(trailer_ctx.trailer().await.status.code(), nrpc::Code::InvalidArgument);
+    }
+
+    // connect unknown method, which streams since its shape is not known
+    let result = nrpc::ClientHandler::call(
+        &handler,
+        "helloworld", "Greeter", "say_goodbye", &nrpc::CallContext::new(), Box::new(nrpc::EmptyStream::default()),
+    ).await.unwrap().next().await;
+    assert!(matches!(result, Some(Err(ServiceError::Status(ref status))) if status.code() == nrpc::Code::Unimplemented));
+
+    // what a plain HTTP client sends and receives
+    let http = hyper::Client::new();
+    let request = hyper::Request::post(format!("{}/helloworld.Greeter/say_hello", url))
+        .header("content-type", "application/json")
+        .header("connect-protocol-version", "1")
+        .body(hyper::Body::from(r#"{"name":"World"}"#))
+        .unwrap();
+    let response = http.request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..], br#"{"message":"Hello World"}"#);
+
+    let request = hyper::Request::post(format!("{}/helloworld.Greeter/say_hello", url))
+        .header("content-type", "application/proto")
+        .header("connect-timeout-ms", "5000")
+        .header("greeting", "Howdy")
+        .body(hyper::Body::from(req.encode_to_vec()))
+        .unwrap();
+    let response = http.request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::OK);
+    assert_eq!(response.headers()["trailer-has-deadline"], "true");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(helloworld::HelloReply::decode(body).unwrap().message, "Howdy World");
+
+    let request = hyper::Request::post(format!("{}/helloworld.Greeter/say_hello", url))
+        .header("content-type", "application/json")
+        .body(hyper::Body::from("{}"))
+        .unwrap();
+    let response = http.request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::BAD_REQUEST);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["code"], "invalid_argument");
+    assert_eq!(error["message"], "name is required");
+    assert_eq!(error["details"][0]["type"], "google.rpc.BadRequest");
+
+    // unary bodies longer than the largest message are refused
+    let chunks = (0..17).map(|_| Ok::<_, std::io::Error>(vec![0u8; 1024 * 1024]));
+    let request = hyper::Request::post(format!("{}/helloworld.Greeter/say_hello", url))
+        .header("content-type", "application/proto")
+        .body(hyper::Body::wrap_stream(futures::stream::iter(chunks)))
+        .unwrap();
+    let response = http.request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::TOO_MANY_REQUESTS);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["code"], "resource_exhausted");
+
+    let message = req.encode_to_vec();
+    let mut body = vec![0u8];
+    body.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    body.extend_from_slice(&message);
+    let request = hyper::Request::post(format!("{}/helloworld.Greeter/say_hello_many_to_many", url))
+        .header("content-type", "application/connect+proto")
+        .body(hyper::Body::from(body))
+        .unwrap();
+    let response = http.request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/connect+proto");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(body[0], 0x00);
+    let length = u32::from_be_bytes(body[1..5].try_into().unwrap()) as usize;
+    let reply = helloworld::HelloReply::decode(&body[5..5 + length]).unwrap();
+    assert_eq!(reply.message, "Hello World");
+    let end = &body[5 + length..];
+    assert_eq!(end[0], 0x02);
+    assert_eq!(&end[5..], b"{}");
+
+    // browsers may not call from other origins unless they are allowed
+    let request = hyper::Request::post(format!("{}/helloworld.Greeter/say_hello", url))
+        .header("content-type", "application/json")
+        .header("origin", "http://example.com")
+        .body(hyper::Body::from(r#"{"name":"World"}"#))
+        .unwrap();
+    let response = http.request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::OK);
+    assert!(!response.headers().contains_key("access-control-allow-origin"));
+}
+
+async fn twirp_transport(req: helloworld::HelloRequest) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = nrpc_http::twirp::TwirpServer::new(
+        nrpc::ServiceRegistry::new().with_cloned(helloworld::GreeterServer::with_codec(GreeterService, nrpc::ContentTypeCodec)),
+    );
+    tokio::spawn(async move { server.serve(listener).await });
+    let handler = nrpc_http::twirp::TwirpClientHandler::new(url.clone());
+
+    for content_type in [None, Some("application/json")] {
+        let new_ctx = || {
+            let mut ctx = nrpc::CallContext::new();
+            if let Some(content_type) = content_type {
+                ctx.metadata_mut().insert(nrpc::CONTENT_TYPE_KEY, content_type).unwrap();
+            }
+            ctx
+        };
+        let client_impl = helloworld::GreeterClient::with_codec(handler.clone(), nrpc::ContentTypeCodec);
+
+        // twirp one to one with metadata
+        let resp = client_impl.say_hello_with_context(&new_ctx(), req.clone()).await.unwrap();
+        assert_eq!(resp.message, "Hello World");
+        let mut greeting_ctx = new_ctx();
+        greeting_ctx.metadata_mut().insert("greeting", "Howdy").unwrap();
+        let resp = client_impl.say_hello_with_context(&greeting_ctx, req.clone()).await.unwrap();
+        assert_eq!(resp.message, "Howdy World");
+        assert!(greeting_ctx.trailer().await.is_ok());
+
+        // twirp status error
+        let trailer_ctx = new_ctx();
+        let result = client_impl.say_hello_with_context(&trailer_ctx, helloworld::HelloRequest::default()).await;
+        match result {
+            Err(ServiceError::Status(status)) => {
+                assert_eq!(status.code(), nrpc::Code::InvalidArgument);
+                assert_eq!(status.message(), "name is required");
+            }
+            other => panic!("Expected status error, got {:?}", other),
+        }
+        assert_eq!(trailer_ctx.trailer().await.status.code(), nrpc::Code::InvalidArgument);
+
+        // twirp cannot stream
+        let result = client_impl.say_hello_one_to_many_with_context(&new_ctx(), req.clone()).await;
+        assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::Unimplemented));
+    }
+
+    // twirp unknown method
+    let result = nrpc::ClientHandler::call(
+        &handler,
+        "helloworld", "Greeter", "say_goodbye", &nrpc::CallContext::new(), Box::new(nrpc::OnceStream::once(Ok(req.encode_to_vec().into()))),
+    ).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::Unimplemented));
+
+    // what a stock twirp client sends and receives
+    let http = hyper::Client::new();
+    let request = hyper::Request::post(format!("{}/twirp/helloworld.Greeter/say_hello", url))
+        .header("content-type", "application/json")
+        .header("origin", "http://example.com")
+        .body(hyper::Body::from(r#"{"name":"World"}"#))
+        .unwrap();
+    let response = http.request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    // browsers may not read the response from other origins unless they are allowed
+    assert!(!response.headers().contains_key("access-control-allow-origin"));
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..], br#"{"message":"Hello World"}"#);
+
+    let request = hyper::Request::post(format!("{}/twirp/helloworld.Greeter/say_hello", url))
+        .header("content-type", "application/protobuf")
+        .body(hyper::Body::from(helloworld::HelloRequest::default().encode_to_vec()))
+        .unwrap();
+    let response = http.request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::BAD_REQUEST);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["code"], "invalid_argument");
+    assert_eq!(error["msg"], "name is required");
+
+    let request = hyper::Request::post(format!("{}/twirp/helloworld.Greeter/say_hello", url))
+        .header("content-type", "application/json")
+        .body(hyper::Body::from("name=World"))
+        .unwrap();
+    let response = http.request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::BAD_REQUEST);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["code"], "malformed");
+
+    // request bodies longer than the largest message are refused
+    let chunks = (0..17).map(|_| Ok::<_, std::io::Error>(vec![0u8; 1024 * 1024]));
+    let request = hyper::Request::post(format!("{}/twirp/helloworld.Greeter/say_hello", url))
+        .header("content-type", "application/protobuf")
+        .body(hyper::Body::wrap_stream(futures::stream::iter(chunks)))
+        .unwrap();
+    let response = http.request(request).await.unwrap();
+    assert_eq!(response.status(), hyper::StatusCode::TOO_MANY_REQUESTS);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["code"], "resource_exhausted");
+
+    for method in ["say_hello_one_to_many", "say_goodbye"] {
+        let request = hyper::Request::post(format!("{}/twirp/helloworld.Greeter/{}", url, method))
+            .header("content-type", "application/protobuf")
+            .body(hyper::Body::from(req.encode_to_vec()))
+            .unwrap();
+        let response = http.request(request).await.unwrap();
+        assert_eq!(response.status(), hyper::StatusCode::NOT_FOUND);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error["code"], "bad_route");
+    }
 }
 
+async fn h2_transport(req: helloworld::HelloRequest) {
+    // nrpc-h2 client to a tonic server
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let tonic_url = format!("http://{}", listener.local_addr().unwrap());
+    let incoming = futures::stream::unfold(listener, |listener| async move {
+        Some((listener.accept().await.map(|(stream, _)| stream), listener))
+    });
+    tokio::spawn(
+        tonic::transport::Server::builder()
+            .add_service(tonic_helloworld::greeter_server::GreeterServer::new(TonicGreeter))
+            .serve_with_incoming(incoming),
+    );
+    let handler = nrpc_h2::H2ClientHandler::new(tonic_url);
+    let client_impl = helloworld::GreeterClient::new(handler.clone());
+
+    // h2 one to one with metadata
+    let resp = client_impl.say_hello(req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Hello World");
+    let mut greeting_ctx = nrpc::CallContext::new().with_timeout(std::time::Duration::from_secs(5));
+    greeting_ctx.metadata_mut().insert("greeting", "Howdy").unwrap();
+    greeting_ctx.metadata_mut().insert_bin("trace-bin", vec![0u8, 1, 2, 255]).unwrap();
+    let resp = client_impl.say_hello_with_context(&greeting_ctx, req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Howdy World");
+
+    // h2 many to one
+    let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+        Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+    let resp = client_impl.say_hello_many_to_one(Box::new(stream_in)).await.unwrap();
+    assert_eq!(resp.message, "Hello World0, World1, World2");
+
+    // h2 one to many
+    let resp: Vec<_> = client_impl.say_hello_one_to_many(req.clone()).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+    assert_eq!(resp, vec!["Hello World"; 3]);
+
+    // h2 many to many
+    let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+        Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+    let resp: Vec<_> = client_impl.say_hello_many_to_many(Box::new(stream_in)).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+    assert_eq!(resp, vec!["Hello World0", "Hello World1", "Hello World2"]);
+
+    // h2 status error from tonic, with its details
+    let trailer_ctx = nrpc::CallContext::new();
+    let result = client_impl.say_hello_with_context(&trailer_ctx, helloworld::HelloRequest::default()).await;
+    match result {
+        Err(ServiceError::Status(status)) => {
+            assert_eq!(status.code(), nrpc::Code::InvalidArgument);
+            assert_eq!(status.message(), "name is required");
+            let bad_request: nrpc::error_details::BadRequest = status.get_detail().unwrap();
+            assert_eq!(bad_request.field_violations[0].field, "name");
+        }
+        other => panic!("Expected status error, got {:?}", other),
+    }
+    assert_eq!(trailer_ctx.trailer().await.status.code(), nrpc::Code::InvalidArgument);
+
+    // h2 unknown method
+    let result = nrpc::ClientHandler::call(
+        &handler,
+        "helloworld", "Greeter", "say_goodbye", &nrpc::CallContext::new(), Box::new(nrpc::EmptyStream::default()),
+    ).await.unwrap().next().await;
+    assert!(matches!(result, Some(Err(ServiceError::Status(ref status))) if status.code() == nrpc::Code::Unimplemented));
+
+    // tonic client to an nrpc-h2 server
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let nrpc_url = format!("http://{}", listener.local_addr().unwrap());
+    let server = nrpc_h2::H2Server::new(nrpc::ServiceRegistry::new().with_cloned(helloworld::GreeterServer::new(GreeterService)));
+    tokio::spawn(async move { server.serve(listener).await });
+    let mut tonic_client = tonic_helloworld::greeter_client::GreeterClient::connect(nrpc_url.clone()).await.unwrap();
+    let tonic_req = || tonic_helloworld::HelloRequest { name: "World".into() };
+    let tonic_names = || futures::stream::iter((0..3).map(|i| tonic_helloworld::HelloRequest { name: format!("World{}", i) }));
+
+    // tonic one to one, with metadata, a deadline and trailing metadata
+    let resp = tonic_client.say_hello(tonic_req()).await.unwrap();
+    assert_eq!(resp.get_ref().message, "Hello World");
+    let mut request = tonic::Request::new(tonic_req());
+    request.metadata_mut().insert("greeting", "Howdy".parse().unwrap());
+    request.metadata_mut().insert_bin("trace-bin", tonic::metadata::MetadataValue::from_bytes(&[0u8, 1, 2, 255]));
+    request.set_timeout(std::time::Duration::from_secs(5));
+    let resp = tonic_client.say_hello(request).await.unwrap();
+    assert_eq!(resp.get_ref().message, "Howdy World");
+    assert_eq!(resp.metadata().get("has-deadline").unwrap(), "true");
+
+    // tonic many to one
+    let resp = tonic_client.say_hello_many_to_one(tonic_names()).await.unwrap();
+    assert_eq!(resp.get_ref().message, "Hello World0, World1, World2");
+
+    // tonic many to one stalled waiting for input, alongside a unary call
+    let (requests, stream_in) = futures::channel::mpsc::unbounded();
+    requests.unbounded_send(tonic_helloworld::HelloRequest { name: "Slow".into() }).unwrap();
+    let mut stalled_client = tonic_client.clone();
+    let mut stalled = Box::pin(stalled_client.say_hello_many_to_one(stream_in));
+    assert!(tokio::time::timeout(std::time::Duration::from_millis(100), &mut stalled).await.is_err());
+    let unary = tonic_client.say_hello(tonic_helloworld::HelloRequest { name: "Fast".into() });
+    let resp = tokio::time::timeout(std::time::Duration::from_secs(5), unary)
+        .await
+        .expect("Unary call was held up by a stalled call");
+    assert_eq!(resp.unwrap().get_ref().message, "Hello Fast");
+    drop(requests);
+    assert_eq!(stalled.await.unwrap().get_ref().message, "Hello Slow");
+
+    // tonic one to many with trailer
+    let mut resp = tonic_client.say_hello_one_to_many(tonic_req()).await.unwrap().into_inner();
+    let mut messages = Vec::new();
+    while let Some(reply) = resp.message().await.unwrap() {
+        messages.push(reply.message);
+    }
+    assert_eq!(messages, vec!["Hello World"; 3]);
+    let trailers = resp.trailers().await.unwrap().unwrap();
+    assert_eq!(trailers.get("greeting-count").unwrap(), "3");
+
+    // tonic many to many
+    let resp = tonic_client.say_hello_many_to_many(tonic_names()).await.unwrap().into_inner();
+    let messages: Vec<_> = resp.map(|reply| reply.unwrap().message).collect().await;
+    assert_eq!(messages, vec!["Hello World0", "Hello World1", "Hello World2"]);
+
+    // tonic status error from nrpc, with its details
+    let status = tonic_client.say_hello(tonic_helloworld::HelloRequest::default()).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    assert_eq!(status.message(), "name is required");
+    let details = nrpc::Status::from_bytes(bytes::Bytes::copy_from_slice(status.details())).unwrap();
+    let bad_request: nrpc::error_details::BadRequest = details.get_detail().unwrap();
+    assert_eq!(bad_request.field_violations[0].field, "name");
+
+    // tonic one to many cancelled by dropping the response stream
+    let endless = tonic_helloworld::HelloRequest { name: "Endless".into() };
+    let mut resp = tonic_client.say_hello_one_to_many(endless).await.unwrap().into_inner();
+    resp.message().await.unwrap().unwrap();
+    drop(resp);
+    wait_for_cancelled_calls(5).await;
+
+    // nrpc-h2 on both ends
+    let client_impl = helloworld::GreeterClient::new(nrpc_h2::H2ClientHandler::new(nrpc_url));
+    let deadline_ctx = nrpc::CallContext::new().with_timeout(std::time::Duration::from_secs(5));
+    let resp = client_impl.say_hello_with_context(&deadline_ctx, req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Hello World");
+    assert_eq!(deadline_ctx.trailer().await.metadata.get("has-deadline"), Some("true"));
+    let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+        Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+    let resp: Vec<_> = client_impl.say_hello_many_to_many(Box::new(stream_in)).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+    assert_eq!(resp, vec!["Hello World0", "Hello World1", "Hello World2"]);
+}
+
+async fn socket_transport(req: helloworld::HelloRequest) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = nrpc_socket::SocketServer::new(
+        nrpc::ServiceRegistry::new().with_cloned(helloworld::GreeterServer::new(GreeterService)),
+    );
+    let tcp_server = server.clone();
+    tokio::spawn(async move { tcp_server.serve(listener).await });
+    let handler = nrpc_socket::SocketClientHandler::connect_tcp(addr).await.unwrap();
+    let client_impl = helloworld::GreeterClient::new(handler.clone());
+
+    // socket one to one with metadata and trailer
+    let mut greeting_ctx = nrpc::CallContext::new();
+    greeting_ctx.metadata_mut().insert("greeting", "Howdy").unwrap();
+    let resp = client_impl.say_hello_with_context(&greeting_ctx, req.clone()).await.unwrap();
+    assert_eq!(resp.message, "Howdy World");
+    assert!(greeting_ctx.trailer().await.is_ok());
+
+    // socket many to one
+    let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+        Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+    let resp = client_impl.say_hello_many_to_one(Box::new(stream_in)).await.unwrap();
+    assert_eq!(resp.message, "Hello World0, World1, World2");
+
+    // socket one to many with trailer
+    let trailer_ctx = nrpc::CallContext::new();
+    let resp: Vec<_> = client_impl.say_hello_one_to_many_with_context(&trailer_ctx, req.clone()).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+    assert_eq!(resp, vec!["Hello World"; 3]);
+    assert_eq!(trailer_ctx.trailer().await.metadata.get("greeting-count"), Some("3"));
+
+    // socket status error
+    let result = client_impl.say_hello(helloworld::HelloRequest::default()).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::InvalidArgument));
+
+    // socket many to many, interleaved with other calls over the same connection
+    let (requests, stream_in) = futures::channel::mpsc::unbounded();
+    let mut resp = client_impl.say_hello_many_to_many(Box::new(stream_in.map(Ok))).await.unwrap();
+    for i in 0..3 {
+        requests.unbounded_send(helloworld::HelloRequest { name: format!("World{}", i) }).unwrap();
+        assert_eq!(resp.next().await.unwrap().unwrap().message, format!("Hello World{}", i));
+        let unary_req = helloworld::HelloRequest { name: format!("Unary{}", i) };
+        assert_eq!(client_impl.say_hello(unary_req).await.unwrap().message, format!("Hello Unary{}", i));
+    }
+    drop(requests);
+    assert!(resp.next().await.is_none());
+
+    // socket many to one stalled waiting for input, alongside a unary call
+    let (requests, stream_in) = futures::channel::mpsc::unbounded();
+    requests.unbounded_send(helloworld::HelloRequest { name: "Slow".into() }).unwrap();
+    let mut stalled = Box::pin(client_impl.say_hello_many_to_one(Box::new(stream_in.map(Ok))));
+    assert!(tokio::time::timeout(std::time::Duration::from_millis(100), &mut stalled).await.is_err());
+    let unary = client_impl.say_hello(helloworld::HelloRequest { name: "Fast".into() });
+    let resp = tokio::time::timeout(std::time::Duration::from_secs(5), unary)
+        .await
+        .expect("Unary call was held up by a stalled call");
+    assert_eq!(resp.unwrap().message, "Hello Fast");
+    drop(requests);
+    assert_eq!(stalled.await.unwrap().message, "Hello Slow");
+
+    // socket concurrent calls
+    let names: Vec<_> = (0..16).map(|i| format!("Concurrent{}", i)).collect();
+    let resps = futures::future::join_all(names.iter().map(|name|
+        client_impl.say_hello(helloworld::HelloRequest { name: name.clone() }))).await;
+    for (resp, name) in resps.into_iter().zip(&names) {
+        assert_eq!(resp.unwrap().message, format!("Hello {}", name));
+    }
+
+    // socket one to many cancelled by dropping the response stream, leaving the connection usable
+    let mut resp = client_impl.say_hello_one_to_many(helloworld::HelloRequest { name: "Endless".into() }).await.unwrap();
+    resp.next().await.unwrap().unwrap();
+    drop(resp);
+    wait_for_cancelled_calls(6).await;
+    assert_eq!(client_impl.say_hello(req.clone()).await.unwrap().message, "Hello World");
+
+    // socket connection closed by the server
+    let (client_stream, server_stream) = tokio::io::duplex(4096);
+    let duplex_server = server.clone();
+    let serving = tokio::spawn(async move { duplex_server.serve_connection(server_stream).await });
+    let duplex_handler = nrpc_socket::SocketClientHandler::new(client_stream);
+    let duplex_client = helloworld::GreeterClient::new(duplex_handler.clone());
+    assert_eq!(duplex_client.say_hello(req.clone()).await.unwrap().message, "Hello World");
+    serving.abort();
+    assert!(serving.await.unwrap_err().is_cancelled());
+    while !duplex_handler.is_closed() {
+        tokio::task::yield_now().await;
+    }
+    let result = duplex_client.say_hello(req.clone()).await;
+    assert!(matches!(result, Err(ServiceError::Status(ref status)) if status.code() == nrpc::Code::Unavailable));
+
+    // socket flow control, where a reader which falls behind holds back the remote writer
+    let window_frame = nrpc::wire::Frame::window(7, 1024);
+    assert_eq!(nrpc::wire::Frame::from_bytes(window_frame.to_bytes().unwrap()).unwrap(), window_frame);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let small_window_server = server.clone().with_window(1024);
+    tokio::spawn(async move { small_window_server.serve(listener).await });
+    let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let client_impl = helloworld::GreeterClient::new(nrpc_socket::SocketClientHandler::with_window(stream, 1024));
+    let sent = Arc::new(AtomicUsize::new(0));
+    let sent_counter = sent.clone();
+    let stream_in = futures::stream::repeat_with(move || {
+        sent_counter.fetch_add(1, Ordering::SeqCst);
+        Ok(helloworld::HelloRequest { name: "Flood".into() })
+    });
+    let mut resp = client_impl.say_hello_many_to_many(Box::new(stream_in)).await.unwrap();
+    assert_eq!(resp.next().await.unwrap().unwrap().message, "Hello Flood");
+    // the server stops reading requests once it may not send more replies
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    let stalled_at = sent.load(Ordering::SeqCst);
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert_eq!(sent.load(Ordering::SeqCst), stalled_at);
+    assert!(stalled_at < 20_000, "{} requests sent without backpressure", stalled_at);
+    // reading the replies lets the requests flow again
+    for _ in 0..stalled_at {
+        assert_eq!(resp.next().await.unwrap().unwrap().message, "Hello Flood");
+    }
+    assert!(sent.load(Ordering::SeqCst) > stalled_at);
+    drop(resp);
+    assert_eq!(client_impl.say_hello(req.clone()).await.unwrap().message, "Hello World");
+
+    // socket peer which ignores its window is disconnected
+    let (mut peer, server_stream) = tokio::io::duplex(1 << 20);
+    let duplex_server = server.clone();
+    let serving = tokio::spawn(async move { duplex_server.serve_connection(server_stream).await });
+    let header = nrpc::wire::CallHeader::new("helloworld", "Greeter", "say_hello_many_to_many");
+    let mut request = bytes::BytesMut::new();
+    nrpc::wire::Frame::call(1, header).encode(&mut request).unwrap();
+    let mut input_buf = bytes::BytesMut::new();
+    helloworld::HelloRequest { name: "x".repeat(4096) }.encode(&mut input_buf).unwrap();
+    // the peer never reads the replies, so the server stops reading requests and granting credit for them
+    for _ in 0..64 {
+        nrpc::wire::Frame::message(1, input_buf.clone().freeze()).encode(&mut request).unwrap();
+    }
+    tokio::io::AsyncWriteExt::write_all(&mut peer, &request).await.unwrap();
+    let result = serving.await.unwrap();
+    assert!(matches!(result, Err(nrpc::wire::WireError::WindowExceeded { stream_id: 1 })), "{:?}", result);
+    let mut response = Vec::new();
+    tokio::io::AsyncReadExt::read_to_end(&mut peer, &mut response).await.unwrap();
+
+    // unix domain socket
+    #[cfg(unix)]
+    {
+        let path = std::env::temp_dir().join(format!("nrpc-socket-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        let server = server.with_window(1 << 20);
+        tokio::spawn(async move { server.serve_unix(listener).await });
+        // windows larger than the initial window are granted when the call starts
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        let client_impl = helloworld::GreeterClient::new(nrpc_socket::SocketClientHandler::with_window(stream, 1 << 20));
+        let resp = client_impl.say_hello(req.clone()).await.unwrap();
+        assert_eq!(resp.message, "Hello World");
+        let stream_in = nrpc::VecStream::from_iter([(); 3].iter().enumerate().map(|(i, _)|
+            Ok(helloworld::HelloRequest { name: format!("World{}", i) })));
+        let resp: Vec<_> = client_impl.say_hello_many_to_many(Box::new(stream_in)).await.unwrap().map(|item_result| item_result.unwrap().message).collect().await;
+        assert_eq!(resp, vec!["Hello World0", "Hello World1", "Hello World2"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+/// Wait for the server to notice that `count` calls have been cancelled
+async fn wait_for_cancelled_calls(count: usize) {
+    for _ in 0..100 {
+        if CANCELLED_CALLS.load(Ordering::SeqCst) >= count {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    panic!("Server did not see the call cancelled");
+}
+
+#[derive(Clone)]
 struct GreeterService;
 
 #[async_trait::async_trait]
 impl helloworld::IGreeter<'_> for GreeterService {
     async fn say_hello(
         &mut self,
+        ctx: &nrpc::CallContext,
         input: helloworld::HelloRequest,
     ) -> Result<helloworld::HelloReply, Box<dyn Error + Send>> {
+        if input.name.is_empty() {
+            let violation = nrpc::error_details::bad_request::FieldViolation::new("name", "must not be empty");
+            return Err(Box::new(
+                nrpc::Status::invalid_argument("name is required")
+                    .with_detail(&nrpc::error_details::BadRequest { field_violations: vec![violation] })
+                    .with_detail(&nrpc::error_details::RetryInfo::new(std::time::Duration::from_millis(1500))),
+            ));
+        }
+        if input.name == "Sleepy" {
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        }
+        if ctx.deadline().is_some() {
+            let mut trailing = nrpc::Metadata::new();
+            trailing.insert("has-deadline", "true").unwrap();
+            ctx.set_trailing_metadata(trailing);
+        }
+        let greeting = ctx.metadata().get("greeting").unwrap_or("Hello");
         let result = helloworld::HelloReply {
-            message: format!("Hello {}", input.name),
+            message: format!("{} {}", greeting, input.name),
         };
         println!("{}", result.message);
         Ok(result)
@@ -145,11 +1666,26 @@ impl helloworld::IGreeter<'_> for GreeterService {
 
     async fn say_hello_one_to_many<'a>(
         &mut self,
+        ctx: &nrpc::CallContext,
         input: helloworld::HelloRequest,
     ) -> Result<
         ::nrpc::ServiceServerStream<'a, helloworld::HelloReply>,
         Box<dyn std::error::Error + Send>,
     > {
+        if input.name == "Endless" {
+            let watch_ctx = ctx.clone();
+            tokio::spawn(async move {
+                watch_ctx.cancelled().await;
+                CANCELLED_CALLS.fetch_add(1, Ordering::SeqCst);
+            });
+            let result = helloworld::HelloReply {
+                message: format!("Hello {}", input.name),
+            };
+            return Ok(Box::new(ctx.stop_on_cancel(futures::stream::repeat_with(move || Ok(result.clone())))));
+        }
+        let mut trailing_metadata = nrpc::Metadata::new();
+        trailing_metadata.insert("greeting-count", "3").map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+        ctx.set_trailing_metadata(trailing_metadata);
         let result = helloworld::HelloReply {
             message: format!("Hello {}", input.name),
         };
@@ -159,6 +1695,7 @@ impl helloworld::IGreeter<'_> for GreeterService {
 
     async fn say_hello_many_to_one<'a>(
         &mut self,
+        _ctx: &nrpc::CallContext,
         mut input: ::nrpc::ServiceServerStream<'a, helloworld::HelloRequest>,
     ) -> Result<helloworld::HelloReply, Box<dyn Error + Send>>{
         let mut message = "Hello ".to_string();
@@ -173,6 +1710,7 @@ impl helloworld::IGreeter<'_> for GreeterService {
 
     async fn say_hello_many_to_many<'a>(
         &mut self,
+        _ctx: &nrpc::CallContext,
         input: ::nrpc::ServiceServerStream<'a, helloworld::HelloRequest>,
     ) -> Result<
         ::nrpc::ServiceServerStream<'a, helloworld::HelloReply>,
@@ -188,49 +1726,60 @@ impl helloworld::IGreeter<'_> for GreeterService {
     }
 }
 
-struct ClientHandler;
+/// The same greeter as a tonic service
+struct TonicGreeter;
 
-#[async_trait::async_trait]
-impl nrpc::ClientHandler<'_> for ClientHandler {
-    /*async fn call(
-        &mut self,
-        package: &str,
-        service: &str,
-        method: &str,
-        input: bytes::Bytes,
-        output: &mut bytes::BytesMut,
-    ) -> Result<(), nrpc::ServiceError> {
-        println!(
-            "call {}.{}/{} with data {:?}",
-            package, service, method, input
-        );
-        // This is ok to hardcode ONLY because it's for testing
-        Ok(helloworld::HelloReply {
-            message: "Hello World".into(),
+type TonicReplies = std::pin::Pin<Box<dyn futures::Stream<Item = Result<tonic_helloworld::HelloReply, tonic::Status>> + Send>>;
+
+// tonic's own streams carry its large Status
+#[allow(clippy::result_large_err)]
+#[tonic::async_trait]
+impl tonic_helloworld::greeter_server::Greeter for TonicGreeter {
+    async fn say_hello(
+        &self,
+        request: tonic::Request<tonic_helloworld::HelloRequest>,
+    ) -> Result<tonic::Response<tonic_helloworld::HelloReply>, tonic::Status> {
+        let greeting = request.metadata().get("greeting").and_then(|value| value.to_str().ok()).unwrap_or("Hello").to_owned();
+        let name = request.into_inner().name;
+        if name.is_empty() {
+            let violation = nrpc::error_details::bad_request::FieldViolation::new("name", "must not be empty");
+            let status = nrpc::Status::invalid_argument("name is required")
+                .with_detail(&nrpc::error_details::BadRequest { field_violations: vec![violation] });
+            return Err(tonic::Status::with_details(tonic::Code::InvalidArgument, "name is required", status.to_bytes()));
         }
-        .encode(output)?)
-    }*/
+        Ok(tonic::Response::new(tonic_helloworld::HelloReply { message: format!("{} {}", greeting, name) }))
+    }
 
-    async fn call<'a>(
+    async fn say_hello_many_to_one(
         &self,
-        package: &str,
-        service: &str,
-        method: &str,
-        input: ::nrpc::ServiceClientStream<'a, ::nrpc::_helpers::bytes::Bytes>,
-    ) -> Result<::nrpc::ServiceClientStream<'a, ::nrpc::_helpers::bytes::Bytes>, ServiceError> {
-        println!(
-            "call {}.{}/{} with data stream",
-            package, service, method
-        );
-        // This is ok to hardcode ONLY because it's for testing
-        Ok(
-            Box::new(input.map(|item_result| {
-                    let mut output = bytes::BytesMut::new();
-                    item_result.and_then(|_item| helloworld::HelloReply {
-                        message: format!("Hello World"),
-                    }.encode(&mut output).map(|_| output.freeze()).map_err(|e| ServiceError::from(e)))
-                }
-            ))
-        )
+        request: tonic::Request<tonic::Streaming<tonic_helloworld::HelloRequest>>,
+    ) -> Result<tonic::Response<tonic_helloworld::HelloReply>, tonic::Status> {
+        let mut input = request.into_inner();
+        let mut names = Vec::new();
+        while let Some(request) = input.message().await? {
+            names.push(request.name);
+        }
+        Ok(tonic::Response::new(tonic_helloworld::HelloReply { message: format!("Hello {}", names.join(", ")) }))
+    }
+
+    type SayHelloOneToManyStream = TonicReplies;
+
+    async fn say_hello_one_to_many(
+        &self,
+        request: tonic::Request<tonic_helloworld::HelloRequest>,
+    ) -> Result<tonic::Response<TonicReplies>, tonic::Status> {
+        let reply = tonic_helloworld::HelloReply { message: format!("Hello {}", request.into_inner().name) };
+        Ok(tonic::Response::new(Box::pin(futures::stream::iter(vec![Ok(reply); 3]))))
+    }
+
+    type SayHelloManyToManyStream = TonicReplies;
+
+    async fn say_hello_many_to_many(
+        &self,
+        request: tonic::Request<tonic::Streaming<tonic_helloworld::HelloRequest>>,
+    ) -> Result<tonic::Response<TonicReplies>, tonic::Status> {
+        let replies = request.into_inner().map(|request| request.map(|request|
+            tonic_helloworld::HelloReply { message: format!("Hello {}", request.name) }));
+        Ok(tonic::Response::new(Box::pin(replies)))
     }
 }
@@ -0,0 +1,127 @@
+use futures::{Stream, StreamExt};
+use hyper::body::HttpBody;
+use hyper::client::HttpConnector;
+use hyper::header::{self, HeaderValue};
+use hyper::{Body, Client, Request, StatusCode};
+use nrpc::wire::{CallHeader, Frame, WireError};
+use nrpc::{CallContext, Metadata, ServiceClientStream, ServiceError, Status, Trailer};
+use nrpc_http::call::request_sink;
+use nrpc_http::envelope::EnvelopeDecoder;
+use nrpc_http::headers::{metadata_to_headers, method_path, status_from_http, trailer_from_headers};
+
+use super::CONTENT_TYPE;
+
+/// Client handler which makes gRPC calls over HTTP/2.
+///
+/// Calls to the same server share one connection.
+#[derive(Clone)]
+pub struct H2ClientHandler {
+    base_url: String,
+    client: Client<HttpConnector>,
+}
+
+impl H2ClientHandler {
+    /// Call the server at `base_url`, such as `http://localhost:50051`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_owned(),
+            client: Client::builder().http2_only(true).build_http(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+}
+
+#[async_trait::async_trait]
+impl<'b> nrpc::ClientHandler<'b> for H2ClientHandler {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, bytes::Bytes>,
+    ) -> Result<ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        let path = method_path(package, service, &super::proto_method_name(method));
+        let (sender, body) = Body::channel();
+        let mut request = Request::post(format!("{}{}", self.base_url, path))
+            .body(body)
+            .map_err(|e| Status::invalid_argument(format!("Invalid gRPC request: {}", e)))?;
+        let headers = request.headers_mut();
+        metadata_to_headers(&ctx.request_metadata(), headers);
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+        headers.insert(header::TE, HeaderValue::from_static("trailers"));
+        let response = self.client.request(request);
+        Ok(Box::new(nrpc::wire::client_call(
+            Box::pin(request_sink(sender, super::encode_message)),
+            Box::pin(response_frames(response)),
+            0,
+            CallHeader::new(package, service, method),
+            ctx.clone(),
+            input,
+        )))
+    }
+}
+
+fn io_error(e: hyper::Error) -> WireError {
+    WireError::Io(std::io::Error::other(e))
+}
+
+/// The frames of the response, which may be an HTTP error or only trailers instead of a gRPC body
+fn response_frames(response: hyper::client::ResponseFuture) -> impl Stream<Item = Result<Frame, WireError>> + Send {
+    futures::stream::once(async move {
+        let response = match response.await {
+            Ok(response) => response,
+            Err(e) => return futures::stream::iter([Err(io_error(e))]).left_stream(),
+        };
+        if let Some(trailer) = trailer_from_headers(response.headers()) {
+            return futures::stream::iter([Ok(Frame::trailer(0, trailer))]).left_stream();
+        }
+        if response.status() != StatusCode::OK {
+            let trailer = Trailer::new(status_from_http(response.status()), Metadata::new());
+            return futures::stream::iter([Ok(Frame::trailer(0, trailer))]).left_stream();
+        }
+        body_frames(response.into_body()).right_stream()
+    })
+    .flatten()
+}
+
+/// The messages of a response body, then its trailers
+fn body_frames(body: Body) -> impl Stream<Item = Result<Frame, WireError>> + Send {
+    let state = Some((body, EnvelopeDecoder::new(false)));
+    futures::stream::unfold(state, |state| async move {
+        let (mut body, mut decoder) = state?;
+        loop {
+            match decoder.next_envelope() {
+                Ok(Some((flags, payload))) => {
+                    let frame = super::message_frame(flags, payload);
+                    let state = frame.is_ok().then_some((body, decoder));
+                    return Some((frame, state));
+                }
+                Ok(None) => {}
+                Err(e) => return Some((Err(e), None)),
+            }
+            match body.data().await {
+                Some(Ok(chunk)) => {
+                    if let Err(e) = decoder.push(&chunk) {
+                        return Some((Err(e), None));
+                    }
+                }
+                Some(Err(e)) => return Some((Err(io_error(e)), None)),
+                None => break,
+            }
+        }
+        if !decoder.is_empty() {
+            return Some((Err(WireError::UnexpectedEof), None));
+        }
+        let trailer = match body.trailers().await {
+            Ok(trailers) => trailers.as_ref().and_then(trailer_from_headers),
+            Err(e) => return Some((Err(io_error(e)), None)),
+        };
+        let trailer = trailer
+            .unwrap_or_else(|| Trailer::new(Status::internal("Response ended without a grpc-status"), Metadata::new()));
+        Some((Ok(Frame::trailer(0, trailer)), None))
+    })
+}
@@ -0,0 +1,79 @@
+//! gRPC over HTTP/2 for nRPC, so nRPC clients and servers can talk to other gRPC implementations.
+//!
+//! Calls are HTTP/2 POSTs to `/package.Service/Method`, with the call metadata as request headers
+//! and the deadline in `grpc-timeout`. Request and response bodies are a sequence of messages,
+//! each one prefixed by a flags byte and a big-endian u32 length.
+//! The status and trailing metadata go in the `grpc-status`, `grpc-message` and
+//! `grpc-status-details-bin` trailers.
+//!
+//! Other gRPC implementations name methods as they are written in the .proto file, such as `SayHello`,
+//! where nRPC uses their snake_case name. Clients send the UpperCamelCase form of the method name
+//! and servers dispatch to the snake_case form of the name they receive.
+//! Compressed messages are not supported, so peers must leave `grpc-encoding` unset.
+
+mod client;
+mod server;
+
+pub use client::H2ClientHandler;
+pub use server::H2Server;
+
+use bytes::Bytes;
+use nrpc::wire::{Frame, FrameBody, WireError};
+
+/// Content type of gRPC calls
+pub const CONTENT_TYPE: &str = "application/grpc";
+
+const FLAG_COMPRESSED: u8 = 0x01;
+
+/// Whether a content type is gRPC's, such as `application/grpc` or `application/grpc+proto`
+fn is_grpc(content_type: &str) -> bool {
+    let base = nrpc_http::headers::media_type(content_type).split('+').next().unwrap_or_default();
+    base.eq_ignore_ascii_case(CONTENT_TYPE)
+}
+
+/// Encode the message frames of a call, the only frames which go in a gRPC body
+fn encode_message(frame: &Frame) -> Result<Option<Bytes>, WireError> {
+    Ok(match &frame.body {
+        FrameBody::Message(payload) => Some(nrpc_http::envelope::encode(0, payload)?.freeze()),
+        _ => None,
+    })
+}
+
+fn message_frame(flags: u8, payload: Bytes) -> Result<Frame, WireError> {
+    if flags & FLAG_COMPRESSED != 0 {
+        Err(WireError::Malformed("compressed gRPC messages are not supported"))
+    } else {
+        Ok(Frame::message(0, payload))
+    }
+}
+
+/// The name other gRPC implementations use for a method, such as `SayHello` for `say_hello`
+pub fn proto_method_name(method: &str) -> String {
+    let mut name = String::with_capacity(method.len());
+    for word in method.split('_') {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            name.extend(first.to_uppercase());
+            name.push_str(chars.as_str());
+        }
+    }
+    name
+}
+
+/// The name nRPC uses for a method, such as `say_hello` for `SayHello`
+pub fn rust_method_name(method: &str) -> String {
+    let chars: Vec<char> = method.chars().collect();
+    let mut name = String::with_capacity(method.len() + 4);
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let previous = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            // a new word starts after a lowercase letter or digit, or at the last capital of an acronym
+            if previous.is_lowercase() || previous.is_ascii_digit() || (previous.is_uppercase() && next_is_lower) {
+                name.push('_');
+            }
+        }
+        name.extend(c.to_lowercase());
+    }
+    name
+}
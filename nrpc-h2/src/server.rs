@@ -0,0 +1,121 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use futures::StreamExt;
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::service::service_fn;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use nrpc::wire::FrameBody;
+use nrpc::{CallContext, ServiceRegistry};
+use nrpc_http::call::spawn_call;
+use nrpc_http::headers::{metadata_from_headers, parse_path, trailer_to_headers};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+
+use super::CONTENT_TYPE;
+
+/// HTTP/2 server which answers gRPC calls from a registry of server services.
+///
+/// Connections are expected to start with the HTTP/2 preface, as gRPC clients send over plain TCP.
+#[derive(Clone)]
+pub struct H2Server {
+    registry: Arc<ServiceRegistry<'static>>,
+}
+
+impl H2Server {
+    pub fn new(registry: ServiceRegistry<'static>) -> Self {
+        Self {
+            registry: Arc::new(registry),
+        }
+    }
+
+    /// Accept connections forever, serving each one on a new task
+    pub async fn serve(&self, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                // errors only affect the calls on this connection
+                let _ = server.serve_connection(stream).await;
+            });
+        }
+    }
+
+    /// Serve the calls made over a connection, many at once
+    pub async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        &self,
+        stream: S,
+    ) -> Result<(), hyper::Error> {
+        let server = self.clone();
+        let service = service_fn(move |request| {
+            let server = server.clone();
+            async move { Ok::<_, Infallible>(server.handle(request)) }
+        });
+        hyper::server::conn::Http::new()
+            .http2_only(true)
+            .serve_connection(stream, service)
+            .await
+    }
+
+    /// Answer one HTTP/2 request, for use with an existing hyper server.
+    ///
+    /// The call runs on a new task, which streams the response body and its trailers.
+    pub fn handle(&self, request: Request<Body>) -> Response<Body> {
+        if request.method() != Method::POST {
+            return http_error(StatusCode::METHOD_NOT_ALLOWED);
+        }
+        let is_grpc = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(super::is_grpc);
+        if !is_grpc {
+            return http_error(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+        let route = parse_path(request.uri().path())
+            .map(|(package, service, method)| (package.to_owned(), service.to_owned(), super::rust_method_name(method)));
+        let ctx = CallContext::from_metadata(metadata_from_headers(request.headers()));
+        let frames = nrpc_http::envelope::decode_body(request.into_body(), false, super::message_frame);
+        let mut rx = spawn_call(self.registry.clone(), route, ctx, frames);
+
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            while let Some(frame) = rx.next().await {
+                match frame.body {
+                    FrameBody::Message(payload) => {
+                        let buf = match nrpc_http::envelope::encode(0, &payload) {
+                            Ok(buf) => buf.freeze(),
+                            Err(_) => {
+                                // the message cannot be framed, so reset the stream; dropping the frames cancels the call
+                                sender.abort();
+                                return;
+                            }
+                        };
+                        if sender.send_data(buf).await.is_err() {
+                            // the client reset the stream; dropping the frames cancels the call
+                            return;
+                        }
+                    }
+                    FrameBody::Trailer(trailer) => {
+                        let mut trailers = HeaderMap::new();
+                        trailer_to_headers(&trailer, &mut trailers);
+                        let _ = sender.send_trailers(trailers).await;
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+        });
+        let mut response = Response::new(body);
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+        response
+    }
+}
+
+fn http_error(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
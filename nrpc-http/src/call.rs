@@ -0,0 +1,75 @@
+//! Plumbing between HTTP bodies and the frame based call handling in [nrpc::wire]
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::channel::mpsc;
+use futures::future;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use hyper::body::Sender;
+use nrpc::wire::{Frame, FrameBody, WireError};
+use nrpc::{CallContext, ServiceError, ServiceRegistry, Status};
+
+/// Package, service and method of a call
+pub type Route = (String, String, String);
+
+/// Run a call received by a server on a new task, returning the frames of its response.
+///
+/// `frames` are the message frames of the request body, which ends the input when it ends.
+/// Calls without a route fail as `Unimplemented`.
+pub fn spawn_call<R>(
+    registry: Arc<ServiceRegistry<'static>>,
+    route: Option<Route>,
+    ctx: CallContext,
+    frames: R,
+) -> mpsc::Receiver<Frame>
+where
+    R: Stream<Item = Result<Frame, WireError>> + Send + Unpin + 'static,
+{
+    // the request body ending is not the client going away, so never end the frames there
+    let frames = frames
+        .chain(futures::stream::once(future::ready(Ok(Frame::end(0)))))
+        .chain(futures::stream::pending());
+    let (input, watch) = nrpc::wire::server_input(Box::pin(frames), &ctx);
+    let (tx, rx) = mpsc::channel(1);
+    let mut sink = tx.sink_map_err(|_| WireError::Io(std::io::ErrorKind::BrokenPipe.into()));
+    tokio::spawn(async move {
+        let serve = async {
+            let output = match &route {
+                Some((package, service, method)) => {
+                    registry.call(package, service, method, &ctx, Box::new(input)).await
+                }
+                None => Err(ServiceError::Status(Status::unimplemented("Invalid method path"))),
+            };
+            nrpc::wire::respond(&mut sink, 0, &ctx, output).await
+        };
+        // if the client goes away first, the call has been cancelled and there is no one to respond to;
+        // either way the response ends once the sink is dropped
+        future::select(Box::pin(serve), Box::pin(watch)).await;
+    });
+    rx
+}
+
+/// Sink which writes the message frames of a call into a request body, ending the body on the end frame.
+///
+/// Fails if `encode` fails to encode a frame.
+pub fn request_sink<F>(sender: Sender, encode: F) -> impl Sink<Frame, Error = WireError>
+where
+    F: Fn(&Frame) -> Result<Option<Bytes>, WireError>,
+{
+    futures::sink::unfold((Some(sender), encode), |(sender, encode), frame: Frame| async move {
+        let mut sender = match (sender, &frame.body) {
+            (_, FrameBody::End) => return Ok((None, encode)),
+            (Some(sender), FrameBody::Message(_)) => sender,
+            // the call frame was already sent as the request headers
+            (sender, _) => return Ok((sender, encode)),
+        };
+        if let Some(buf) = encode(&frame)? {
+            if sender.send_data(buf).await.is_err() {
+                // the server stopped reading the request, its response will say why
+                return Ok((None, encode));
+            }
+        }
+        Ok((Some(sender), encode))
+    })
+}
@@ -0,0 +1,151 @@
+use futures::{SinkExt, Stream, StreamExt};
+use hyper::client::HttpConnector;
+use hyper::header::{self, HeaderValue};
+use hyper::{Body, Client, Request, StatusCode};
+use nrpc::wire::{CallHeader, Frame, FrameBody, WireError};
+use nrpc::{CallContext, Metadata, MethodKind, ServiceClientStream, ServiceError, Status, Trailer};
+
+use super::{Format, Protocol, PROTOCOL_VERSION_KEY, TIMEOUT_KEY};
+use crate::call::request_sink;
+use crate::headers::{is_json, metadata_to_headers, method_path, status_from_http};
+
+/// Client handler which makes calls to a Connect server over HTTP/1.1.
+///
+/// Calls are sent as JSON when the call metadata has a JSON `content-type`, as set for
+/// [nrpc::ContentTypeCodec], and as protobuf otherwise.
+/// Generated clients tell the handler which methods stream; for other callers a call streams
+/// unless its input is known to be a single message.
+#[derive(Clone)]
+pub struct ConnectClientHandler {
+    base_url: String,
+    client: Client<HttpConnector>,
+}
+
+impl ConnectClientHandler {
+    /// Call the server at `base_url`, such as `http://localhost:8080`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_owned(),
+            client: Client::new(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+}
+
+#[async_trait::async_trait]
+impl<'b> nrpc::ClientHandler<'b> for ConnectClientHandler {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, bytes::Bytes>,
+    ) -> Result<ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        let format = match ctx.metadata().get(header::CONTENT_TYPE.as_str()) {
+            Some(content_type) if is_json(content_type) => Format::Json,
+            _ => Format::Proto,
+        };
+        let unary = match ctx.method_kind() {
+            Some(kind) => matches!(kind, MethodKind::Unary),
+            None => input.size_hint() == (1, Some(1)),
+        };
+        let protocol = if unary {
+            Protocol::Unary(format)
+        } else {
+            Protocol::Streaming(format)
+        };
+
+        let (sender, body) = Body::channel();
+        let mut request = Request::post(format!("{}{}", self.base_url, method_path(package, service, method)))
+            .body(body)
+            .map_err(|e| Status::invalid_argument(format!("Invalid Connect request: {}", e)))?;
+        let headers = request.headers_mut();
+        let mut metadata = ctx.metadata().clone();
+        metadata.remove(header::CONTENT_TYPE.as_str());
+        metadata_to_headers(&metadata, headers);
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(protocol.content_type()));
+        headers.insert(PROTOCOL_VERSION_KEY, HeaderValue::from_static("1"));
+        if let Some(remaining) = ctx.remaining() {
+            // a timeout of zero would mean none at all
+            let millis = remaining.as_millis().max(1);
+            headers.insert(TIMEOUT_KEY, HeaderValue::from(millis as u64));
+        }
+        let response = self.client.request(request);
+
+        let (sink, frames) = match protocol {
+            Protocol::Unary(_) => (
+                request_sink(sender, message_payload).left_sink(),
+                unary_response_frames(response).left_stream(),
+            ),
+            Protocol::Streaming(_) => (
+                request_sink(sender, super::encode_frame).right_sink(),
+                streaming_response_frames(response).right_stream(),
+            ),
+        };
+        Ok(Box::new(nrpc::wire::client_call(
+            Box::pin(sink),
+            Box::pin(frames),
+            0,
+            CallHeader::new(package, service, method),
+            ctx.clone(),
+            input,
+        )))
+    }
+}
+
+/// The body of a unary request is the bare message
+fn message_payload(frame: &Frame) -> Result<Option<bytes::Bytes>, WireError> {
+    Ok(match &frame.body {
+        FrameBody::Message(payload) => Some(payload.clone()),
+        _ => None,
+    })
+}
+
+fn io_error(e: hyper::Error) -> WireError {
+    WireError::Io(std::io::Error::other(e))
+}
+
+/// The frames of a unary response: the message and a trailer from the `trailer-` headers, or the error
+fn unary_response_frames(response: hyper::client::ResponseFuture) -> impl Stream<Item = Result<Frame, WireError>> + Send {
+    futures::stream::once(async move {
+        let response = response.await.map_err(io_error)?;
+        let status = response.status();
+        let metadata = super::trailer_metadata_from_headers(response.headers());
+        let body = hyper::body::to_bytes(response.into_body()).await.map_err(io_error)?;
+        if status == StatusCode::OK {
+            return Ok(vec![Ok(Frame::message(0, body)), Ok(Frame::trailer(0, Trailer::ok(metadata)))]);
+        }
+        let status = serde_json::from_slice(&body)
+            .ok()
+            .and_then(|error| super::error_from_json(&error))
+            .unwrap_or_else(|| status_from_http(status));
+        Ok(vec![Ok(Frame::trailer(0, Trailer::new(status, metadata)))])
+    })
+    .map(|frames: Result<_, WireError>| match frames {
+        Ok(frames) => futures::stream::iter(frames),
+        Err(e) => futures::stream::iter(vec![Err(e)]),
+    })
+    .flatten()
+}
+
+/// The frames of a streaming response, which may be an HTTP error instead of a Connect body
+fn streaming_response_frames(
+    response: hyper::client::ResponseFuture,
+) -> impl Stream<Item = Result<Frame, WireError>> + Send {
+    futures::stream::once(async move {
+        let response = match response.await {
+            Ok(response) => response,
+            Err(e) => return futures::stream::iter([Err(io_error(e))]).left_stream(),
+        };
+        if response.status() != StatusCode::OK {
+            let trailer = Trailer::new(status_from_http(response.status()), Metadata::new());
+            return futures::stream::iter([Ok(Frame::trailer(0, trailer))]).left_stream();
+        }
+        super::decode_body(response.into_body()).right_stream()
+    })
+    .flatten()
+}
@@ -0,0 +1,290 @@
+//! The Connect protocol, which serves the same calls as gRPC to plain HTTP clients.
+//!
+//! Calls are HTTP POSTs to `/package.Service/Method`, with the call metadata as request headers.
+//! Unary calls send and receive one bare message, with the `application/proto` or `application/json`
+//! content type. Their trailing metadata is sent as headers prefixed with `trailer-`,
+//! and errors as a JSON body with an HTTP error status.
+//! Streaming calls use the `application/connect+proto` or `application/connect+json` content type,
+//! with bodies made of frames like gRPC-Web. The response ends with a frame with flags `0x02`,
+//! carrying the error, if any, and the trailing metadata as JSON.
+//!
+//! The content type is left in the call metadata, so servers using [nrpc::ContentTypeCodec]
+//! answer JSON calls in JSON.
+
+mod client;
+mod server;
+
+pub use client::ConnectClientHandler;
+pub use server::ConnectServer;
+
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use bytes::Bytes;
+use futures::Stream;
+use hyper::header::{HeaderMap, HeaderName};
+use hyper::{Body, StatusCode};
+use nrpc::wire::{Frame, FrameBody, WireError};
+use nrpc::{Code, Metadata, MetadataValue, Status, Trailer, BINARY_SUFFIX};
+use serde_json::{json, Map, Value};
+
+use crate::headers::BASE64_ANY_PADDING;
+
+/// Header carrying the version of the Connect protocol, which is always `1`
+pub const PROTOCOL_VERSION_KEY: &str = "connect-protocol-version";
+/// Header carrying the timeout of a call in milliseconds
+pub const TIMEOUT_KEY: &str = "connect-timeout-ms";
+/// Prefix of the headers carrying the trailing metadata of unary calls
+pub const TRAILER_PREFIX: &str = "trailer-";
+
+const FLAG_COMPRESSED: u8 = 0x01;
+const FLAG_END_STREAM: u8 = 0x02;
+
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+/// How the messages of a call are encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Proto,
+    Json,
+}
+
+/// Whether a call is unary, with its content type, or streaming
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Protocol {
+    Unary(Format),
+    Streaming(Format),
+}
+
+impl Protocol {
+    /// The protocol of a call with this content type, if it is a Connect one
+    fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = crate::headers::media_type(content_type).to_ascii_lowercase();
+        match mime.as_str() {
+            "application/proto" => Some(Self::Unary(Format::Proto)),
+            "application/json" => Some(Self::Unary(Format::Json)),
+            "application/connect+proto" => Some(Self::Streaming(Format::Proto)),
+            "application/connect+json" => Some(Self::Streaming(Format::Json)),
+            _ => None,
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Self::Unary(Format::Proto) => "application/proto",
+            Self::Unary(Format::Json) => "application/json",
+            Self::Streaming(Format::Proto) => "application/connect+proto",
+            Self::Streaming(Format::Json) => "application/connect+json",
+        }
+    }
+}
+
+/// Name of a status code in Connect errors
+fn code_to_str(code: Code) -> &'static str {
+    match code {
+        Code::Ok => "ok",
+        Code::Cancelled => "canceled",
+        Code::Unknown => "unknown",
+        Code::InvalidArgument => "invalid_argument",
+        Code::DeadlineExceeded => "deadline_exceeded",
+        Code::NotFound => "not_found",
+        Code::AlreadyExists => "already_exists",
+        Code::PermissionDenied => "permission_denied",
+        Code::ResourceExhausted => "resource_exhausted",
+        Code::FailedPrecondition => "failed_precondition",
+        Code::Aborted => "aborted",
+        Code::OutOfRange => "out_of_range",
+        Code::Unimplemented => "unimplemented",
+        Code::Internal => "internal",
+        Code::Unavailable => "unavailable",
+        Code::DataLoss => "data_loss",
+        Code::Unauthenticated => "unauthenticated",
+    }
+}
+
+fn code_from_str(code: &str) -> Option<Code> {
+    Some(match code {
+        "canceled" => Code::Cancelled,
+        "unknown" => Code::Unknown,
+        "invalid_argument" => Code::InvalidArgument,
+        "deadline_exceeded" => Code::DeadlineExceeded,
+        "not_found" => Code::NotFound,
+        "already_exists" => Code::AlreadyExists,
+        "permission_denied" => Code::PermissionDenied,
+        "resource_exhausted" => Code::ResourceExhausted,
+        "failed_precondition" => Code::FailedPrecondition,
+        "aborted" => Code::Aborted,
+        "out_of_range" => Code::OutOfRange,
+        "unimplemented" => Code::Unimplemented,
+        "internal" => Code::Internal,
+        "unavailable" => Code::Unavailable,
+        "data_loss" => Code::DataLoss,
+        "unauthenticated" => Code::Unauthenticated,
+        _ => return None,
+    })
+}
+
+/// HTTP status of a unary call which failed with this code
+fn http_status(code: Code) -> StatusCode {
+    match code {
+        Code::Ok => StatusCode::OK,
+        Code::Cancelled => StatusCode::from_u16(499).unwrap(),
+        Code::InvalidArgument | Code::FailedPrecondition | Code::OutOfRange => StatusCode::BAD_REQUEST,
+        Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+        Code::NotFound => StatusCode::NOT_FOUND,
+        Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+        Code::PermissionDenied => StatusCode::FORBIDDEN,
+        Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+        Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+        Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+        Code::Unknown | Code::Internal | Code::DataLoss => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// A failed status as a Connect error, with its details as base64 encoded protobuf messages
+fn error_to_json(status: &Status) -> Value {
+    let mut error = Map::new();
+    error.insert("code".into(), code_to_str(status.code()).into());
+    if !status.message().is_empty() {
+        error.insert("message".into(), status.message().into());
+    }
+    if !status.details().is_empty() {
+        let details = status
+            .details()
+            .iter()
+            .map(|detail| {
+                json!({
+                    "type": detail.type_url.strip_prefix(TYPE_URL_PREFIX).unwrap_or(&detail.type_url),
+                    "value": STANDARD_NO_PAD.encode(&detail.value),
+                })
+            })
+            .collect();
+        error.insert("details".into(), Value::Array(details));
+    }
+    Value::Object(error)
+}
+
+/// The status of a Connect error, if it is one; details which cannot be decoded are dropped
+fn error_from_json(error: &Value) -> Option<Status> {
+    let code = code_from_str(error.get("code")?.as_str()?)?;
+    let message = error.get("message").and_then(Value::as_str).unwrap_or_default();
+    let details = error
+        .get("details")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|detail| {
+            let type_name = detail.get("type")?.as_str()?;
+            let value = BASE64_ANY_PADDING.decode(detail.get("value")?.as_str()?).ok()?;
+            Some(nrpc::_helpers::prost_types::Any {
+                type_url: format!("{}{}", TYPE_URL_PREFIX, type_name),
+                value,
+            })
+        })
+        .collect();
+    Some(Status::new(code, message).with_details(details))
+}
+
+/// Metadata as a JSON object of value arrays, with binary values base64 encoded
+fn metadata_to_json(metadata: &Metadata) -> Value {
+    let mut object = Map::new();
+    for (key, value) in metadata.iter() {
+        let value = match value {
+            MetadataValue::Ascii(value) => value.clone(),
+            MetadataValue::Binary(value) => STANDARD_NO_PAD.encode(value),
+        };
+        if let Value::Array(values) = object.entry(key).or_insert_with(|| Value::Array(Vec::new())) {
+            values.push(value.into());
+        }
+    }
+    Value::Object(object)
+}
+
+/// Metadata from a JSON object of value arrays, dropping any entries which are not valid metadata
+fn metadata_from_json(object: &Value) -> Metadata {
+    let mut metadata = Metadata::new();
+    let entries = object.as_object().into_iter().flatten();
+    for (key, values) in entries {
+        let key = key.to_ascii_lowercase();
+        for value in values.as_array().into_iter().flatten().filter_map(Value::as_str) {
+            let value = if key.ends_with(BINARY_SUFFIX) {
+                match BASE64_ANY_PADDING.decode(value) {
+                    Ok(value) => MetadataValue::Binary(value.into()),
+                    Err(_) => continue,
+                }
+            } else {
+                MetadataValue::Ascii(value.to_owned())
+            };
+            let _ = metadata.append_value(key.clone(), value);
+        }
+    }
+    metadata
+}
+
+/// Add trailing metadata to the headers of a unary response
+fn trailer_metadata_to_headers(metadata: &Metadata, headers: &mut HeaderMap) {
+    let mut trailers = HeaderMap::new();
+    crate::headers::metadata_to_headers(metadata, &mut trailers);
+    for (name, value) in &trailers {
+        if let Ok(name) = HeaderName::from_bytes(format!("{}{}", TRAILER_PREFIX, name).as_bytes()) {
+            headers.append(name, value.clone());
+        }
+    }
+}
+
+/// Trailing metadata from the headers of a unary response
+fn trailer_metadata_from_headers(headers: &HeaderMap) -> Metadata {
+    let mut trailers = HeaderMap::new();
+    for (name, value) in headers {
+        if let Some(name) = name.as_str().strip_prefix(TRAILER_PREFIX) {
+            if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
+                trailers.append(name, value.clone());
+            }
+        }
+    }
+    crate::headers::metadata_from_headers(&trailers)
+}
+
+/// The end of a streaming response, with the error if the call failed
+fn encode_end_stream(trailer: &Trailer) -> Bytes {
+    let mut end = Map::new();
+    if !trailer.status.is_ok() {
+        end.insert("error".into(), error_to_json(&trailer.status));
+    }
+    if !trailer.metadata.is_empty() {
+        end.insert("metadata".into(), metadata_to_json(&trailer.metadata));
+    }
+    Value::Object(end).to_string().into()
+}
+
+fn decode_end_stream(payload: &[u8]) -> Result<Trailer, WireError> {
+    let end: Value = serde_json::from_slice(payload).map_err(|_| WireError::Malformed("invalid end of stream JSON"))?;
+    let status = match end.get("error") {
+        Some(error) => error_from_json(error).unwrap_or_else(|| Status::unknown("Invalid Connect error")),
+        None => Status::ok(),
+    };
+    let metadata = end.get("metadata").map(metadata_from_json).unwrap_or_default();
+    Ok(Trailer::new(status, metadata))
+}
+
+/// Encode the message and trailer frames of a streaming call as Connect envelopes
+fn encode_frame(frame: &Frame) -> Result<Option<Bytes>, WireError> {
+    Ok(match &frame.body {
+        FrameBody::Message(payload) => Some(crate::envelope::encode(0, payload)?.freeze()),
+        FrameBody::Trailer(trailer) => Some(crate::envelope::encode(FLAG_END_STREAM, &encode_end_stream(trailer))?.freeze()),
+        _ => None,
+    })
+}
+
+/// The message and trailer frames of a streaming Connect body
+fn decode_body(body: Body) -> impl Stream<Item = Result<Frame, WireError>> + Send + Unpin {
+    crate::envelope::decode_body(body, false, |flags, payload| {
+        if flags & FLAG_COMPRESSED != 0 {
+            Err(WireError::Malformed("compressed Connect messages are not supported"))
+        } else if flags & FLAG_END_STREAM != 0 {
+            Ok(Frame::trailer(0, decode_end_stream(&payload)?))
+        } else {
+            Ok(Frame::message(0, payload))
+        }
+    })
+}
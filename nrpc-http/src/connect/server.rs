@@ -0,0 +1,202 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future;
+use futures::StreamExt;
+use hyper::header::{self, HeaderValue};
+use hyper::service::service_fn;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use nrpc::wire::{Frame, FrameBody, WireError, DEFAULT_MAX_FRAME_LENGTH};
+use nrpc::{CallContext, Metadata, ServiceRegistry, Status, Trailer};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+
+use super::{Protocol, TIMEOUT_KEY, TRAILER_PREFIX};
+use crate::call::spawn_call;
+use crate::cors;
+use crate::headers::{metadata_from_headers, parse_path};
+
+/// Request headers of Connect calls which browsers may send from other origins
+const REQUEST_HEADERS: &[&str] = &["content-type", super::PROTOCOL_VERSION_KEY, TIMEOUT_KEY];
+
+/// HTTP server which answers Connect calls from a registry of server services.
+///
+/// Browsers may only call it from its own origin, unless others are allowed with
+/// [with_allowed_origins](Self::with_allowed_origins).
+#[derive(Clone)]
+pub struct ConnectServer {
+    registry: Arc<ServiceRegistry<'static>>,
+    cors: cors::Cors,
+}
+
+impl ConnectServer {
+    pub fn new(registry: ServiceRegistry<'static>) -> Self {
+        Self {
+            registry: Arc::new(registry),
+            cors: cors::Cors::default(),
+        }
+    }
+
+    /// Let browsers call the server from `origins`, such as `https://example.com`, or from any origin with `*`
+    pub fn with_allowed_origins(mut self, origins: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.cors.set_origins(origins);
+        self
+    }
+
+    /// Let browsers send `headers` from other origins, such as those carrying call metadata
+    pub fn with_allowed_headers(mut self, headers: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.cors.set_headers(headers);
+        self
+    }
+
+    /// Accept connections forever, serving each one on a new task
+    pub async fn serve(&self, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                // errors only affect the calls on this connection
+                let _ = server.serve_connection(stream).await;
+            });
+        }
+    }
+
+    /// Serve the HTTP/1.1 requests made over a connection
+    pub async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        &self,
+        stream: S,
+    ) -> Result<(), hyper::Error> {
+        let server = self.clone();
+        let service = service_fn(move |request| {
+            let server = server.clone();
+            async move { Ok::<_, Infallible>(server.handle(request).await) }
+        });
+        hyper::server::conn::Http::new()
+            .http1_only(true)
+            .serve_connection(stream, service)
+            .await
+    }
+
+    /// Answer one HTTP request, for use with an existing hyper server.
+    ///
+    /// Streaming calls run on a new task, which streams the response body.
+    pub async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let origin = request.headers().get(header::ORIGIN).cloned();
+        let mut response = if request.method() == Method::OPTIONS {
+            self.cors.preflight(request.headers(), REQUEST_HEADERS)
+        } else {
+            self.handle_call(request).await
+        };
+        // unary calls have a header per trailing metadata key
+        let trailers: Vec<String> = response
+            .headers()
+            .keys()
+            .map(|name| name.as_str())
+            .filter(|name| name.starts_with(TRAILER_PREFIX))
+            .map(str::to_owned)
+            .collect();
+        let trailers: Vec<&str> = trailers.iter().map(String::as_str).collect();
+        self.cors.allow(origin, &trailers, &mut response);
+        response
+    }
+
+    async fn handle_call(&self, request: Request<Body>) -> Response<Body> {
+        if request.method() != Method::POST {
+            return crate::http_error(StatusCode::METHOD_NOT_ALLOWED);
+        }
+        let protocol = match request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(Protocol::from_content_type)
+        {
+            Some(protocol) => protocol,
+            None => return crate::http_error(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+        };
+        let route = parse_path(request.uri().path())
+            .map(|(package, service, method)| (package.to_owned(), service.to_owned(), method.to_owned()));
+        let mut ctx = CallContext::from_metadata(metadata_from_headers(request.headers()));
+        let timeout = request
+            .headers()
+            .get(TIMEOUT_KEY)
+            .and_then(|timeout| timeout.to_str().ok())
+            .and_then(|timeout| timeout.parse().ok());
+        if let Some(timeout) = timeout {
+            ctx.set_timeout(Duration::from_millis(timeout));
+        }
+
+        if let Protocol::Unary(_) = protocol {
+            return self.handle_unary(route, ctx, request.into_body(), protocol).await;
+        }
+        let rx = spawn_call(self.registry.clone(), route, ctx, super::decode_body(request.into_body()));
+        let body = rx.filter_map(|frame: Frame| future::ready(super::encode_frame(&frame).transpose()));
+        let mut response = Response::new(Body::wrap_stream(body));
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static(protocol.content_type()));
+        response
+    }
+
+    /// Answer a unary call once it has completed, since its status decides the HTTP status
+    async fn handle_unary(
+        &self,
+        route: Option<crate::call::Route>,
+        ctx: CallContext,
+        body: Body,
+        protocol: Protocol,
+    ) -> Response<Body> {
+        let input = match crate::read_body(body, DEFAULT_MAX_FRAME_LENGTH).await {
+            Ok(input) => input,
+            Err(e) => {
+                let status = match e {
+                    WireError::FrameTooLarge { .. } => Status::resource_exhausted(e.to_string()),
+                    e => Status::invalid_argument(format!("Failed to read request body: {}", e)),
+                };
+                return unary_response(None, Trailer::new(status, Metadata::new()), protocol);
+            }
+        };
+        let frames = futures::stream::once(future::ready(Ok(Frame::message(0, input))));
+        let mut rx = spawn_call(self.registry.clone(), route, ctx, Box::pin(frames));
+        let mut message = None;
+        let mut trailer = None;
+        while let Some(frame) = rx.next().await {
+            match frame.body {
+                FrameBody::Message(payload) if message.is_none() => message = Some(payload),
+                FrameBody::Message(_) => {
+                    trailer = Some(Trailer::new(
+                        Status::unimplemented("Unary call answered with more than one message"),
+                        Metadata::new(),
+                    ));
+                    break;
+                }
+                FrameBody::Trailer(t) => trailer = Some(t),
+                _ => {}
+            }
+        }
+        let trailer = trailer.unwrap_or_else(|| Trailer::new(Status::internal("Call ended without a status"), Metadata::new()));
+        unary_response(message, trailer, protocol)
+    }
+}
+
+/// Response to a unary call, with its message if it succeeded and its error otherwise
+fn unary_response(message: Option<bytes::Bytes>, trailer: Trailer, protocol: Protocol) -> Response<Body> {
+    let (status, body, content_type) = match message {
+        Some(message) if trailer.status.is_ok() => (StatusCode::OK, message, protocol.content_type()),
+        _ => {
+            let status = if trailer.status.is_ok() {
+                Status::unimplemented("Unary call answered without a message")
+            } else {
+                trailer.status.clone()
+            };
+            let error = super::error_to_json(&status).to_string();
+            (super::http_status(status.code()), error.into(), "application/json")
+        }
+    };
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    super::trailer_metadata_to_headers(&trailer.metadata, headers);
+    response
+}
@@ -0,0 +1,80 @@
+//! CORS headers, so browsers may call the servers in this crate from the origins they allow
+
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::{Body, Response, StatusCode};
+
+/// Origins which may call a server from a browser, and the extra request headers they may send.
+///
+/// Allows no other origins by default, leaving browsers to only call the server from its own.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Cors {
+    origins: Vec<String>,
+    headers: Vec<String>,
+}
+
+impl Cors {
+    /// Allow calls from `origins`, such as `https://example.com`, or from anywhere with `*`
+    pub(crate) fn set_origins(&mut self, origins: impl IntoIterator<Item = impl Into<String>>) {
+        self.origins = origins.into_iter().map(Into::into).collect();
+    }
+
+    /// Allow request headers beyond the protocol's own, such as those carrying call metadata
+    pub(crate) fn set_headers(&mut self, headers: impl IntoIterator<Item = impl Into<String>>) {
+        self.headers = headers.into_iter().map(|name| name.into().to_ascii_lowercase()).collect();
+    }
+
+    fn allows_any(&self) -> bool {
+        self.origins.iter().any(|origin| origin == "*")
+    }
+
+    /// Value of `Access-Control-Allow-Origin` for a request from `origin`, if that origin is allowed
+    fn allowed_origin(&self, origin: Option<&HeaderValue>) -> Option<HeaderValue> {
+        let origin = origin?;
+        if self.allows_any() {
+            return Some(HeaderValue::from_static("*"));
+        }
+        let origin_str = origin.to_str().ok()?;
+        self.origins
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(origin_str))
+            .then(|| origin.clone())
+    }
+
+    /// Answer a preflight request, allowing the protocol's `request_headers` and the configured ones
+    pub(crate) fn preflight(&self, headers: &HeaderMap, request_headers: &[&str]) -> Response<Body> {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NO_CONTENT;
+        if self.allowed_origin(headers.get(header::ORIGIN)).is_none() {
+            return response;
+        }
+        let allowed: Vec<&str> = request_headers
+            .iter()
+            .copied()
+            .chain(self.headers.iter().map(String::as_str))
+            .collect();
+        let headers = response.headers_mut();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static("POST, OPTIONS"));
+        if let Ok(allowed) = HeaderValue::from_str(&allowed.join(", ")) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, allowed);
+        }
+        headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from_static("86400"));
+        response
+    }
+
+    /// Allow the request's origin to read the response, including the `expose` headers, if it is allowed
+    pub(crate) fn allow(&self, origin: Option<HeaderValue>, expose: &[&str], response: &mut Response<Body>) {
+        let headers = response.headers_mut();
+        // the response depends on the origin, so caches must not hand it to other origins
+        if !self.origins.is_empty() && !self.allows_any() {
+            headers.append(header::VARY, HeaderValue::from_static("Origin"));
+        }
+        let allowed = match self.allowed_origin(origin.as_ref()) {
+            Some(allowed) => allowed,
+            None => return,
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allowed);
+        if !expose.is_empty() {
+            headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, HeaderValue::from_str(&expose.join(",")).unwrap());
+        }
+    }
+}
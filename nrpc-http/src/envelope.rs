@@ -0,0 +1,131 @@
+//! Length-prefixed messages, as used by gRPC-Web bodies and Connect streaming bodies:
+//! a flags byte, a big-endian u32 length and that many bytes of payload.
+//! gRPC over HTTP/2 frames its messages the same way.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use hyper::Body;
+use nrpc::wire::{Frame, WireError, DEFAULT_MAX_FRAME_LENGTH};
+
+const PREFIX_LENGTH: usize = 5;
+
+/// One envelope, ready to be sent, unless the payload is too long for its length prefix
+pub fn encode(flags: u8, payload: &[u8]) -> Result<BytesMut, WireError> {
+    let length = u32::try_from(payload.len()).map_err(|_| WireError::FrameTooLarge {
+        length: payload.len(),
+        max: u32::MAX,
+    })?;
+    let mut buf = BytesMut::with_capacity(PREFIX_LENGTH + payload.len());
+    buf.put_u8(flags);
+    buf.put_u32(length);
+    buf.put_slice(payload);
+    Ok(buf)
+}
+
+/// Incremental decoder of body chunks into envelopes
+pub struct EnvelopeDecoder {
+    /// base64 text which has not been decoded yet, if the body is base64 encoded
+    text: Option<BytesMut>,
+    buf: BytesMut,
+}
+
+impl EnvelopeDecoder {
+    /// Decode a body which is base64 encoded text if `base64` is set, like gRPC-Web text bodies
+    pub fn new(base64: bool) -> Self {
+        Self {
+            text: base64.then(BytesMut::new),
+            buf: BytesMut::new(),
+        }
+    }
+
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), WireError> {
+        let text = match self.text.as_mut() {
+            Some(text) => text,
+            None => {
+                self.buf.extend_from_slice(chunk);
+                return Ok(());
+            }
+        };
+        text.extend_from_slice(chunk);
+        // only whole 4 character groups can be decoded, and each chunk the sender encoded may end in padding
+        let whole = text.len() / 4 * 4;
+        let text = text.split_to(whole);
+        for group in text.split_inclusive(|c| *c == b'=') {
+            if group.iter().all(|c| *c == b'=') {
+                continue;
+            }
+            let mut group = group.to_vec();
+            group.resize(group.len().div_ceil(4) * 4, b'=');
+            let decoded = STANDARD
+                .decode(&group)
+                .map_err(|_| WireError::Malformed("invalid base64 body"))?;
+            self.buf.extend_from_slice(&decoded);
+        }
+        Ok(())
+    }
+
+    /// The flags and payload of the next envelope, once it has been received in full
+    pub fn next_envelope(&mut self) -> Result<Option<(u8, Bytes)>, WireError> {
+        if self.buf.len() < PREFIX_LENGTH {
+            return Ok(None);
+        }
+        let flags = self.buf[0];
+        let length = u32::from_be_bytes(self.buf[1..PREFIX_LENGTH].try_into().unwrap());
+        if length > DEFAULT_MAX_FRAME_LENGTH {
+            return Err(WireError::FrameTooLarge {
+                length: length as usize,
+                max: DEFAULT_MAX_FRAME_LENGTH,
+            });
+        }
+        if self.buf.len() < PREFIX_LENGTH + length as usize {
+            return Ok(None);
+        }
+        self.buf.advance(PREFIX_LENGTH);
+        Ok(Some((flags, self.buf.split_to(length as usize).freeze())))
+    }
+
+    /// Whether everything pushed so far has been taken as envelopes
+    pub fn is_empty(&self) -> bool {
+        let text_empty = match &self.text {
+            Some(text) => text.iter().all(|c| *c == b'='),
+            None => true,
+        };
+        self.buf.is_empty() && text_empty
+    }
+}
+
+/// The envelopes of a body as frames; the stream ends when the body does
+pub fn decode_body<F>(
+    body: Body,
+    base64: bool,
+    to_frame: F,
+) -> impl Stream<Item = Result<Frame, WireError>> + Send + Unpin
+where
+    F: FnMut(u8, Bytes) -> Result<Frame, WireError> + Send + 'static,
+{
+    let state = (body, EnvelopeDecoder::new(base64), to_frame, false);
+    Box::pin(futures::stream::unfold(state, |(mut body, mut decoder, mut to_frame, failed)| async move {
+        if failed {
+            return None;
+        }
+        loop {
+            let result = match decoder.next_envelope() {
+                Ok(Some((flags, payload))) => to_frame(flags, payload),
+                Ok(None) => match body.next().await {
+                    Some(Ok(chunk)) => match decoder.push(&chunk) {
+                        Ok(()) => continue,
+                        Err(e) => Err(e),
+                    },
+                    Some(Err(e)) => Err(WireError::Io(std::io::Error::other(e))),
+                    None if decoder.is_empty() => return None,
+                    None => Err(WireError::UnexpectedEof),
+                },
+                Err(e) => Err(e),
+            };
+            let failed = result.is_err();
+            return Some((result, (body, decoder, to_frame, failed)));
+        }
+    }))
+}
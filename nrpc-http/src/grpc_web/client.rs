@@ -0,0 +1,92 @@
+use futures::{Stream, StreamExt};
+use hyper::client::HttpConnector;
+use hyper::header::{self, HeaderValue};
+use hyper::{Body, Client, Request, StatusCode};
+use nrpc::wire::{CallHeader, Frame, WireError};
+use nrpc::{CallContext, Metadata, ServiceClientStream, ServiceError, Status, Trailer};
+
+use super::Encoding;
+use crate::call::request_sink;
+use crate::headers::{metadata_to_headers, method_path, status_from_http, trailer_from_headers};
+
+/// Client handler which makes calls to a gRPC-Web server over HTTP/1.1
+#[derive(Clone)]
+pub struct GrpcWebClientHandler {
+    base_url: String,
+    encoding: Encoding,
+    client: Client<HttpConnector>,
+}
+
+impl GrpcWebClientHandler {
+    /// Call the server at `base_url`, such as `http://localhost:8080`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_owned(),
+            encoding: Encoding::Binary,
+            client: Client::new(),
+        }
+    }
+
+    /// Send base64 encoded bodies, like browsers which cannot stream binary responses
+    pub fn with_text_encoding(mut self) -> Self {
+        self.encoding = Encoding::Text;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+}
+
+#[async_trait::async_trait]
+impl<'b> nrpc::ClientHandler<'b> for GrpcWebClientHandler {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, bytes::Bytes>,
+    ) -> Result<ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        let (sender, body) = Body::channel();
+        let mut request = Request::post(format!("{}{}", self.base_url, method_path(package, service, method)))
+            .body(body)
+            .map_err(|e| Status::invalid_argument(format!("Invalid gRPC-Web request: {}", e)))?;
+        let headers = request.headers_mut();
+        metadata_to_headers(&ctx.request_metadata(), headers);
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(self.encoding.content_type()));
+        headers.insert("x-grpc-web", HeaderValue::from_static("1"));
+        let response = self.client.request(request);
+        let encoding = self.encoding;
+        Ok(Box::new(nrpc::wire::client_call(
+            Box::pin(request_sink(sender, move |frame| super::encode_frame(frame, encoding))),
+            Box::pin(response_frames(response, encoding)),
+            0,
+            CallHeader::new(package, service, method),
+            ctx.clone(),
+            input,
+        )))
+    }
+}
+
+/// The frames of the response, which may be an HTTP error or only a trailer instead of a gRPC-Web body
+fn response_frames(
+    response: hyper::client::ResponseFuture,
+    encoding: Encoding,
+) -> impl Stream<Item = Result<Frame, WireError>> + Send {
+    futures::stream::once(async move {
+        let response = match response.await {
+            Ok(response) => response,
+            Err(e) => return futures::stream::iter([Err(WireError::Io(std::io::Error::other(e)))]).left_stream(),
+        };
+        if let Some(trailer) = trailer_from_headers(response.headers()) {
+            return futures::stream::iter([Ok(Frame::trailer(0, trailer))]).left_stream();
+        }
+        if response.status() != StatusCode::OK {
+            let trailer = Trailer::new(status_from_http(response.status()), Metadata::new());
+            return futures::stream::iter([Ok(Frame::trailer(0, trailer))]).left_stream();
+        }
+        super::decode_body(response.into_body(), encoding).right_stream()
+    })
+    .flatten()
+}
@@ -0,0 +1,111 @@
+//! gRPC-Web, the protocol browsers use to make gRPC calls.
+//!
+//! Calls are HTTP POSTs to `/package.Service/Method`, with the call metadata as request headers.
+//! Request and response bodies are a sequence of frames, each one a flags byte,
+//! a big-endian u32 length and that many bytes of payload.
+//! Frames with flags `0x00` carry one message. The response ends with a frame with flags `0x80`,
+//! carrying the status and trailing metadata formatted like HTTP/1 headers.
+//! With the `application/grpc-web-text` content type, bodies are base64 encoded.
+
+mod client;
+mod server;
+
+pub use client::GrpcWebClientHandler;
+pub use server::GrpcWebServer;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::Stream;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::Body;
+use nrpc::wire::{Frame, FrameBody, WireError};
+use nrpc::{Metadata, Status, Trailer};
+
+/// Content type of binary gRPC-Web calls
+pub const CONTENT_TYPE: &str = "application/grpc-web";
+/// Content type of base64 encoded gRPC-Web calls
+pub const TEXT_CONTENT_TYPE: &str = "application/grpc-web-text";
+
+const FLAG_TRAILER: u8 = 0x80;
+const FLAG_COMPRESSED: u8 = 0x01;
+
+/// How a gRPC-Web body is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Binary,
+    Text,
+}
+
+impl Encoding {
+    /// Encoding of a body with this content type, if it is a gRPC-Web one
+    fn from_content_type(content_type: &str) -> Option<Self> {
+        let base = crate::headers::media_type(content_type).split('+').next().unwrap_or_default();
+        if base.eq_ignore_ascii_case(TEXT_CONTENT_TYPE) {
+            Some(Self::Text)
+        } else if base.eq_ignore_ascii_case(CONTENT_TYPE) {
+            Some(Self::Binary)
+        } else {
+            None
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Self::Binary => CONTENT_TYPE,
+            Self::Text => TEXT_CONTENT_TYPE,
+        }
+    }
+}
+
+/// Encode the message and trailer frames of a call as gRPC-Web, other frames have no gRPC-Web equivalent
+fn encode_frame(frame: &Frame, encoding: Encoding) -> Result<Option<Bytes>, WireError> {
+    let buf = match &frame.body {
+        FrameBody::Message(payload) => crate::envelope::encode(0, payload)?,
+        FrameBody::Trailer(trailer) => crate::envelope::encode(FLAG_TRAILER, &encode_trailer(trailer))?,
+        _ => return Ok(None),
+    };
+    Ok(Some(match encoding {
+        Encoding::Binary => buf.freeze(),
+        Encoding::Text => STANDARD.encode(buf).into(),
+    }))
+}
+
+fn encode_trailer(trailer: &Trailer) -> Bytes {
+    let mut headers = HeaderMap::new();
+    crate::headers::trailer_to_headers(trailer, &mut headers);
+    let mut buf = BytesMut::new();
+    for (name, value) in &headers {
+        buf.put_slice(name.as_str().as_bytes());
+        buf.put_slice(b": ");
+        buf.put_slice(value.as_bytes());
+        buf.put_slice(b"\r\n");
+    }
+    buf.freeze()
+}
+
+fn decode_trailer(payload: &[u8]) -> Result<Trailer, WireError> {
+    let text = std::str::from_utf8(payload).map_err(|_| WireError::InvalidUtf8)?;
+    let mut headers = HeaderMap::new();
+    for line in text.split("\r\n").filter(|line| !line.is_empty()) {
+        let (name, value) = line.split_once(':').ok_or(WireError::Malformed("trailer line without a colon"))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| WireError::Malformed("invalid trailer name"))?;
+        let value = HeaderValue::from_str(value.trim()).map_err(|_| WireError::Malformed("invalid trailer value"))?;
+        headers.append(name, value);
+    }
+    Ok(crate::headers::trailer_from_headers(&headers)
+        .unwrap_or_else(|| Trailer::new(Status::internal("Trailer without grpc-status"), Metadata::new())))
+}
+
+/// The message and trailer frames of a gRPC-Web body
+fn decode_body(body: Body, encoding: Encoding) -> impl Stream<Item = Result<Frame, WireError>> + Send + Unpin {
+    crate::envelope::decode_body(body, encoding == Encoding::Text, |flags, payload| {
+        if flags & FLAG_COMPRESSED != 0 {
+            Err(WireError::Malformed("compressed gRPC-Web frames are not supported"))
+        } else if flags & FLAG_TRAILER != 0 {
+            Ok(Frame::trailer(0, decode_trailer(&payload)?))
+        } else {
+            Ok(Frame::message(0, payload))
+        }
+    })
+}
@@ -0,0 +1,121 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use futures::future;
+use futures::StreamExt;
+use hyper::header::{self, HeaderValue};
+use hyper::service::service_fn;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use nrpc::wire::Frame;
+use nrpc::{CallContext, ServiceRegistry};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+
+use super::Encoding;
+use crate::call::spawn_call;
+use crate::cors;
+use crate::headers::{metadata_from_headers, parse_path, MESSAGE_KEY, STATUS_DETAILS_KEY, STATUS_KEY};
+
+/// Request headers of gRPC-Web calls which browsers may send from other origins
+const REQUEST_HEADERS: &[&str] = &["content-type", "x-grpc-web", "x-user-agent", nrpc::TIMEOUT_KEY];
+
+/// HTTP server which answers gRPC-Web calls from a registry of server services.
+///
+/// Browsers may only call it from its own origin, unless others are allowed with
+/// [with_allowed_origins](Self::with_allowed_origins).
+#[derive(Clone)]
+pub struct GrpcWebServer {
+    registry: Arc<ServiceRegistry<'static>>,
+    cors: cors::Cors,
+}
+
+impl GrpcWebServer {
+    pub fn new(registry: ServiceRegistry<'static>) -> Self {
+        Self {
+            registry: Arc::new(registry),
+            cors: cors::Cors::default(),
+        }
+    }
+
+    /// Let browsers call the server from `origins`, such as `https://example.com`, or from any origin with `*`
+    pub fn with_allowed_origins(mut self, origins: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.cors.set_origins(origins);
+        self
+    }
+
+    /// Let browsers send `headers` from other origins, such as those carrying call metadata
+    pub fn with_allowed_headers(mut self, headers: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.cors.set_headers(headers);
+        self
+    }
+
+    /// Accept connections forever, serving each one on a new task
+    pub async fn serve(&self, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                // errors only affect the calls on this connection
+                let _ = server.serve_connection(stream).await;
+            });
+        }
+    }
+
+    /// Serve the HTTP/1.1 requests made over a connection
+    pub async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        &self,
+        stream: S,
+    ) -> Result<(), hyper::Error> {
+        let server = self.clone();
+        let service = service_fn(move |request| {
+            let server = server.clone();
+            async move { Ok::<_, Infallible>(server.handle(request).await) }
+        });
+        hyper::server::conn::Http::new()
+            .http1_only(true)
+            .serve_connection(stream, service)
+            .await
+    }
+
+    /// Answer one HTTP request, for use with an existing hyper server.
+    ///
+    /// The call runs on a new task, which streams the response body.
+    pub async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let origin = request.headers().get(header::ORIGIN).cloned();
+        let mut response = if request.method() == Method::OPTIONS {
+            self.cors.preflight(request.headers(), REQUEST_HEADERS)
+        } else {
+            self.handle_call(request)
+        };
+        self.cors.allow(origin, &[STATUS_KEY, MESSAGE_KEY, STATUS_DETAILS_KEY], &mut response);
+        response
+    }
+
+    fn handle_call(&self, request: Request<Body>) -> Response<Body> {
+        if request.method() != Method::POST {
+            return crate::http_error(StatusCode::METHOD_NOT_ALLOWED);
+        }
+        let content_type = request.headers().get(header::CONTENT_TYPE).cloned();
+        let encoding = match content_type
+            .as_ref()
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(Encoding::from_content_type)
+        {
+            Some(encoding) => encoding,
+            None => return crate::http_error(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+        };
+        let route = parse_path(request.uri().path())
+            .map(|(package, service, method)| (package.to_owned(), service.to_owned(), method.to_owned()));
+        let ctx = CallContext::from_metadata(metadata_from_headers(request.headers()));
+        let frames = super::decode_body(request.into_body(), encoding);
+        let rx = spawn_call(self.registry.clone(), route, ctx, frames);
+
+        let body = rx.filter_map(move |frame: Frame| future::ready(super::encode_frame(&frame, encoding).transpose()));
+        let mut response = Response::new(Body::wrap_stream(body));
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            content_type.unwrap_or_else(|| HeaderValue::from_static(encoding.content_type())),
+        );
+        response
+    }
+}
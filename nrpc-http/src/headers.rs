@@ -0,0 +1,201 @@
+//! Conversion between nRPC metadata and HTTP headers, following the gRPC conventions:
+//! `-bin` values are base64 encoded and the call status goes in `grpc-status`, `grpc-message`
+//! and `grpc-status-details-bin`.
+
+use base64::alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig, STANDARD_NO_PAD};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::StatusCode;
+use nrpc::{Code, Metadata, MetadataValue, Status, Trailer, BINARY_SUFFIX};
+
+/// Header carrying the numeric status code of a call
+pub const STATUS_KEY: &str = "grpc-status";
+/// Header carrying the percent-encoded status message of a call
+pub const MESSAGE_KEY: &str = "grpc-message";
+/// Header carrying the whole status of a call, as a protobuf encoded `google.rpc.Status`
+pub const STATUS_DETAILS_KEY: &str = "grpc-status-details-bin";
+
+/// Headers which belong to the HTTP connection rather than to the call
+const CONNECTION_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "host",
+    "keep-alive",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Binary values may be sent with or without padding
+pub(crate) const BASE64_ANY_PADDING: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Call metadata sent as HTTP headers, leaving out connection headers and any which are not valid metadata
+pub fn metadata_from_headers(headers: &HeaderMap) -> Metadata {
+    let mut metadata = Metadata::new();
+    for (name, value) in headers {
+        let key = name.as_str();
+        if CONNECTION_HEADERS.contains(&key) {
+            continue;
+        }
+        let value = if key.ends_with(BINARY_SUFFIX) {
+            match BASE64_ANY_PADDING.decode(value.as_bytes()) {
+                Ok(value) => MetadataValue::Binary(value.into()),
+                Err(_) => continue,
+            }
+        } else {
+            match value.to_str() {
+                Ok(value) => MetadataValue::Ascii(value.to_owned()),
+                Err(_) => continue,
+            }
+        };
+        // invalid entries are dropped, like a gRPC server would
+        let _ = metadata.append_value(key, value);
+    }
+    metadata
+}
+
+/// Add call metadata to HTTP headers
+pub fn metadata_to_headers(metadata: &Metadata, headers: &mut HeaderMap) {
+    for (key, value) in metadata.iter() {
+        let value = match value {
+            MetadataValue::Ascii(value) => HeaderValue::from_str(value),
+            MetadataValue::Binary(value) => HeaderValue::from_str(&STANDARD_NO_PAD.encode(value)),
+        };
+        // metadata keys and values are always valid header names and values
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(key.as_bytes()), value) {
+            headers.append(name, value);
+        }
+    }
+}
+
+/// Add the status and trailing metadata of a call to HTTP headers
+pub fn trailer_to_headers(trailer: &Trailer, headers: &mut HeaderMap) {
+    let status = &trailer.status;
+    headers.insert(STATUS_KEY, HeaderValue::from(i32::from(status.code())));
+    if !status.message().is_empty() {
+        headers.insert(MESSAGE_KEY, HeaderValue::from_str(&percent_encode(status.message())).unwrap());
+    }
+    if !status.details().is_empty() {
+        let details = STANDARD_NO_PAD.encode(status.to_bytes());
+        headers.insert(STATUS_DETAILS_KEY, HeaderValue::from_str(&details).unwrap());
+    }
+    metadata_to_headers(&trailer.metadata, headers);
+}
+
+/// The status and trailing metadata of a call from HTTP headers, if they include a status
+pub fn trailer_from_headers(headers: &HeaderMap) -> Option<Trailer> {
+    let code = headers.get(STATUS_KEY)?.to_str().ok()?.trim().parse().ok()?;
+    let message = headers
+        .get(MESSAGE_KEY)
+        .and_then(|message| message.to_str().ok())
+        .map(percent_decode)
+        .unwrap_or_default();
+    let details = headers
+        .get(STATUS_DETAILS_KEY)
+        .and_then(|details| BASE64_ANY_PADDING.decode(details.as_bytes()).ok())
+        .and_then(|details| Status::from_bytes(details.into()).ok());
+    let status = match details {
+        // the details have the same code and message, but keep whatever the headers say
+        Some(details) => Status::new(Code::from_i32(code), message).with_details(details.details().to_vec()),
+        None => Status::new(Code::from_i32(code), message),
+    };
+    let mut metadata = metadata_from_headers(headers);
+    for key in [STATUS_KEY, MESSAGE_KEY, STATUS_DETAILS_KEY, "content-type"] {
+        metadata.remove(key);
+    }
+    Some(Trailer::new(status, metadata))
+}
+
+/// Status of a call which failed with an HTTP error instead of a gRPC status
+pub fn status_from_http(status: StatusCode) -> Status {
+    let code = match status {
+        StatusCode::BAD_REQUEST => Code::Internal,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::Unimplemented,
+        StatusCode::TOO_MANY_REQUESTS
+        | StatusCode::BAD_GATEWAY
+        | StatusCode::SERVICE_UNAVAILABLE
+        | StatusCode::GATEWAY_TIMEOUT => Code::Unavailable,
+        _ => Code::Unknown,
+    };
+    Status::new(code, format!("HTTP status {}", status))
+}
+
+/// Split a `/package.Service/Method` request path into its package, service and method
+pub fn parse_path(path: &str) -> Option<(&str, &str, &str)> {
+    let (qualified_service, method) = path.strip_prefix('/')?.split_once('/')?;
+    if method.is_empty() || method.contains('/') {
+        return None;
+    }
+    match qualified_service.rsplit_once('.') {
+        Some((package, service)) if !service.is_empty() => Some((package, service, method)),
+        Some(_) => None,
+        None if !qualified_service.is_empty() => Some(("", qualified_service, method)),
+        None => None,
+    }
+}
+
+/// Request path of a method, the reverse of [parse_path]
+pub fn method_path(package: &str, service: &str, method: &str) -> String {
+    if package.is_empty() {
+        format!("/{}/{}", service, method)
+    } else {
+        format!("/{}.{}/{}", package, service, method)
+    }
+}
+
+/// Media type of a content type, without parameters like "; charset=utf-8"
+pub fn media_type(content_type: &str) -> &str {
+    content_type.split(';').next().unwrap_or_default().trim()
+}
+
+/// Whether a content type is JSON, such as `application/json` or `application/connect+json`
+pub(crate) fn is_json(content_type: &str) -> bool {
+    let mime = media_type(content_type).to_ascii_lowercase();
+    mime == "application/json" || mime.ends_with("+json")
+}
+
+/// Percent-encode a status message, as `grpc-message` requires
+fn percent_encode(message: &str) -> String {
+    let mut encoded = String::with_capacity(message.len());
+    for byte in message.bytes() {
+        if (0x20..0x7f).contains(&byte) && byte != b'%' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Decode a percent-encoded status message, leaving invalid escapes as they are
+fn percent_decode(message: &str) -> String {
+    let bytes = message.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,44 @@
+//! HTTP transports for nRPC, so services can be called by clients which speak standard HTTP protocols.
+//!
+//! - [grpc_web]: gRPC-Web, as used by browser gRPC clients
+//! - [connect]: the Connect protocol, which also takes JSON messages from plain HTTP clients
+//! - [twirp]: Twirp, for unary calls to and from existing Twirp services
+//!
+//! [call], [envelope] and [headers] are shared with other HTTP based transports, such as `nrpc-h2`.
+
+pub mod call;
+pub mod connect;
+mod cors;
+pub mod envelope;
+pub mod grpc_web;
+pub mod headers;
+pub mod twirp;
+
+use hyper::body::HttpBody;
+use nrpc::wire::WireError;
+
+/// Response to a request which is not a call at all
+fn http_error(status: hyper::StatusCode) -> hyper::Response<hyper::Body> {
+    let mut response = hyper::Response::new(hyper::Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+/// Whole body of a request, failing with [WireError::FrameTooLarge] once it is longer than `max` bytes
+async fn read_body(mut body: hyper::Body, max: u32) -> Result<bytes::Bytes, WireError> {
+    let too_large = |length| WireError::FrameTooLarge { length, max };
+    // a declared length is known to be too long before reading any of it
+    if body.size_hint().lower() > u64::from(max) {
+        return Err(too_large(usize::try_from(body.size_hint().lower()).unwrap_or(usize::MAX)));
+    }
+    let mut buf = bytes::BytesMut::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| WireError::Io(std::io::Error::other(e)))?;
+        let length = buf.len() + chunk.len();
+        if length > max as usize {
+            return Err(too_large(length));
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(buf.freeze())
+}
@@ -0,0 +1,113 @@
+use futures::StreamExt;
+use hyper::client::HttpConnector;
+use hyper::header::{self, HeaderValue};
+use hyper::{Body, Client, Request, StatusCode};
+use nrpc::{CallContext, Metadata, ServiceClientStream, ServiceError, Status, Trailer};
+
+use super::{DEFAULT_PREFIX, JSON_CONTENT_TYPE, PROTOBUF_CONTENT_TYPE};
+use crate::headers::{is_json, metadata_from_headers, metadata_to_headers, method_path, status_from_http};
+
+/// Client handler which makes calls to a Twirp server over HTTP/1.1.
+///
+/// Calls are sent as JSON when the call metadata has a JSON `content-type`, as set for
+/// [nrpc::ContentTypeCodec], and as protobuf otherwise.
+/// Calls to streaming methods fail as `Unimplemented` without reaching the server.
+#[derive(Clone)]
+pub struct TwirpClientHandler {
+    base_url: String,
+    prefix: String,
+    client: Client<HttpConnector>,
+}
+
+impl TwirpClientHandler {
+    /// Call the server at `base_url`, such as `http://localhost:8080`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_owned(),
+            prefix: DEFAULT_PREFIX.to_owned(),
+            client: Client::new(),
+        }
+    }
+
+    /// Call routes under `prefix` instead of `/twirp`
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into().trim_end_matches('/').to_owned();
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    async fn call_unary(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        message: bytes::Bytes,
+    ) -> Result<(bytes::Bytes, Metadata), (Status, Metadata)> {
+        let url = format!("{}{}{}", self.base_url, self.prefix, method_path(package, service, method));
+        let mut request = Request::post(url)
+            .body(Body::from(message))
+            .map_err(|e| (Status::invalid_argument(format!("Invalid Twirp request: {}", e)), Metadata::new()))?;
+        let content_type = match ctx.metadata().get(header::CONTENT_TYPE.as_str()) {
+            Some(content_type) if is_json(content_type) => JSON_CONTENT_TYPE,
+            _ => PROTOBUF_CONTENT_TYPE,
+        };
+        let headers = request.headers_mut();
+        let mut metadata = ctx.metadata().clone();
+        metadata.remove(header::CONTENT_TYPE.as_str());
+        metadata_to_headers(&metadata, headers);
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+
+        let unavailable = |e: hyper::Error| (Status::unavailable(e.to_string()), Metadata::new());
+        let response = self.client.request(request).await.map_err(unavailable)?;
+        let status = response.status();
+        let mut metadata = metadata_from_headers(response.headers());
+        metadata.remove(header::CONTENT_TYPE.as_str());
+        let body = hyper::body::to_bytes(response.into_body()).await.map_err(unavailable)?;
+        if status == StatusCode::OK {
+            return Ok((body, metadata));
+        }
+        // errors from proxies in front of the server are not Twirp errors
+        Err(serde_json::from_slice(&body)
+            .ok()
+            .and_then(|error| super::error_from_json(&error))
+            .unwrap_or_else(|| (status_from_http(status), Metadata::new())))
+    }
+}
+
+#[async_trait::async_trait]
+impl<'b> nrpc::ClientHandler<'b> for TwirpClientHandler {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        mut input: ServiceClientStream<'a, bytes::Bytes>,
+    ) -> Result<ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        let result = match ctx.method_kind() {
+            Some(kind) if kind != nrpc::MethodKind::Unary => Err((
+                Status::unimplemented(format!("{} is a {} method, which Twirp cannot call", method, kind.as_str())),
+                Metadata::new(),
+            )),
+            _ => match input.next().await {
+                Some(Ok(message)) => self.call_unary(package, service, method, ctx, message).await,
+                Some(Err(e)) => Err((e.status(), Metadata::new())),
+                None => Err((Status::invalid_argument("Unary call without a request message"), Metadata::new())),
+            },
+        };
+        match result {
+            Ok((message, metadata)) => {
+                ctx.complete(Trailer::ok(metadata));
+                Ok(Box::new(nrpc::OnceStream::once(Ok(message))))
+            }
+            Err((status, metadata)) => {
+                ctx.complete(Trailer::new(status.clone(), metadata));
+                Err(ServiceError::Status(status))
+            }
+        }
+    }
+}
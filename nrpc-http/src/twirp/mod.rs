@@ -0,0 +1,129 @@
+//! Twirp, a simple unary only protocol over HTTP.
+//!
+//! Calls are HTTP POSTs to `/twirp/package.Service/Method`, with the call metadata as request headers.
+//! Requests and responses are one bare message, with the `application/protobuf` or `application/json`
+//! content type. Errors are a JSON body with an HTTP error status:
+//! `{"code": "not_found", "msg": "...", "meta": {"key": "value"}}`.
+//! Twirp has no trailers, so trailing metadata is sent as response headers, or in `meta` for errors.
+//!
+//! Streaming methods cannot be called over Twirp and fail with a `bad_route` error.
+//! The content type is left in the call metadata, so servers using [nrpc::ContentTypeCodec]
+//! answer JSON calls in JSON.
+
+mod client;
+mod server;
+
+pub use client::TwirpClientHandler;
+pub use server::TwirpServer;
+
+use hyper::StatusCode;
+use nrpc::{Code, Metadata, MetadataValue, Status};
+use serde_json::{Map, Value};
+
+/// Path prefix of Twirp routes, unless a different one is configured
+pub const DEFAULT_PREFIX: &str = "/twirp";
+
+/// Content type of protobuf binary calls
+pub const PROTOBUF_CONTENT_TYPE: &str = "application/protobuf";
+/// Content type of JSON calls
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// Error code of requests which do not name a method which can be called
+const BAD_ROUTE: &str = "bad_route";
+/// Error code of request bodies which cannot be decoded
+const MALFORMED: &str = "malformed";
+
+/// Name of a status code in Twirp errors
+fn code_to_str(code: Code) -> &'static str {
+    match code {
+        Code::Ok => "ok",
+        Code::Cancelled => "canceled",
+        Code::Unknown => "unknown",
+        Code::InvalidArgument => "invalid_argument",
+        Code::DeadlineExceeded => "deadline_exceeded",
+        Code::NotFound => "not_found",
+        Code::AlreadyExists => "already_exists",
+        Code::PermissionDenied => "permission_denied",
+        Code::ResourceExhausted => "resource_exhausted",
+        Code::FailedPrecondition => "failed_precondition",
+        Code::Aborted => "aborted",
+        Code::OutOfRange => "out_of_range",
+        Code::Unimplemented => "unimplemented",
+        Code::Internal => "internal",
+        Code::Unavailable => "unavailable",
+        Code::DataLoss => "dataloss",
+        Code::Unauthenticated => "unauthenticated",
+    }
+}
+
+fn code_from_str(code: &str) -> Option<Code> {
+    Some(match code {
+        "canceled" => Code::Cancelled,
+        "unknown" => Code::Unknown,
+        "invalid_argument" | MALFORMED => Code::InvalidArgument,
+        "deadline_exceeded" => Code::DeadlineExceeded,
+        "not_found" => Code::NotFound,
+        "already_exists" => Code::AlreadyExists,
+        "permission_denied" => Code::PermissionDenied,
+        "resource_exhausted" => Code::ResourceExhausted,
+        "failed_precondition" => Code::FailedPrecondition,
+        "aborted" => Code::Aborted,
+        "out_of_range" => Code::OutOfRange,
+        "unimplemented" | BAD_ROUTE => Code::Unimplemented,
+        "internal" => Code::Internal,
+        "unavailable" => Code::Unavailable,
+        "dataloss" => Code::DataLoss,
+        "unauthenticated" => Code::Unauthenticated,
+        _ => return None,
+    })
+}
+
+/// HTTP status of an error with this Twirp code
+fn http_status(code: &str) -> StatusCode {
+    match code {
+        "canceled" | "deadline_exceeded" => StatusCode::REQUEST_TIMEOUT,
+        "invalid_argument" | MALFORMED | "out_of_range" => StatusCode::BAD_REQUEST,
+        "not_found" | BAD_ROUTE => StatusCode::NOT_FOUND,
+        "already_exists" | "aborted" => StatusCode::CONFLICT,
+        "permission_denied" => StatusCode::FORBIDDEN,
+        "unauthenticated" => StatusCode::UNAUTHORIZED,
+        "resource_exhausted" => StatusCode::TOO_MANY_REQUESTS,
+        "failed_precondition" => StatusCode::PRECONDITION_FAILED,
+        "unimplemented" => StatusCode::NOT_IMPLEMENTED,
+        "unavailable" => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// A Twirp error body; binary metadata has no place in `meta` and is left out
+fn error_to_json(code: &str, message: &str, metadata: &Metadata) -> Value {
+    let mut error = Map::new();
+    error.insert("code".into(), code.into());
+    error.insert("msg".into(), message.into());
+    let meta: Map<String, Value> = metadata
+        .iter()
+        .filter_map(|(key, value)| match value {
+            MetadataValue::Ascii(value) => Some((key.to_owned(), value.clone().into())),
+            MetadataValue::Binary(_) => None,
+        })
+        .collect();
+    if !meta.is_empty() {
+        error.insert("meta".into(), Value::Object(meta));
+    }
+    Value::Object(error)
+}
+
+/// The status and metadata of a Twirp error body, if it is one
+fn error_from_json(error: &Value) -> Option<(Status, Metadata)> {
+    let code = code_from_str(error.get("code")?.as_str()?).unwrap_or(Code::Unknown);
+    let message = error.get("msg").and_then(Value::as_str).unwrap_or_default();
+    let mut metadata = Metadata::new();
+    let meta = error.get("meta").and_then(Value::as_object).into_iter().flatten();
+    for (key, value) in meta {
+        if let Some(value) = value.as_str() {
+            // invalid entries are dropped, like invalid headers
+            let _ = metadata.append(key.to_ascii_lowercase(), value);
+        }
+    }
+    Some((Status::new(code, message), metadata))
+}
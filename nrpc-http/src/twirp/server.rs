@@ -0,0 +1,203 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use futures::StreamExt;
+use hyper::header::{self, HeaderValue};
+use hyper::service::service_fn;
+use hyper::{Body, Method, Request, Response};
+use nrpc::wire::{WireError, DEFAULT_MAX_FRAME_LENGTH};
+use nrpc::{CallContext, Metadata, MethodKind, ServiceError, ServiceRegistry, Status, Trailer};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+
+use super::{BAD_ROUTE, DEFAULT_PREFIX, JSON_CONTENT_TYPE, MALFORMED, PROTOBUF_CONTENT_TYPE};
+use crate::cors;
+use crate::headers::{media_type, metadata_from_headers, metadata_to_headers, parse_path};
+
+/// Request headers of Twirp calls which browsers may send from other origins
+const REQUEST_HEADERS: &[&str] = &["content-type"];
+
+/// HTTP server which answers Twirp calls from a registry of server services.
+///
+/// Browsers may only call it from its own origin, unless others are allowed with
+/// [with_allowed_origins](Self::with_allowed_origins).
+#[derive(Clone)]
+pub struct TwirpServer {
+    registry: Arc<ServiceRegistry<'static>>,
+    cors: cors::Cors,
+    prefix: String,
+}
+
+impl TwirpServer {
+    pub fn new(registry: ServiceRegistry<'static>) -> Self {
+        Self {
+            registry: Arc::new(registry),
+            cors: cors::Cors::default(),
+            prefix: DEFAULT_PREFIX.to_owned(),
+        }
+    }
+
+    /// Serve routes under `prefix` instead of `/twirp`
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into().trim_end_matches('/').to_owned();
+        self
+    }
+
+    /// Let browsers call the server from `origins`, such as `https://example.com`, or from any origin with `*`
+    pub fn with_allowed_origins(mut self, origins: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.cors.set_origins(origins);
+        self
+    }
+
+    /// Let browsers send `headers` from other origins, such as those carrying call metadata
+    pub fn with_allowed_headers(mut self, headers: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.cors.set_headers(headers);
+        self
+    }
+
+    /// Accept connections forever, serving each one on a new task
+    pub async fn serve(&self, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                // errors only affect the calls on this connection
+                let _ = server.serve_connection(stream).await;
+            });
+        }
+    }
+
+    /// Serve the HTTP/1.1 requests made over a connection
+    pub async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        &self,
+        stream: S,
+    ) -> Result<(), hyper::Error> {
+        let server = self.clone();
+        let service = service_fn(move |request| {
+            let server = server.clone();
+            async move { Ok::<_, Infallible>(server.handle(request).await) }
+        });
+        hyper::server::conn::Http::new()
+            .http1_only(true)
+            .serve_connection(stream, service)
+            .await
+    }
+
+    /// Answer one HTTP request, for use with an existing hyper server
+    pub async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let origin = request.headers().get(header::ORIGIN).cloned();
+        let mut response = if request.method() == Method::OPTIONS {
+            self.cors.preflight(request.headers(), REQUEST_HEADERS)
+        } else {
+            self.handle_call(request).await
+        };
+        self.cors.allow(origin, &[], &mut response);
+        response
+    }
+
+    async fn handle_call(&self, request: Request<Body>) -> Response<Body> {
+        if request.method() != Method::POST {
+            return error_response(BAD_ROUTE, "Twirp calls must be POST requests", &Metadata::new());
+        }
+        let content_type = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(content_type);
+        let content_type = match content_type {
+            Some(content_type) => content_type,
+            None => return error_response(BAD_ROUTE, "Unexpected Content-Type", &Metadata::new()),
+        };
+        let route = request
+            .uri()
+            .path()
+            .strip_prefix(self.prefix.as_str())
+            .and_then(parse_path)
+            .map(|(package, service, method)| (package.to_owned(), service.to_owned(), method.to_owned()));
+        let (package, service, method) = match route {
+            Some(route) => route,
+            None => return error_response(BAD_ROUTE, "No such route", &Metadata::new()),
+        };
+        let ctx = CallContext::from_metadata(metadata_from_headers(request.headers()));
+        let message = match crate::read_body(request.into_body(), DEFAULT_MAX_FRAME_LENGTH).await {
+            Ok(message) => message,
+            Err(e @ WireError::FrameTooLarge { .. }) => {
+                return error_response(super::code_to_str(nrpc::Code::ResourceExhausted), &e.to_string(), &Metadata::new())
+            }
+            Err(e) => return error_response(MALFORMED, &format!("Failed to read request body: {}", e), &Metadata::new()),
+        };
+        // no codec can decode a JSON message from anything but a JSON object
+        if content_type == JSON_CONTENT_TYPE
+            && serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&message).is_err()
+        {
+            return error_response(MALFORMED, "Request body is not a JSON object", &Metadata::new());
+        }
+
+        // methods of services which do not know their shapes fail below if they answer with a stream
+        if let Some(kind @ (MethodKind::ClientStreaming | MethodKind::ServerStreaming | MethodKind::BidiStreaming)) =
+            self.registry.method_kind(&package, &service, &method).await
+        {
+            let message = format!("{} is a {} method, which Twirp cannot call", method, kind.as_str());
+            return error_response(BAD_ROUTE, &message, &Metadata::new());
+        }
+        let input = nrpc::OnceStream::once(Ok(message));
+        let output = self.registry.call(&package, &service, &method, &ctx, Box::new(input)).await;
+
+        let result = match output {
+            Ok(mut output) => match output.next().await {
+                Some(Ok(message)) if output.next().await.is_none() => Ok(message),
+                Some(Ok(_)) => Err(ServiceError::Status(Status::unimplemented(
+                    "Unary call answered with more than one message",
+                ))),
+                Some(Err(e)) => Err(e),
+                None => Err(ServiceError::Status(Status::unimplemented(
+                    "Unary call answered without a message",
+                ))),
+            },
+            Err(e) => Err(e),
+        };
+        let metadata = ctx.take_trailing_metadata();
+        let (status, response) = match result {
+            Ok(message) => {
+                let mut response = Response::new(Body::from(message));
+                let headers = response.headers_mut();
+                metadata_to_headers(&metadata, headers);
+                headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+                (Status::ok(), response)
+            }
+            Err(e) => {
+                let code = match &e {
+                    ServiceError::MethodNotFound | ServiceError::ServiceNotFound => BAD_ROUTE,
+                    ServiceError::Decode(_) => MALFORMED,
+                    e => super::code_to_str(e.code()),
+                };
+                let status = e.status();
+                (status.clone(), error_response(code, status.message(), &metadata))
+            }
+        };
+        ctx.complete(Trailer::new(status, metadata));
+        response
+    }
+}
+
+/// The content type of a Twirp call, if it is one
+fn content_type(content_type: &str) -> Option<&'static str> {
+    let mime = media_type(content_type);
+    if mime.eq_ignore_ascii_case(PROTOBUF_CONTENT_TYPE) {
+        Some(PROTOBUF_CONTENT_TYPE)
+    } else if mime.eq_ignore_ascii_case(JSON_CONTENT_TYPE) {
+        Some(JSON_CONTENT_TYPE)
+    } else {
+        None
+    }
+}
+
+fn error_response(code: &str, message: &str, metadata: &Metadata) -> Response<Body> {
+    let body = super::error_to_json(code, message, metadata).to_string();
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = super::http_status(code);
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(JSON_CONTENT_TYPE));
+    response
+}
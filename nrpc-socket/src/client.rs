@@ -0,0 +1,63 @@
+use nrpc::mux::MultiplexClientHandler;
+use nrpc::{CallContext, ServiceClientStream, ServiceError};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+/// Client handler which performs every call over one connection, many at once.
+///
+/// The connection is driven by a task spawned onto the current tokio runtime.
+/// It is closed once the handler and all of its clones, and every call made with them, are dropped.
+#[derive(Clone)]
+pub struct SocketClientHandler {
+    inner: MultiplexClientHandler,
+}
+
+impl SocketClientHandler {
+    /// Make calls over an established connection
+    pub fn new<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(stream: S) -> Self {
+        Self::with_window(stream, nrpc::mux::INITIAL_WINDOW)
+    }
+
+    /// Make calls over an established connection, letting the server send up to `window` bytes
+    /// of each response ahead of it being read
+    pub fn with_window<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(stream: S, window: u32) -> Self {
+        let (frames, writer) = super::split(stream);
+        let (inner, drive) = MultiplexClientHandler::with_window(writer, frames, window);
+        tokio::spawn(drive);
+        Self { inner }
+    }
+
+    /// Connect to a TCP server
+    pub async fn connect_tcp(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        Ok(Self::new(stream))
+    }
+
+    /// Connect to a Unix domain socket server
+    #[cfg(unix)]
+    pub async fn connect_unix(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Ok(Self::new(UnixStream::connect(path).await?))
+    }
+
+    /// Whether the server has closed the connection, so that calls will fail
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+}
+
+#[async_trait::async_trait]
+impl<'b> nrpc::ClientHandler<'b> for SocketClientHandler {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, bytes::Bytes>,
+    ) -> Result<ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        self.inner.call(package, service, method, ctx, input).await
+    }
+}
@@ -0,0 +1,23 @@
+//! TCP and Unix domain socket transport for nRPC.
+//!
+//! A connection carries [nrpc::wire] frames back to back, for many calls at once,
+//! as described by [nrpc::mux].
+
+mod client;
+mod server;
+
+pub use client::SocketClientHandler;
+pub use server::SocketServer;
+
+use futures::io::{ReadHalf, WriteHalf};
+use nrpc::wire::{FrameReader, FrameWriter};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+type Reader<S> = FrameReader<ReadHalf<Compat<S>>>;
+type Writer<S> = FrameWriter<WriteHalf<Compat<S>>>;
+
+fn split<S: AsyncRead + AsyncWrite>(stream: S) -> (Reader<S>, Writer<S>) {
+    let (read, write) = futures::AsyncReadExt::split(stream.compat());
+    (FrameReader::new(read), FrameWriter::new(write))
+}
@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use nrpc::wire::WireError;
+use nrpc::ServiceRegistry;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+/// Socket server which dispatches calls into a registry of server services
+#[derive(Clone)]
+pub struct SocketServer {
+    registry: Arc<ServiceRegistry<'static>>,
+    window: u32,
+}
+
+impl SocketServer {
+    pub fn new(registry: ServiceRegistry<'static>) -> Self {
+        Self {
+            registry: Arc::new(registry),
+            window: nrpc::mux::INITIAL_WINDOW,
+        }
+    }
+
+    /// Let clients send up to `window` bytes of each call's input ahead of it being read
+    pub fn with_window(mut self, window: u32) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Accept TCP connections forever, serving each one on a new task
+    pub async fn serve(&self, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            // a connection which cannot disable Nagle's algorithm is still served, and one the peer
+            // already reset fails on its own task, neither should stop the server
+            let _ = stream.set_nodelay(true);
+            let server = self.clone();
+            tokio::spawn(async move {
+                // errors only affect the calls on this connection
+                let _ = server.serve_connection(stream).await;
+            });
+        }
+    }
+
+    /// Accept Unix domain socket connections forever, serving each one on a new task
+    #[cfg(unix)]
+    pub async fn serve_unix(&self, listener: UnixListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                // errors only affect the calls on this connection
+                let _ = server.serve_connection(stream).await;
+            });
+        }
+    }
+
+    /// Serve the calls made over a connection, many at once.
+    ///
+    /// Returns once the client has closed the connection and every call has been answered.
+    /// Calls still running when the connection closes are cancelled.
+    pub async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        &self,
+        stream: S,
+    ) -> Result<(), WireError> {
+        let (frames, writer) = super::split(stream);
+        nrpc::mux::serve_with_window(&self.registry, writer, frames, self.window).await
+    }
+}
@@ -0,0 +1,73 @@
+use futures::StreamExt;
+use nrpc::mux::MultiplexClientHandler;
+use nrpc::wire::CallHeader;
+use nrpc::{CallContext, ServiceClientStream, ServiceError};
+
+/// Client handler which performs each call over a new websocket connection
+#[derive(Clone)]
+pub struct WebSocketClientHandler {
+    url: String,
+    window: u32,
+}
+
+impl WebSocketClientHandler {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            window: nrpc::mux::INITIAL_WINDOW,
+        }
+    }
+
+    /// Let the server send up to `window` bytes of each response ahead of it being read,
+    /// for connections opened by [Self::connect_multiplexed]
+    pub fn with_window(mut self, window: u32) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Open one websocket connection to make many calls over at once.
+    ///
+    /// The connection is driven by a task spawned onto the current tokio runtime.
+    pub async fn connect_multiplexed(&self) -> Result<MultiplexClientHandler, ServiceError> {
+        let (ws, _) = tokio_tungstenite::connect_async(self.url.as_str())
+            .await
+            .map_err(super::ws_error)?;
+        let (sink, stream) = ws.split();
+        let (handler, drive) = MultiplexClientHandler::with_window(
+            Box::pin(super::frame_sink(sink)),
+            Box::pin(super::frame_stream(stream)),
+            self.window,
+        );
+        tokio::spawn(drive);
+        Ok(handler)
+    }
+}
+
+#[async_trait::async_trait]
+impl<'b> nrpc::ClientHandler<'b> for WebSocketClientHandler {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, bytes::Bytes>,
+    ) -> Result<ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        let (ws, _) = tokio_tungstenite::connect_async(self.url.as_str())
+            .await
+            .map_err(super::ws_error)?;
+        let (sink, stream) = ws.split();
+        Ok(Box::new(nrpc::wire::client_call(
+            Box::pin(super::frame_sink(sink)),
+            Box::pin(super::frame_stream(stream)),
+            0,
+            CallHeader::new(package, service, method).with_metadata(ctx.request_metadata()),
+            ctx.clone(),
+            input,
+        )))
+    }
+}
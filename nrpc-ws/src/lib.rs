@@ -0,0 +1,35 @@
+//! WebSocket transport for nRPC.
+//!
+//! Each call uses its own websocket connection, unless the client multiplexes calls
+//! over one connection with [nrpc::mux].
+//! Every binary websocket message carries exactly one [nrpc::wire] frame.
+
+mod client;
+mod server;
+
+pub use client::WebSocketClientHandler;
+pub use server::WebSocketServer;
+
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use nrpc::wire::{Frame, WireError};
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+fn ws_error(e: WsError) -> WireError {
+    WireError::Io(std::io::Error::other(e))
+}
+
+fn frame_sink<S: Sink<Message, Error = WsError>>(sink: S) -> impl Sink<Frame, Error = WireError> {
+    sink.sink_map_err(ws_error)
+        .with(|frame: Frame| futures::future::ready(frame.to_bytes().map(|buf| Message::Binary(buf.into()))))
+}
+
+fn frame_stream<S: Stream<Item = Result<Message, WsError>>>(stream: S) -> impl Stream<Item = Result<Frame, WireError>> {
+    stream
+        .take_while(|msg| futures::future::ready(!matches!(msg, Ok(Message::Close(_)))))
+        .filter_map(|msg| futures::future::ready(match msg {
+            Ok(Message::Binary(buf)) => Some(Frame::from_bytes(buf.into())),
+            Ok(Message::Text(_)) => Some(Err(WireError::Malformed("text websocket message"))),
+            Ok(_) => None,
+            Err(e) => Some(Err(ws_error(e))),
+        }))
+}
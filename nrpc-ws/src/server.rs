@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use futures::future::{self, Either};
+use futures::StreamExt;
+use nrpc::wire::{Frame, FrameBody, WireError};
+use nrpc::{CallContext, ServiceError, ServiceRegistry};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio_tungstenite::WebSocketStream;
+
+/// Websocket server which dispatches calls into a registry of server services
+#[derive(Clone)]
+pub struct WebSocketServer {
+    registry: Arc<ServiceRegistry<'static>>,
+    window: u32,
+}
+
+impl WebSocketServer {
+    pub fn new(registry: ServiceRegistry<'static>) -> Self {
+        Self {
+            registry: Arc::new(registry),
+            window: nrpc::mux::INITIAL_WINDOW,
+        }
+    }
+
+    /// Let multiplexing clients send up to `window` bytes of each call's input ahead of it being read
+    pub fn with_window(mut self, window: u32) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Accept connections forever, serving each one on a new task
+    pub async fn serve(&self, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = self.clone();
+            tokio::spawn(async move {
+                // errors only affect the one call, the connection is closed either way
+                let _ = server.serve_connection(stream).await;
+            });
+        }
+    }
+
+    /// Perform the websocket handshake on a new connection and then serve the calls made over it
+    pub async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        &self,
+        stream: S,
+    ) -> Result<(), ServiceError> {
+        let ws = tokio_tungstenite::accept_async(stream)
+            .await
+            .map_err(super::ws_error)?;
+        self.serve_websocket(ws).await
+    }
+
+    /// Serve the calls made over an established websocket.
+    ///
+    /// A first call with stream id 0 is the only call made over the websocket,
+    /// otherwise the client is multiplexing calls as described by [nrpc::mux].
+    pub async fn serve_websocket<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        &self,
+        ws: WebSocketStream<S>,
+    ) -> Result<(), ServiceError> {
+        let (sink, stream) = ws.split();
+        let mut sink = Box::pin(super::frame_sink(sink));
+        let mut frames = Box::pin(super::frame_stream(stream));
+        let (stream_id, header) = match frames.next().await {
+            Some(Ok(frame)) => match frame.body {
+                FrameBody::Call(header) if frame.stream_id != 0 => {
+                    let first = Frame::call(frame.stream_id, header);
+                    let frames = futures::stream::once(future::ready(Ok(first))).chain(frames);
+                    return Ok(nrpc::mux::serve_with_window(&self.registry, sink, frames, self.window).await?);
+                }
+                FrameBody::Call(header) => (frame.stream_id, header),
+                _ => return Err(WireError::UnexpectedFrame.into()),
+            },
+            Some(Err(e)) => return Err(e.into()),
+            None => return Err(WireError::UnexpectedEof.into()),
+        };
+        let ctx = CallContext::from_metadata(header.metadata);
+        let (input, watch) = nrpc::wire::server_input(frames, &ctx);
+        let serve = async {
+            let output = self
+                .registry
+                .call(&header.package, &header.service, &header.method, &ctx, Box::new(input))
+                .await;
+            nrpc::wire::respond(&mut sink, stream_id, &ctx, output).await
+        };
+        // if the client goes away first, the call has been cancelled and there is no one to respond to
+        match future::select(Box::pin(serve), Box::pin(watch)).await {
+            Either::Left((result, _)) => result?,
+            Either::Right(((), _)) => return Ok(()),
+        }
+        futures::SinkExt::close(&mut sink).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,320 @@
+//! Per-method call metrics, exported through the `metrics` facade.
+//!
+//! Every metric is labelled with the called method as `method="package.Service/method"`:
+//!
+//! - `nrpc_{client,server}_started_total`: calls started
+//! - `nrpc_{client,server}_handled_total`: calls finished, also labelled with the status `code`
+//! - `nrpc_{client,server}_handling_seconds`: histogram of time from call start until the response ends
+//! - `nrpc_{client,server}_msg_{sent,received}_total`: stream messages
+//! - `nrpc_{client,server}_msg_{sent,received}_bytes`: histogram of stream message sizes
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::time::Instant;
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use metrics::{counter, histogram, Counter, Histogram};
+
+use super::{CallContext, ClientHandler, Code, MethodKind, ServerService, ServiceClientStream, ServiceError, ServiceServerStream};
+
+struct MetricNames {
+    started: &'static str,
+    handled: &'static str,
+    handling_seconds: &'static str,
+    msg_sent: &'static str,
+    msg_sent_bytes: &'static str,
+    msg_received: &'static str,
+    msg_received_bytes: &'static str,
+}
+
+const CLIENT_METRICS: MetricNames = MetricNames {
+    started: "nrpc_client_started_total",
+    handled: "nrpc_client_handled_total",
+    handling_seconds: "nrpc_client_handling_seconds",
+    msg_sent: "nrpc_client_msg_sent_total",
+    msg_sent_bytes: "nrpc_client_msg_sent_bytes",
+    msg_received: "nrpc_client_msg_received_total",
+    msg_received_bytes: "nrpc_client_msg_received_bytes",
+};
+
+const SERVER_METRICS: MetricNames = MetricNames {
+    started: "nrpc_server_started_total",
+    handled: "nrpc_server_handled_total",
+    handling_seconds: "nrpc_server_handling_seconds",
+    msg_sent: "nrpc_server_msg_sent_total",
+    msg_sent_bytes: "nrpc_server_msg_sent_bytes",
+    msg_received: "nrpc_server_msg_received_total",
+    msg_received_bytes: "nrpc_server_msg_received_bytes",
+};
+
+/// Message count and size metrics for one direction of a call
+struct MessageMetrics {
+    count: Counter,
+    bytes: Histogram,
+}
+
+impl MessageMetrics {
+    fn sent(names: &MetricNames, method: &str) -> Self {
+        Self {
+            count: counter!(names.msg_sent, "method" => method.to_owned()),
+            bytes: histogram!(names.msg_sent_bytes, "method" => method.to_owned()),
+        }
+    }
+
+    fn received(names: &MetricNames, method: &str) -> Self {
+        Self {
+            count: counter!(names.msg_received, "method" => method.to_owned()),
+            bytes: histogram!(names.msg_received_bytes, "method" => method.to_owned()),
+        }
+    }
+
+    fn record(&self, message: &Bytes) {
+        self.count.increment(1);
+        self.bytes.record(message.len() as f64);
+    }
+}
+
+/// Outcome and latency of one call, which counts as cancelled if dropped before it finishes
+struct CallMetrics {
+    names: &'static MetricNames,
+    method: String,
+    start: Instant,
+    finished: bool,
+}
+
+impl CallMetrics {
+    fn start(names: &'static MetricNames, method: String) -> Self {
+        counter!(names.started, "method" => method.clone()).increment(1);
+        Self {
+            names,
+            method,
+            start: Instant::now(),
+            finished: false,
+        }
+    }
+
+    fn finish(&mut self, code: Code) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        counter!(self.names.handled, "method" => self.method.clone(), "code" => format!("{:?}", code)).increment(1);
+        histogram!(self.names.handling_seconds, "method" => self.method.clone()).record(self.start.elapsed());
+    }
+}
+
+impl Drop for CallMetrics {
+    fn drop(&mut self) {
+        self.finish(Code::Cancelled);
+    }
+}
+
+/// Stream which records its messages, and finishes the call when it is the response
+struct MeteredStream<S> {
+    inner: S,
+    messages: MessageMetrics,
+    call: Option<CallMetrics>,
+}
+
+impl<S: Stream<Item = Result<Bytes, ServiceError>> + Unpin> Stream for MeteredStream<S> {
+    type Item = Result<Bytes, ServiceError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = futures::ready!(self.inner.poll_next_unpin(cx));
+        match &item {
+            Some(Ok(message)) => self.messages.record(message),
+            Some(Err(e)) => {
+                let code = e.code();
+                if let Some(call) = self.call.as_mut() {
+                    call.finish(code);
+                }
+            }
+            None => {
+                if let Some(call) = self.call.as_mut() {
+                    call.finish(Code::Ok);
+                }
+            }
+        }
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Client handler which records metrics for every call made through it
+pub struct MetricsClientHandler<H> {
+    inner: H,
+}
+
+impl<H> MetricsClientHandler<H> {
+    pub fn new(inner: H) -> Self {
+        Self { inner }
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+#[cfg(feature = "client-send")]
+#[async_trait::async_trait]
+impl<'b, H: ClientHandler<'b> + Sync> ClientHandler<'b> for MetricsClientHandler<H> {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, Bytes>,
+    ) -> Result<ServiceClientStream<'a, Bytes>, ServiceError> {
+        self.call_metered(package, service, method, ctx, input).await
+    }
+}
+
+#[cfg(not(feature = "client-send"))]
+#[async_trait::async_trait(?Send)]
+impl<'b, H: ClientHandler<'b>> ClientHandler<'b> for MetricsClientHandler<H> {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, Bytes>,
+    ) -> Result<ServiceClientStream<'a, Bytes>, ServiceError> {
+        self.call_metered(package, service, method, ctx, input).await
+    }
+}
+
+impl<H> MetricsClientHandler<H> {
+    async fn call_metered<'a: 'b, 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, Bytes>,
+    ) -> Result<ServiceClientStream<'a, Bytes>, ServiceError>
+    where
+        H: ClientHandler<'b>,
+    {
+        let descriptor = format!("{}.{}/{}", package, service, method);
+        let mut call = CallMetrics::start(&CLIENT_METRICS, descriptor);
+        let input = MeteredStream {
+            inner: input,
+            messages: MessageMetrics::sent(&CLIENT_METRICS, &call.method),
+            call: None,
+        };
+        match self.inner.call(package, service, method, ctx, Box::new(input)).await {
+            Ok(output) => Ok(Box::new(MeteredStream {
+                inner: output,
+                messages: MessageMetrics::received(&CLIENT_METRICS, &call.method),
+                call: Some(call),
+            })),
+            Err(e) => {
+                call.finish(e.code());
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Server service which records metrics for every call it handles
+#[derive(Clone)]
+pub struct MetricsService<S> {
+    inner: S,
+}
+
+impl<S> MetricsService<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[cfg(feature = "server-send")]
+#[async_trait::async_trait]
+impl<'b, S: ServerService<'b> + Send> ServerService<'b> for MetricsService<S> {
+    fn descriptor(&self) -> &'static str {
+        self.inner.descriptor()
+    }
+
+    fn method_kind(&self, method: &str) -> Option<MethodKind> {
+        self.inner.method_kind(method)
+    }
+
+    fn methods(&self) -> &'static [&'static str] {
+        self.inner.methods()
+    }
+
+    async fn call<'a: 'b>(
+        &mut self,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceServerStream<'a, Bytes>,
+    ) -> Result<ServiceServerStream<'a, Bytes>, ServiceError> {
+        self.call_metered(method, ctx, input).await
+    }
+}
+
+#[cfg(not(feature = "server-send"))]
+#[async_trait::async_trait(?Send)]
+impl<'b, S: ServerService<'b>> ServerService<'b> for MetricsService<S> {
+    fn descriptor(&self) -> &'static str {
+        self.inner.descriptor()
+    }
+
+    fn method_kind(&self, method: &str) -> Option<MethodKind> {
+        self.inner.method_kind(method)
+    }
+
+    fn methods(&self) -> &'static [&'static str] {
+        self.inner.methods()
+    }
+
+    async fn call<'a: 'b>(
+        &mut self,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceServerStream<'a, Bytes>,
+    ) -> Result<ServiceServerStream<'a, Bytes>, ServiceError> {
+        self.call_metered(method, ctx, input).await
+    }
+}
+
+impl<S> MetricsService<S> {
+    async fn call_metered<'a: 'b, 'b>(
+        &mut self,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceServerStream<'a, Bytes>,
+    ) -> Result<ServiceServerStream<'a, Bytes>, ServiceError>
+    where
+        S: ServerService<'b>,
+    {
+        let descriptor = format!("{}/{}", self.inner.descriptor(), method);
+        let mut call = CallMetrics::start(&SERVER_METRICS, descriptor);
+        let input = MeteredStream {
+            inner: input,
+            messages: MessageMetrics::received(&SERVER_METRICS, &call.method),
+            call: None,
+        };
+        match self.inner.call(method, ctx, Box::new(input)).await {
+            Ok(output) => Ok(Box::new(MeteredStream {
+                inner: output,
+                messages: MessageMetrics::sent(&SERVER_METRICS, &call.method),
+                call: Some(call),
+            })),
+            Err(e) => {
+                call.finish(e.code());
+                Err(e)
+            }
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use bytes::{Bytes, BytesMut};
+
+use super::{CallContext, ServiceError};
+
+/// How messages of type `M` are turned into bytes and back.
+///
+/// Generated clients and servers call through a codec, which is protobuf binary ([ProstCodec]) by default.
+/// The call's context is passed along, so a codec can pick an encoding per call, e.g. from its metadata.
+pub trait Codec<M> {
+    fn encode(&self, ctx: &CallContext, message: &M) -> Result<Bytes, ServiceError>;
+
+    fn decode(&self, ctx: &CallContext, buf: Bytes) -> Result<M, ServiceError>;
+}
+
+/// Protobuf binary encoding, using the `prost` generated message impls
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProstCodec;
+
+impl<M: prost::Message + Default> Codec<M> for ProstCodec {
+    fn encode(&self, _ctx: &CallContext, message: &M) -> Result<Bytes, ServiceError> {
+        let mut buf = BytesMut::with_capacity(message.encoded_len());
+        message.encode(&mut buf)?;
+        Ok(buf.freeze())
+    }
+
+    fn decode(&self, _ctx: &CallContext, buf: Bytes) -> Result<M, ServiceError> {
+        Ok(M::decode(buf)?)
+    }
+}
@@ -0,0 +1,312 @@
+//! Per-message compression of call streams.
+//!
+//! The client chooses the encoding of a call and names it in the `grpc-encoding` header,
+//! listing every encoding it can decompress in `grpc-accept-encoding`.
+//! nRPC calls have no response headers, so the server compresses its response with the same encoding,
+//! or fails the call as `Unimplemented` if it does not accept it.
+
+#[cfg(any(feature = "gzip", feature = "deflate"))]
+use std::io::{Read, Write};
+
+use bytes::Bytes;
+use futures::StreamExt;
+
+use super::{CallContext, ClientHandler, Metadata, MethodKind, ServerService, ServiceClientStream, ServiceError, ServiceServerStream, Status};
+
+/// Header naming the encoding of the call's messages
+pub const ENCODING_KEY: &str = "grpc-encoding";
+/// Header listing the encodings the sender can decompress
+pub const ACCEPT_ENCODING_KEY: &str = "grpc-accept-encoding";
+
+/// Message compression algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Compression {
+    /// Messages are sent as-is
+    #[default]
+    None,
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// zlib format, as gRPC uses for `deflate`
+    #[cfg(feature = "deflate")]
+    Deflate,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Every algorithm which was compiled in, except `None`
+    pub const SUPPORTED: &'static [Compression] = &[
+        #[cfg(feature = "gzip")]
+        Self::Gzip,
+        #[cfg(feature = "deflate")]
+        Self::Deflate,
+        #[cfg(feature = "zstd")]
+        Self::Zstd,
+    ];
+
+    /// Name of the encoding in call metadata
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::None => "identity",
+            #[cfg(feature = "gzip")]
+            Self::Gzip => "gzip",
+            #[cfg(feature = "deflate")]
+            Self::Deflate => "deflate",
+            #[cfg(feature = "zstd")]
+            Self::Zstd => "zstd",
+        }
+    }
+
+    /// Algorithm of an encoding name, if it was compiled in
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name == Self::None.name() {
+            return Some(Self::None);
+        }
+        Self::SUPPORTED.iter().copied().find(|compression| compression.name() == name)
+    }
+
+    pub fn compress(&self, message: &[u8]) -> Result<Bytes, ServiceError> {
+        let compressed: std::io::Result<Vec<u8>> = match self {
+            Self::None => Ok(message.to_vec()),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(message).and_then(|_| encoder.finish())
+            }
+            #[cfg(feature = "deflate")]
+            Self::Deflate => {
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(message).and_then(|_| encoder.finish())
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => zstd::bulk::compress(message, zstd::DEFAULT_COMPRESSION_LEVEL),
+        };
+        compressed
+            .map(Bytes::from)
+            .map_err(|e| Status::internal(format!("Failed to compress message: {}", e)).into())
+    }
+
+    pub fn decompress(&self, message: &[u8]) -> Result<Bytes, ServiceError> {
+        let decompressed: std::io::Result<Vec<u8>> = match self {
+            Self::None => Ok(message.to_vec()),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => read_all(flate2::read::GzDecoder::new(message)),
+            #[cfg(feature = "deflate")]
+            Self::Deflate => read_all(flate2::read::ZlibDecoder::new(message)),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => zstd::stream::decode_all(message),
+        };
+        decompressed
+            .map(Bytes::from)
+            .map_err(|e| Status::internal(format!("Failed to decompress message: {}", e)).into())
+    }
+}
+
+#[cfg(any(feature = "gzip", feature = "deflate"))]
+fn read_all(mut reader: impl Read) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Value of the `grpc-accept-encoding` header for this build
+fn accept_encoding() -> String {
+    let names: Vec<_> = Compression::SUPPORTED.iter().map(Compression::name).collect();
+    if names.is_empty() {
+        Compression::None.name().to_owned()
+    } else {
+        names.join(",")
+    }
+}
+
+/// Client handler which compresses the messages of every call with the chosen algorithm
+pub struct CompressionClientHandler<H> {
+    inner: H,
+    compression: Compression,
+}
+
+impl<H> CompressionClientHandler<H> {
+    pub fn new(inner: H, compression: Compression) -> Self {
+        Self { inner, compression }
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+#[cfg(feature = "client-send")]
+#[async_trait::async_trait]
+impl<'b, H: ClientHandler<'b> + Sync> ClientHandler<'b> for CompressionClientHandler<H> {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, Bytes>,
+    ) -> Result<ServiceClientStream<'a, Bytes>, ServiceError> {
+        self.call_compressed(package, service, method, ctx, input).await
+    }
+}
+
+#[cfg(not(feature = "client-send"))]
+#[async_trait::async_trait(?Send)]
+impl<'b, H: ClientHandler<'b>> ClientHandler<'b> for CompressionClientHandler<H> {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, Bytes>,
+    ) -> Result<ServiceClientStream<'a, Bytes>, ServiceError> {
+        self.call_compressed(package, service, method, ctx, input).await
+    }
+}
+
+impl<H> CompressionClientHandler<H> {
+    async fn call_compressed<'a: 'b, 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, Bytes>,
+    ) -> Result<ServiceClientStream<'a, Bytes>, ServiceError>
+    where
+        H: ClientHandler<'b>,
+    {
+        // clones share the trailer, so the caller still sees how the call completed
+        let mut ctx = ctx.clone();
+        ctx.metadata_mut().insert(ACCEPT_ENCODING_KEY, accept_encoding()).unwrap();
+        let compression = self.compression;
+        if compression == Compression::None {
+            ctx.metadata_mut().remove(ENCODING_KEY);
+            return self.inner.call(package, service, method, &ctx, input).await;
+        }
+        ctx.metadata_mut().insert(ENCODING_KEY, compression.name()).unwrap();
+        let input = input.map(move |item| item.and_then(|message| compression.compress(&message)));
+        let output = self.inner.call(package, service, method, &ctx, Box::new(input)).await?;
+        Ok(Box::new(output.map(move |item| item.and_then(|message| compression.decompress(&message)))))
+    }
+}
+
+/// Server service which decompresses requests and compresses responses with the encoding the client chose
+#[derive(Clone)]
+pub struct CompressionService<S> {
+    inner: S,
+    accepted: Vec<Compression>,
+}
+
+impl<S> CompressionService<S> {
+    /// Accept every algorithm which was compiled in
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            accepted: Compression::SUPPORTED.to_vec(),
+        }
+    }
+
+    /// Only accept calls compressed with these algorithms, or not compressed
+    pub fn with_accepted(mut self, accepted: impl IntoIterator<Item = Compression>) -> Self {
+        self.accepted = accepted.into_iter().collect();
+        self
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[cfg(feature = "server-send")]
+#[async_trait::async_trait]
+impl<'b, S: ServerService<'b> + Send> ServerService<'b> for CompressionService<S> {
+    fn descriptor(&self) -> &'static str {
+        self.inner.descriptor()
+    }
+
+    fn method_kind(&self, method: &str) -> Option<MethodKind> {
+        self.inner.method_kind(method)
+    }
+
+    fn methods(&self) -> &'static [&'static str] {
+        self.inner.methods()
+    }
+
+    async fn call<'a: 'b>(
+        &mut self,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceServerStream<'a, Bytes>,
+    ) -> Result<ServiceServerStream<'a, Bytes>, ServiceError> {
+        self.call_compressed(method, ctx, input).await
+    }
+}
+
+#[cfg(not(feature = "server-send"))]
+#[async_trait::async_trait(?Send)]
+impl<'b, S: ServerService<'b>> ServerService<'b> for CompressionService<S> {
+    fn descriptor(&self) -> &'static str {
+        self.inner.descriptor()
+    }
+
+    fn method_kind(&self, method: &str) -> Option<MethodKind> {
+        self.inner.method_kind(method)
+    }
+
+    fn methods(&self) -> &'static [&'static str] {
+        self.inner.methods()
+    }
+
+    async fn call<'a: 'b>(
+        &mut self,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceServerStream<'a, Bytes>,
+    ) -> Result<ServiceServerStream<'a, Bytes>, ServiceError> {
+        self.call_compressed(method, ctx, input).await
+    }
+}
+
+impl<S> CompressionService<S> {
+    async fn call_compressed<'a: 'b, 'b>(
+        &mut self,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceServerStream<'a, Bytes>,
+    ) -> Result<ServiceServerStream<'a, Bytes>, ServiceError>
+    where
+        S: ServerService<'b>,
+    {
+        let name = ctx.metadata().get(ENCODING_KEY).unwrap_or_else(|| Compression::None.name());
+        let accepted = Compression::from_name(name)
+            .filter(|compression| *compression == Compression::None || self.accepted.contains(compression));
+        let compression = match accepted {
+            Some(compression) => compression,
+            None => {
+                let accepted: Vec<_> = self.accepted.iter().map(Compression::name).collect();
+                let mut metadata = Metadata::new();
+                metadata.insert(ACCEPT_ENCODING_KEY, accepted.join(",")).unwrap();
+                ctx.set_trailing_metadata(metadata);
+                return Err(Status::unimplemented(format!("Message encoding \"{}\" is not supported", name)).into());
+            }
+        };
+        if compression == Compression::None {
+            return self.inner.call(method, ctx, input).await;
+        }
+        let input = input.map(move |item| item.and_then(|message| compression.decompress(&message)));
+        let output = self.inner.call(method, ctx, Box::new(input)).await?;
+        Ok(Box::new(output.map(move |item| item.and_then(|message| compression.compress(&message)))))
+    }
+}
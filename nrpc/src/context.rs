@@ -0,0 +1,371 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use futures::Stream;
+
+use super::deadline::{decode_timeout, encode_timeout, TIMEOUT_KEY};
+use super::{Metadata, MethodKind, ServiceError, Status, Trailer};
+
+/// Per-call information shared between the caller, the transport and the server.
+///
+/// Clones of a context share the call's trailer and cancellation, so a client can keep a clone
+/// and await the trailer once the response stream has been drained,
+/// and a server can notice when the client has given up on the call.
+#[derive(Debug, Clone, Default)]
+pub struct CallContext {
+    metadata: Metadata,
+    deadline: Option<Instant>,
+    method_kind: Option<MethodKind>,
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    trailer: Mutex<TrailerState>,
+    cancel: Mutex<CancelState>,
+}
+
+#[derive(Debug, Default)]
+struct CancelState {
+    cancelled: bool,
+    wakers: Vec<Waker>,
+}
+
+#[derive(Debug, Default)]
+struct TrailerState {
+    trailing_metadata: Metadata,
+    trailer: Option<Trailer>,
+    wakers: Vec<Waker>,
+}
+
+impl CallContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Context for a call received by a transport, taking the deadline from the `grpc-timeout` header
+    pub fn from_metadata(mut metadata: Metadata) -> Self {
+        let timeout = metadata.get(TIMEOUT_KEY).and_then(decode_timeout);
+        metadata.remove(TIMEOUT_KEY);
+        Self {
+            metadata,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            method_kind: None,
+            shared: Default::default(),
+        }
+    }
+
+    /// Context for another attempt at this call, with the same metadata, deadline and method kind
+    /// but its own trailer and cancellation
+    pub fn child(&self) -> Self {
+        Self {
+            metadata: self.metadata.clone(),
+            deadline: self.deadline,
+            method_kind: self.method_kind,
+            shared: Default::default(),
+        }
+    }
+
+    /// Call headers to send to the server, including the `grpc-timeout` header if there is a deadline
+    pub fn request_metadata(&self) -> Metadata {
+        let mut metadata = self.metadata.clone();
+        if let Some(remaining) = self.remaining() {
+            metadata.insert(TIMEOUT_KEY, encode_timeout(remaining)).unwrap();
+        }
+        metadata
+    }
+
+    /// Call headers sent by the client
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut Metadata {
+        &mut self.metadata
+    }
+
+    /// Time by which the call must complete
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Set the deadline to `timeout` from now
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.deadline = Some(Instant::now() + timeout);
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Time left until the deadline, if there is one
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    /// Shape of the called method, if known.
+    ///
+    /// Generated clients set this, for transports which send streaming calls differently.
+    pub fn method_kind(&self) -> Option<MethodKind> {
+        self.method_kind
+    }
+
+    pub fn set_method_kind(&mut self, kind: MethodKind) {
+        self.method_kind = Some(kind);
+    }
+
+    pub fn with_method_kind(mut self, kind: MethodKind) -> Self {
+        self.set_method_kind(kind);
+        self
+    }
+
+    /// Set the metadata the server will send in the trailer
+    pub fn set_trailing_metadata(&self, metadata: Metadata) {
+        self.shared.trailer.lock().unwrap().trailing_metadata = metadata;
+    }
+
+    /// Take the metadata to send in the trailer, leaving it empty
+    pub fn take_trailing_metadata(&self) -> Metadata {
+        std::mem::take(&mut self.shared.trailer.lock().unwrap().trailing_metadata)
+    }
+
+    /// Finish the call with a trailer, waking anything waiting for it.
+    ///
+    /// Only the first trailer is kept; returns false if the call was already complete.
+    pub fn complete(&self, trailer: Trailer) -> bool {
+        let mut state = self.shared.trailer.lock().unwrap();
+        if state.trailer.is_some() {
+            return false;
+        }
+        state.trailer = Some(trailer);
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+        true
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.shared.trailer.lock().unwrap().trailer.is_some()
+    }
+
+    /// The trailer, if the call is complete
+    pub fn try_trailer(&self) -> Option<Trailer> {
+        self.shared.trailer.lock().unwrap().trailer.clone()
+    }
+
+    /// Wait for the call to complete
+    pub fn trailer(&self) -> TrailerFuture {
+        TrailerFuture { ctx: self.clone() }
+    }
+
+    /// Cancel the call, waking anything waiting for cancellation.
+    ///
+    /// Returns false if the call was already cancelled.
+    pub fn cancel(&self) -> bool {
+        let mut state = self.shared.cancel.lock().unwrap();
+        if state.cancelled {
+            return false;
+        }
+        state.cancelled = true;
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+        true
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancel.lock().unwrap().cancelled
+    }
+
+    /// Check for cancellation, waking the current task once the call is cancelled
+    pub fn poll_cancelled(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.shared.cancel.lock().unwrap();
+        if state.cancelled {
+            Poll::Ready(())
+        } else {
+            if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                state.wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        }
+    }
+
+    /// Wait for the call to be cancelled
+    pub fn cancelled(&self) -> CancelledFuture {
+        CancelledFuture { ctx: self.clone() }
+    }
+
+    /// End a server's response stream with a `Cancelled` error once the call is cancelled
+    pub fn stop_on_cancel<S>(&self, stream: S) -> StopOnCancel<S> {
+        StopOnCancel {
+            inner: Some(stream),
+            ctx: self.clone(),
+        }
+    }
+
+    /// Guard which cancels the call if it is dropped before the call is complete
+    pub fn cancel_on_drop(&self) -> CancelOnDrop {
+        CancelOnDrop { ctx: self.clone() }
+    }
+
+    /// Complete the call when the response stream ends, with an error status if it yields an error
+    pub fn complete_on_end<S>(&self, stream: S) -> CompleteOnEnd<S> {
+        CompleteOnEnd {
+            inner: stream,
+            ctx: self.clone(),
+        }
+    }
+}
+
+pub struct TrailerFuture {
+    ctx: CallContext,
+}
+
+impl Future for TrailerFuture {
+    type Output = Trailer;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.ctx.shared.trailer.lock().unwrap();
+        if let Some(trailer) = &state.trailer {
+            Poll::Ready(trailer.clone())
+        } else {
+            if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                state.wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        }
+    }
+}
+
+/// Response stream which completes its call once it ends, see [CallContext::complete_on_end]
+pub struct CompleteOnEnd<S> {
+    inner: S,
+    ctx: CallContext,
+}
+
+impl<T, S: Stream<Item = Result<T, ServiceError>> + Unpin> Stream for CompleteOnEnd<S> {
+    type Item = Result<T, ServiceError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.ctx.is_complete() {
+            return Poll::Ready(None);
+        }
+        if self.ctx.poll_cancelled(cx).is_ready() {
+            let status = Status::cancelled("Call cancelled");
+            self.ctx.complete(Trailer::new(status.clone(), Metadata::new()));
+            return Poll::Ready(Some(Err(status.into())));
+        }
+        let item = futures::ready!(Pin::new(&mut self.inner).poll_next(cx));
+        match &item {
+            Some(Ok(_)) => {}
+            Some(Err(e)) => {
+                self.ctx.complete(Trailer::new(e.status(), self.ctx.take_trailing_metadata()));
+            }
+            None => {
+                self.ctx.complete(Trailer::ok(self.ctx.take_trailing_metadata()));
+            }
+        }
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S> Drop for CompleteOnEnd<S> {
+    fn drop(&mut self) {
+        if self.ctx.complete(Trailer::new(Status::cancelled("Response dropped before completion"), Metadata::new())) {
+            self.ctx.cancel();
+        }
+    }
+}
+
+pub struct CancelledFuture {
+    ctx: CallContext,
+}
+
+impl Future for CancelledFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.ctx.poll_cancelled(cx)
+    }
+}
+
+/// Response stream which stops once its call is cancelled, see [CallContext::stop_on_cancel]
+pub struct StopOnCancel<S> {
+    inner: Option<S>,
+    ctx: CallContext,
+}
+
+impl<T, S: Stream<Item = Result<T, ServiceError>> + Unpin> Stream for StopOnCancel<S> {
+    type Item = Result<T, ServiceError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.inner.is_none() {
+            return Poll::Ready(None);
+        }
+        if self.ctx.poll_cancelled(cx).is_ready() {
+            // drop the producer straight away, it may be holding resources
+            self.inner = None;
+            return Poll::Ready(Some(Err(Status::cancelled("Call cancelled").into())));
+        }
+        Pin::new(self.inner.as_mut().unwrap()).poll_next(cx)
+    }
+}
+
+/// Cancels its call when dropped, unless the call is already complete, see [CallContext::cancel_on_drop]
+pub struct CancelOnDrop {
+    ctx: CallContext,
+}
+
+impl CancelOnDrop {
+    /// Move the guard into a response stream, so the call is cancelled if the stream is dropped early
+    pub fn wrap<S>(self, stream: S) -> CancelOnDropStream<S> {
+        CancelOnDropStream {
+            inner: stream,
+            _guard: self,
+        }
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if !self.ctx.is_complete() {
+            self.ctx.cancel();
+        }
+    }
+}
+
+/// Stream with a [CancelOnDrop] guard attached
+pub struct CancelOnDropStream<S> {
+    inner: S,
+    _guard: CancelOnDrop,
+}
+
+impl<S: Stream + Unpin> Stream for CancelOnDropStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
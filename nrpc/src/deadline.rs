@@ -0,0 +1,186 @@
+use core::marker::Unpin;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::{self, Either};
+use futures::{FutureExt, Stream, StreamExt};
+
+use super::{CallContext, ClientHandler, Metadata, ServiceClientStream, ServiceError, Sleep, Status, Timer, Trailer};
+
+/// Metadata key which carries the time remaining until the call's deadline, as in gRPC
+pub const TIMEOUT_KEY: &str = "grpc-timeout";
+
+/// Encode a timeout as a `grpc-timeout` value, rounding up to the precision of the unit used
+pub fn encode_timeout(timeout: Duration) -> String {
+    const MAX: u128 = 99_999_999;
+    let nanos = timeout.as_nanos();
+    let units = [
+        ("n", 1u128),
+        ("u", 1_000),
+        ("m", 1_000_000),
+        ("S", 1_000_000_000),
+        ("M", 60_000_000_000),
+        ("H", 3_600_000_000_000),
+    ];
+    for (unit, per) in units {
+        let value = nanos.div_ceil(per);
+        if value <= MAX {
+            return format!("{}{}", value, unit);
+        }
+    }
+    format!("{}H", MAX)
+}
+
+/// Decode a `grpc-timeout` value
+pub fn decode_timeout(value: &str) -> Option<Duration> {
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (digits, unit) = value.split_at(value.len() - 1);
+    if !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let value: u64 = digits.parse().ok()?;
+    Some(match unit {
+        "n" => Duration::from_nanos(value),
+        "u" => Duration::from_micros(value),
+        "m" => Duration::from_millis(value),
+        "S" => Duration::from_secs(value),
+        "M" => Duration::from_secs(value * 60),
+        "H" => Duration::from_secs(value * 3600),
+        _ => return None,
+    })
+}
+
+/// Client handler which enforces call deadlines, failing expired calls with `DeadlineExceeded`.
+///
+/// Calls without a deadline in their context get the default timeout, if one is set.
+pub struct DeadlineClientHandler<H, T> {
+    inner: H,
+    timer: T,
+    default_timeout: Option<Duration>,
+}
+
+impl<H, T: Timer> DeadlineClientHandler<H, T> {
+    pub fn new(inner: H, timer: T) -> Self {
+        Self {
+            inner,
+            timer,
+            default_timeout: None,
+        }
+    }
+
+    /// Timeout for calls which do not have a deadline
+    pub fn with_default_timeout(mut self, timeout: Duration) -> Self {
+        self.default_timeout = Some(timeout);
+        self
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+/// Complete and cancel an expired call, so the server can stop working on it
+fn deadline_exceeded(ctx: &CallContext) -> ServiceError {
+    let status = Status::deadline_exceeded("Deadline exceeded");
+    ctx.complete(Trailer::new(status.clone(), Metadata::new()));
+    ctx.cancel();
+    status.into()
+}
+
+#[cfg(feature = "client-send")]
+#[async_trait::async_trait]
+impl<'b, H: ClientHandler<'b> + Sync, T: Timer + Sync> ClientHandler<'b> for DeadlineClientHandler<H, T> {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, bytes::Bytes>,
+    ) -> Result<ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        self.call_with_deadline(package, service, method, ctx, input).await
+    }
+}
+
+#[cfg(not(feature = "client-send"))]
+#[async_trait::async_trait(?Send)]
+impl<'b, H: ClientHandler<'b>, T: Timer> ClientHandler<'b> for DeadlineClientHandler<H, T> {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, bytes::Bytes>,
+    ) -> Result<ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        self.call_with_deadline(package, service, method, ctx, input).await
+    }
+}
+
+impl<H, T: Timer> DeadlineClientHandler<H, T> {
+    async fn call_with_deadline<'a: 'b, 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, bytes::Bytes>,
+    ) -> Result<ServiceClientStream<'a, bytes::Bytes>, ServiceError>
+    where
+        H: ClientHandler<'b>,
+    {
+        // clones share the trailer, so the caller still sees how the call completed
+        let mut ctx = ctx.clone();
+        if ctx.deadline().is_none() {
+            if let Some(timeout) = self.default_timeout {
+                ctx.set_timeout(timeout);
+            }
+        }
+        let deadline = match ctx.deadline() {
+            Some(deadline) => deadline,
+            None => return self.inner.call(package, service, method, &ctx, input).await,
+        };
+        let mut sleep = self.timer.sleep_until(deadline);
+        let call = self.inner.call(package, service, method, &ctx, input);
+        let output = match future::select(call, &mut sleep).await {
+            Either::Left((output, _)) => output?,
+            Either::Right(((), _)) => return Err(deadline_exceeded(&ctx)),
+        };
+        Ok(Box::new(DeadlineStream {
+            inner: Some(output),
+            sleep,
+            ctx,
+        }))
+    }
+}
+
+/// Response stream which fails once the call's deadline passes
+struct DeadlineStream<S> {
+    inner: Option<S>,
+    sleep: Sleep,
+    ctx: CallContext,
+}
+
+impl<S: Stream<Item = Result<bytes::Bytes, ServiceError>> + Unpin> Stream for DeadlineStream<S> {
+    type Item = Result<bytes::Bytes, ServiceError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let inner = match self.inner.as_mut() {
+            Some(inner) => inner,
+            None => return Poll::Ready(None),
+        };
+        if let Poll::Ready(item) = inner.poll_next_unpin(cx) {
+            return Poll::Ready(item);
+        }
+        if self.sleep.poll_unpin(cx).is_ready() {
+            // complete before dropping the inner stream, which would complete the call as cancelled
+            let error = deadline_exceeded(&self.ctx);
+            self.inner = None;
+            return Poll::Ready(Some(Err(error)));
+        }
+        Poll::Pending
+    }
+}
@@ -0,0 +1,227 @@
+//! Standard `google.rpc` error detail messages, for [Status](super::Status) details.
+//!
+//! These are compatible with `google/rpc/error_details.proto`,
+//! so they can be understood by other gRPC implementations.
+
+use std::collections::HashMap;
+
+const TYPE_URL_PREFIX: &str = "type.googleapis.com/";
+
+/// A message which can be packed into the details of a [Status](super::Status)
+pub trait ErrorDetail: prost::Message + Default {
+    /// Fully-qualified protobuf name of the message
+    const NAME: &'static str;
+
+    fn type_url() -> String {
+        format!("{}{}", TYPE_URL_PREFIX, Self::NAME)
+    }
+
+    /// Pack into an `Any` message
+    fn to_any(&self) -> prost_types::Any {
+        prost_types::Any {
+            type_url: Self::type_url(),
+            value: self.encode_to_vec(),
+        }
+    }
+
+    /// Unpack from an `Any` message, returning `None` if it holds a different message type
+    fn from_any(any: &prost_types::Any) -> Option<Result<Self, prost::DecodeError>> {
+        if any.type_url == Self::type_url() {
+            Some(Self::decode(any.value.as_slice()))
+        } else {
+            None
+        }
+    }
+}
+
+/// The reason of an error, with its domain and some structured context
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct ErrorInfo {
+    #[prost(string, tag = "1")]
+    pub reason: String,
+    #[prost(string, tag = "2")]
+    pub domain: String,
+    #[prost(map = "string, string", tag = "3")]
+    pub metadata: HashMap<String, String>,
+}
+
+impl ErrorDetail for ErrorInfo {
+    const NAME: &'static str = "google.rpc.ErrorInfo";
+}
+
+/// How long the client should wait before retrying
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RetryInfo {
+    #[prost(message, optional, tag = "1")]
+    pub retry_delay: Option<prost_types::Duration>,
+}
+
+impl RetryInfo {
+    pub fn new(retry_delay: std::time::Duration) -> Self {
+        Self {
+            retry_delay: prost_types::Duration::try_from(retry_delay).ok(),
+        }
+    }
+
+    /// The retry delay, if it is set and not negative
+    pub fn retry_delay(&self) -> Option<std::time::Duration> {
+        self.retry_delay
+            .clone()
+            .and_then(|d| std::time::Duration::try_from(d).ok())
+    }
+}
+
+impl ErrorDetail for RetryInfo {
+    const NAME: &'static str = "google.rpc.RetryInfo";
+}
+
+/// Debugging information from the server
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct DebugInfo {
+    #[prost(string, repeated, tag = "1")]
+    pub stack_entries: Vec<String>,
+    #[prost(string, tag = "2")]
+    pub detail: String,
+}
+
+impl ErrorDetail for DebugInfo {
+    const NAME: &'static str = "google.rpc.DebugInfo";
+}
+
+/// Which quotas were exceeded
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct QuotaFailure {
+    #[prost(message, repeated, tag = "1")]
+    pub violations: Vec<quota_failure::Violation>,
+}
+
+pub mod quota_failure {
+    #[derive(Clone, PartialEq, Eq, prost::Message)]
+    pub struct Violation {
+        #[prost(string, tag = "1")]
+        pub subject: String,
+        #[prost(string, tag = "2")]
+        pub description: String,
+    }
+}
+
+impl ErrorDetail for QuotaFailure {
+    const NAME: &'static str = "google.rpc.QuotaFailure";
+}
+
+/// Which preconditions of the request were not met
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct PreconditionFailure {
+    #[prost(message, repeated, tag = "1")]
+    pub violations: Vec<precondition_failure::Violation>,
+}
+
+pub mod precondition_failure {
+    #[derive(Clone, PartialEq, Eq, prost::Message)]
+    pub struct Violation {
+        #[prost(string, tag = "1")]
+        pub r#type: String,
+        #[prost(string, tag = "2")]
+        pub subject: String,
+        #[prost(string, tag = "3")]
+        pub description: String,
+    }
+}
+
+impl ErrorDetail for PreconditionFailure {
+    const NAME: &'static str = "google.rpc.PreconditionFailure";
+}
+
+/// Which fields of the request were invalid
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct BadRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub field_violations: Vec<bad_request::FieldViolation>,
+}
+
+pub mod bad_request {
+    #[derive(Clone, PartialEq, Eq, prost::Message)]
+    pub struct FieldViolation {
+        #[prost(string, tag = "1")]
+        pub field: String,
+        #[prost(string, tag = "2")]
+        pub description: String,
+    }
+
+    impl FieldViolation {
+        pub fn new(field: impl Into<String>, description: impl Into<String>) -> Self {
+            Self {
+                field: field.into(),
+                description: description.into(),
+            }
+        }
+    }
+}
+
+impl ErrorDetail for BadRequest {
+    const NAME: &'static str = "google.rpc.BadRequest";
+}
+
+/// Identifies the request, for bug reports
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct RequestInfo {
+    #[prost(string, tag = "1")]
+    pub request_id: String,
+    #[prost(string, tag = "2")]
+    pub serving_data: String,
+}
+
+impl ErrorDetail for RequestInfo {
+    const NAME: &'static str = "google.rpc.RequestInfo";
+}
+
+/// The resource which was being accessed
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct ResourceInfo {
+    #[prost(string, tag = "1")]
+    pub resource_type: String,
+    #[prost(string, tag = "2")]
+    pub resource_name: String,
+    #[prost(string, tag = "3")]
+    pub owner: String,
+    #[prost(string, tag = "4")]
+    pub description: String,
+}
+
+impl ErrorDetail for ResourceInfo {
+    const NAME: &'static str = "google.rpc.ResourceInfo";
+}
+
+/// Links to documentation about the error
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct Help {
+    #[prost(message, repeated, tag = "1")]
+    pub links: Vec<help::Link>,
+}
+
+pub mod help {
+    #[derive(Clone, PartialEq, Eq, prost::Message)]
+    pub struct Link {
+        #[prost(string, tag = "1")]
+        pub description: String,
+        #[prost(string, tag = "2")]
+        pub url: String,
+    }
+}
+
+impl ErrorDetail for Help {
+    const NAME: &'static str = "google.rpc.Help";
+}
+
+/// An error message localized for the end user
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct LocalizedMessage {
+    #[prost(string, tag = "1")]
+    pub locale: String,
+    #[prost(string, tag = "2")]
+    pub message: String,
+}
+
+impl ErrorDetail for LocalizedMessage {
+    const NAME: &'static str = "google.rpc.LocalizedMessage";
+}
@@ -0,0 +1,317 @@
+use bytes::Bytes;
+
+use super::{CallContext, ClientHandler, MethodKind, ServerService, ServiceClientStream, ServiceError, ServiceServerStream};
+
+#[cfg(feature = "client-send")]
+type BoxedClientInterceptor<'b> = Box<dyn ClientInterceptor<'b> + Send + Sync + 'b>;
+
+#[cfg(not(feature = "client-send"))]
+type BoxedClientInterceptor<'b> = Box<dyn ClientInterceptor<'b> + 'b>;
+
+#[cfg(feature = "client-send")]
+type DynClientHandler<'b> = dyn ClientHandler<'b> + Sync + 'b;
+
+#[cfg(not(feature = "client-send"))]
+type DynClientHandler<'b> = dyn ClientHandler<'b> + 'b;
+
+#[cfg(feature = "server-send")]
+type BoxedServerInterceptor<'b> = Box<dyn ServerInterceptor<'b> + Send + Sync + 'b>;
+
+#[cfg(not(feature = "server-send"))]
+type BoxedServerInterceptor<'b> = Box<dyn ServerInterceptor<'b> + 'b>;
+
+#[cfg(feature = "server-send")]
+type DynServerService<'b> = dyn ServerService<'b> + Send + 'b;
+
+#[cfg(not(feature = "server-send"))]
+type DynServerService<'b> = dyn ServerService<'b> + 'b;
+
+/// A call made by a client, as seen by client interceptors
+pub struct ClientRequest<'a> {
+    pub package: String,
+    pub service: String,
+    pub method: String,
+    /// Clone of the caller's context, which shares its trailer and cancellation
+    pub ctx: CallContext,
+    pub input: ServiceClientStream<'a, Bytes>,
+}
+
+/// Logic to run around every call made through an [InterceptedClientHandler]
+#[cfg_attr(feature = "client-send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "client-send"), async_trait::async_trait(?Send))]
+pub trait ClientInterceptor<'b> {
+    /// Handle a call, usually by modifying the request and passing it on with `next.run(request)`.
+    ///
+    /// Returning without calling `next` short-circuits the call.
+    async fn intercept<'a: 'b>(
+        &self,
+        request: ClientRequest<'a>,
+        next: ClientNext<'_, 'b>,
+    ) -> Result<ServiceClientStream<'a, Bytes>, ServiceError>;
+}
+
+/// The rest of the client interceptor chain, ending with the wrapped client handler
+pub struct ClientNext<'n, 'b> {
+    interceptors: &'n [BoxedClientInterceptor<'b>],
+    handler: &'n DynClientHandler<'b>,
+}
+
+impl<'n, 'b> ClientNext<'n, 'b> {
+    pub async fn run<'a: 'b>(self, request: ClientRequest<'a>) -> Result<ServiceClientStream<'a, Bytes>, ServiceError> {
+        match self.interceptors.split_first() {
+            Some((first, rest)) => {
+                let next = ClientNext {
+                    interceptors: rest,
+                    handler: self.handler,
+                };
+                first.intercept(request, next).await
+            }
+            None => {
+                self.handler
+                    .call(&request.package, &request.service, &request.method, &request.ctx, request.input)
+                    .await
+            }
+        }
+    }
+}
+
+/// Client handler which passes every call through a chain of interceptors.
+///
+/// Interceptors run in the order they were added, so the first one sees the call first
+/// and the result last.
+pub struct InterceptedClientHandler<'b, H> {
+    inner: H,
+    interceptors: Vec<BoxedClientInterceptor<'b>>,
+}
+
+impl<'b, H> InterceptedClientHandler<'b, H> {
+    pub fn new(inner: H) -> Self {
+        Self {
+            inner,
+            interceptors: Vec::new(),
+        }
+    }
+
+    /// Add an interceptor to the end of the chain
+    #[cfg(feature = "client-send")]
+    pub fn with<I: ClientInterceptor<'b> + Send + Sync + 'b>(mut self, interceptor: I) -> Self {
+        self.interceptors.push(Box::new(interceptor));
+        self
+    }
+
+    /// Add an interceptor to the end of the chain
+    #[cfg(not(feature = "client-send"))]
+    pub fn with<I: ClientInterceptor<'b> + 'b>(mut self, interceptor: I) -> Self {
+        self.interceptors.push(Box::new(interceptor));
+        self
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+#[cfg(feature = "client-send")]
+#[async_trait::async_trait]
+impl<'b, H: ClientHandler<'b> + Sync + 'b> ClientHandler<'b> for InterceptedClientHandler<'b, H> {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, Bytes>,
+    ) -> Result<ServiceClientStream<'a, Bytes>, ServiceError> {
+        let next = ClientNext {
+            interceptors: &self.interceptors,
+            handler: &self.inner,
+        };
+        next.run(client_request(package, service, method, ctx, input)).await
+    }
+}
+
+#[cfg(not(feature = "client-send"))]
+#[async_trait::async_trait(?Send)]
+impl<'b, H: ClientHandler<'b> + 'b> ClientHandler<'b> for InterceptedClientHandler<'b, H> {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, Bytes>,
+    ) -> Result<ServiceClientStream<'a, Bytes>, ServiceError> {
+        let next = ClientNext {
+            interceptors: &self.interceptors,
+            handler: &self.inner,
+        };
+        next.run(client_request(package, service, method, ctx, input)).await
+    }
+}
+
+fn client_request<'a>(
+    package: &str,
+    service: &str,
+    method: &str,
+    ctx: &CallContext,
+    input: ServiceClientStream<'a, Bytes>,
+) -> ClientRequest<'a> {
+    ClientRequest {
+        package: package.to_owned(),
+        service: service.to_owned(),
+        method: method.to_owned(),
+        ctx: ctx.clone(),
+        input,
+    }
+}
+
+/// A call received by a server service, as seen by server interceptors
+pub struct ServerRequest<'a> {
+    /// Descriptor of the service being called
+    pub service: &'static str,
+    pub method: String,
+    /// Clone of the call's context, which shares its trailer and cancellation
+    pub ctx: CallContext,
+    pub input: ServiceServerStream<'a, Bytes>,
+}
+
+/// Logic to run around every call handled by an [InterceptedService]
+#[cfg_attr(feature = "server-send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "server-send"), async_trait::async_trait(?Send))]
+pub trait ServerInterceptor<'b> {
+    /// Handle a call, usually by modifying the request and passing it on with `next.run(request)`.
+    ///
+    /// Returning without calling `next` short-circuits the call.
+    async fn intercept<'a: 'b>(
+        &self,
+        request: ServerRequest<'a>,
+        next: ServerNext<'_, 'b>,
+    ) -> Result<ServiceServerStream<'a, Bytes>, ServiceError>;
+}
+
+/// The rest of the server interceptor chain, ending with the wrapped server service
+pub struct ServerNext<'n, 'b> {
+    interceptors: &'n [BoxedServerInterceptor<'b>],
+    service: &'n mut DynServerService<'b>,
+}
+
+impl<'n, 'b> ServerNext<'n, 'b> {
+    pub async fn run<'a: 'b>(self, request: ServerRequest<'a>) -> Result<ServiceServerStream<'a, Bytes>, ServiceError> {
+        match self.interceptors.split_first() {
+            Some((first, rest)) => {
+                let next = ServerNext {
+                    interceptors: rest,
+                    service: self.service,
+                };
+                first.intercept(request, next).await
+            }
+            None => self.service.call(&request.method, &request.ctx, request.input).await,
+        }
+    }
+}
+
+/// Server service which passes every call through a chain of interceptors before the wrapped service.
+///
+/// Interceptors run in the order they were added, so the first one sees the call first
+/// and the result last.
+pub struct InterceptedService<'b, S> {
+    inner: S,
+    interceptors: Vec<BoxedServerInterceptor<'b>>,
+}
+
+impl<'b, S> InterceptedService<'b, S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            interceptors: Vec::new(),
+        }
+    }
+
+    /// Add an interceptor to the end of the chain
+    #[cfg(feature = "server-send")]
+    pub fn with<I: ServerInterceptor<'b> + Send + Sync + 'b>(mut self, interceptor: I) -> Self {
+        self.interceptors.push(Box::new(interceptor));
+        self
+    }
+
+    /// Add an interceptor to the end of the chain
+    #[cfg(not(feature = "server-send"))]
+    pub fn with<I: ServerInterceptor<'b> + 'b>(mut self, interceptor: I) -> Self {
+        self.interceptors.push(Box::new(interceptor));
+        self
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[cfg(feature = "server-send")]
+#[async_trait::async_trait]
+impl<'b, S: ServerService<'b> + Send + 'b> ServerService<'b> for InterceptedService<'b, S> {
+    fn descriptor(&self) -> &'static str {
+        self.inner.descriptor()
+    }
+
+    fn method_kind(&self, method: &str) -> Option<MethodKind> {
+        self.inner.method_kind(method)
+    }
+
+    fn methods(&self) -> &'static [&'static str] {
+        self.inner.methods()
+    }
+
+    async fn call<'a: 'b>(
+        &mut self,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceServerStream<'a, Bytes>,
+    ) -> Result<ServiceServerStream<'a, Bytes>, ServiceError> {
+        let request = ServerRequest {
+            service: self.inner.descriptor(),
+            method: method.to_owned(),
+            ctx: ctx.clone(),
+            input,
+        };
+        let next = ServerNext {
+            interceptors: &self.interceptors,
+            service: &mut self.inner,
+        };
+        next.run(request).await
+    }
+}
+
+#[cfg(not(feature = "server-send"))]
+#[async_trait::async_trait(?Send)]
+impl<'b, S: ServerService<'b> + 'b> ServerService<'b> for InterceptedService<'b, S> {
+    fn descriptor(&self) -> &'static str {
+        self.inner.descriptor()
+    }
+
+    fn method_kind(&self, method: &str) -> Option<MethodKind> {
+        self.inner.method_kind(method)
+    }
+
+    fn methods(&self) -> &'static [&'static str] {
+        self.inner.methods()
+    }
+
+    async fn call<'a: 'b>(
+        &mut self,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceServerStream<'a, Bytes>,
+    ) -> Result<ServiceServerStream<'a, Bytes>, ServiceError> {
+        let request = ServerRequest {
+            service: self.inner.descriptor(),
+            method: method.to_owned(),
+            ctx: ctx.clone(),
+            input,
+        };
+        let next = ServerNext {
+            interceptors: &self.interceptors,
+            service: &mut self.inner,
+        };
+        next.run(request).await
+    }
+}
@@ -0,0 +1,69 @@
+//! JSON encoding of messages, following the canonical proto3 JSON mapping.
+//!
+//! The serde impls come from `pbjson`, generated by `nrpc_build::Transpiler::generate_json`.
+//! A call is JSON encoded when its `content-type` header is `application/json`
+//! or ends in `+json`, like `application/grpc+json`.
+
+use bytes::Bytes;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{CallContext, Codec, ProstCodec, ServiceError, Status};
+
+/// Header naming the encoding of the call's messages
+pub const CONTENT_TYPE_KEY: &str = "content-type";
+/// Content type of JSON encoded calls
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// Whether a call's `content-type` header asks for JSON
+pub fn is_json(ctx: &CallContext) -> bool {
+    let content_type = match ctx.metadata().get(CONTENT_TYPE_KEY) {
+        Some(content_type) => content_type,
+        None => return false,
+    };
+    // ignore parameters like "; charset=utf-8"
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    mime.eq_ignore_ascii_case(JSON_CONTENT_TYPE) || mime.to_ascii_lowercase().ends_with("+json")
+}
+
+/// JSON encoding for every call, whatever its content type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct JsonCodec;
+
+impl<M: Serialize + DeserializeOwned> Codec<M> for JsonCodec {
+    fn encode(&self, _ctx: &CallContext, message: &M) -> Result<Bytes, ServiceError> {
+        serde_json::to_vec(message)
+            .map(Bytes::from)
+            .map_err(|e| Status::internal(format!("Failed to encode JSON message: {}", e)).into())
+    }
+
+    fn decode(&self, _ctx: &CallContext, buf: Bytes) -> Result<M, ServiceError> {
+        serde_json::from_slice(&buf)
+            .map_err(|e| Status::invalid_argument(format!("Invalid JSON message: {}", e)).into())
+    }
+}
+
+/// JSON encoding for calls with a JSON content type, protobuf binary for the rest.
+///
+/// Lets one server handle both kinds of clients,
+/// and lets a client pick the encoding of each call by setting its `content-type` header.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ContentTypeCodec;
+
+impl<M: prost::Message + Default + Serialize + DeserializeOwned> Codec<M> for ContentTypeCodec {
+    fn encode(&self, ctx: &CallContext, message: &M) -> Result<Bytes, ServiceError> {
+        if is_json(ctx) {
+            JsonCodec.encode(ctx, message)
+        } else {
+            ProstCodec.encode(ctx, message)
+        }
+    }
+
+    fn decode(&self, ctx: &CallContext, buf: Bytes) -> Result<M, ServiceError> {
+        if is_json(ctx) {
+            JsonCodec.decode(ctx, buf)
+        } else {
+            ProstCodec.decode(ctx, buf)
+        }
+    }
+}
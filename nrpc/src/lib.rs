@@ -1,13 +1,67 @@
+#[cfg(feature = "metrics")]
+mod call_metrics;
+mod codec;
+mod compression;
+mod context;
+mod deadline;
+pub mod error_details;
+mod interceptor;
+#[cfg(feature = "json")]
+mod json;
+// client and server streams are only interchangeable when their Send-ness matches
+#[cfg(any(
+    all(feature = "client-send", feature = "server-send"),
+    not(any(feature = "client-send", feature = "server-send"))
+))]
+mod loopback;
+mod metadata;
+pub mod mux;
+mod registry;
+mod retry;
 mod service;
+mod status;
 mod stream_utils;
+mod timer;
+mod trace;
+#[cfg(feature = "tower")]
+mod tower_adapters;
+pub mod wire;
 
-pub use service::{ClientHandler, ClientService, ServerService, ServiceError, ServiceClientStream, ServiceServerStream};
+#[cfg(any(
+    all(feature = "client-send", feature = "server-send"),
+    not(any(feature = "client-send", feature = "server-send"))
+))]
+pub use loopback::LoopbackClientHandler;
+#[cfg(feature = "metrics")]
+pub use call_metrics::{MetricsClientHandler, MetricsService};
+pub use codec::{Codec, ProstCodec};
+pub use compression::{Compression, CompressionClientHandler, CompressionService, ACCEPT_ENCODING_KEY, ENCODING_KEY};
+pub use context::{CallContext, CancelOnDrop, CancelOnDropStream, CancelledFuture, CompleteOnEnd, StopOnCancel, TrailerFuture};
+pub use deadline::{decode_timeout, encode_timeout, DeadlineClientHandler, TIMEOUT_KEY};
+pub use interceptor::{
+    ClientInterceptor, ClientNext, ClientRequest, InterceptedClientHandler, InterceptedService, ServerInterceptor,
+    ServerNext, ServerRequest,
+};
+#[cfg(feature = "json")]
+pub use json::{is_json, ContentTypeCodec, JsonCodec, CONTENT_TYPE_KEY, JSON_CONTENT_TYPE};
+pub use metadata::{Metadata, MetadataError, MetadataValue, BINARY_SUFFIX};
+pub use registry::ServiceRegistry;
+pub use retry::{RetryClientHandler, RetryPolicy, PREVIOUS_ATTEMPTS_KEY};
+pub use service::{ClientHandler, ClientService, MethodKind, ServerService, ServiceError, ServiceClientStream, ServiceServerStream, Trailer};
+pub use status::{Code, Status};
 
 pub use stream_utils::{EmptyStream, OnceStream, VecStream};
+#[cfg(feature = "tokio")]
+pub use timer::TokioTimer;
+pub use timer::{Sleep, Timer};
+pub use trace::{CallSpan, InstrumentedCall, TracedStream};
+#[cfg(feature = "tower")]
+pub use tower_adapters::{ClientTowerService, ServerTowerService, SyncServiceError, TowerClientHandler, TowerServerService};
 
 pub mod _helpers {
     pub use async_trait;
     pub use bytes;
     pub use prost;
+    pub use prost_types;
     pub use futures;
 }
@@ -0,0 +1,63 @@
+use super::{CallContext, ClientHandler, ServerService, ServiceClientStream, ServiceError, ServiceRegistry, Trailer};
+
+/// In-process client handler which calls server services directly, without any transport.
+///
+/// Useful for testing generated clients against generated servers.
+/// The call context is completed when the server's response stream ends.
+///
+/// A service added to the registry with [ServiceRegistry::register] handles one call at a time,
+/// so a call which waits for another call to the same service never finishes.
+/// Add services with [ServiceRegistry::register_cloned] to have their calls run concurrently.
+pub struct LoopbackClientHandler<'b> {
+    registry: ServiceRegistry<'b>,
+}
+
+impl<'b> LoopbackClientHandler<'b> {
+    pub fn new(registry: ServiceRegistry<'b>) -> Self {
+        Self { registry }
+    }
+
+    /// Loopback to a single server service, which handles one call at a time
+    #[cfg(feature = "server-send")]
+    pub fn from_service<S: ServerService<'b> + Send + 'b>(service: S) -> Self {
+        Self::new(ServiceRegistry::new().with(service))
+    }
+
+    /// Loopback to a single server service, which handles one call at a time
+    #[cfg(not(feature = "server-send"))]
+    pub fn from_service<S: ServerService<'b> + 'b>(service: S) -> Self {
+        Self::new(ServiceRegistry::new().with(service))
+    }
+
+    pub fn into_inner(self) -> ServiceRegistry<'b> {
+        self.registry
+    }
+}
+
+impl<'b> std::convert::From<ServiceRegistry<'b>> for LoopbackClientHandler<'b> {
+    fn from(value: ServiceRegistry<'b>) -> Self {
+        Self::new(value)
+    }
+}
+
+#[cfg_attr(feature = "client-send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "client-send"), async_trait::async_trait(?Send))]
+impl<'b> ClientHandler<'b> for LoopbackClientHandler<'b> {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, bytes::Bytes>,
+    ) -> Result<ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        let output = self.registry.call(package, service, method, ctx, input).await;
+        match output {
+            Ok(stream) => Ok(Box::new(ctx.complete_on_end(stream))),
+            Err(e) => {
+                ctx.complete(Trailer::new(e.status(), ctx.take_trailing_metadata()));
+                Err(e)
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use bytes::Bytes;
+
+/// Suffix of keys which have binary values
+pub const BINARY_SUFFIX: &str = "-bin";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataValue {
+    Ascii(String),
+    Binary(Bytes),
+}
+
+impl MetadataValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Ascii(s) => Some(s),
+            Self::Binary(_) => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Ascii(s) => s.as_bytes(),
+            Self::Binary(b) => b,
+        }
+    }
+}
+
+/// Call headers, as key-value pairs.
+///
+/// Like gRPC metadata, keys are lowercase and keys ending in `-bin` have binary values
+/// while all other keys have printable ASCII values. A key may have multiple values.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    entries: Vec<(String, MetadataValue)>,
+}
+
+impl Metadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the ASCII value of a key, replacing any existing values
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) -> Result<(), MetadataError> {
+        let (key, value) = ascii_entry(key.into(), value.into())?;
+        self.remove(&key);
+        self.entries.push((key, value));
+        Ok(())
+    }
+
+    /// Add an ASCII value to a key, keeping any existing values
+    pub fn append(&mut self, key: impl Into<String>, value: impl Into<String>) -> Result<(), MetadataError> {
+        let entry = ascii_entry(key.into(), value.into())?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Set the binary value of a `-bin` key, replacing any existing values
+    pub fn insert_bin(&mut self, key: impl Into<String>, value: impl Into<Bytes>) -> Result<(), MetadataError> {
+        let (key, value) = binary_entry(key.into(), value.into())?;
+        self.remove(&key);
+        self.entries.push((key, value));
+        Ok(())
+    }
+
+    /// Add a binary value to a `-bin` key, keeping any existing values
+    pub fn append_bin(&mut self, key: impl Into<String>, value: impl Into<Bytes>) -> Result<(), MetadataError> {
+        let entry = binary_entry(key.into(), value.into())?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Add a value of either kind, as received from a transport
+    pub fn append_value(&mut self, key: impl Into<String>, value: MetadataValue) -> Result<(), MetadataError> {
+        let entry = match value {
+            MetadataValue::Ascii(s) => ascii_entry(key.into(), s)?,
+            MetadataValue::Binary(b) => binary_entry(key.into(), b)?,
+        };
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// First ASCII value of a key
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_value(key).and_then(MetadataValue::as_str)
+    }
+
+    /// First binary value of a `-bin` key
+    pub fn get_bin(&self, key: &str) -> Option<&Bytes> {
+        match self.get_value(key) {
+            Some(MetadataValue::Binary(b)) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn get_value(&self, key: &str) -> Option<&MetadataValue> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+
+    /// All values of a key, in the order they were added
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a MetadataValue> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get_value(key).is_some()
+    }
+
+    /// Remove all values of a key, returning whether there were any
+    pub fn remove(&mut self, key: &str) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+        len != self.entries.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &MetadataValue)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Add all entries of another map to this one
+    pub fn extend(&mut self, other: Metadata) {
+        self.entries.extend(other.entries);
+    }
+}
+
+fn validate_key(key: String) -> Result<String, MetadataError> {
+    if key.is_empty()
+        || !key
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_' || c == b'.')
+    {
+        return Err(MetadataError::InvalidKey(key));
+    }
+    Ok(key.to_ascii_lowercase())
+}
+
+fn ascii_entry(key: String, value: String) -> Result<(String, MetadataValue), MetadataError> {
+    let key = validate_key(key)?;
+    if key.ends_with(BINARY_SUFFIX) {
+        return Err(MetadataError::ExpectedBinary(key));
+    }
+    if !value.bytes().all(|c| (0x20..0x7f).contains(&c)) {
+        return Err(MetadataError::InvalidValue(key));
+    }
+    Ok((key, MetadataValue::Ascii(value)))
+}
+
+fn binary_entry(key: String, value: Bytes) -> Result<(String, MetadataValue), MetadataError> {
+    let key = validate_key(key)?;
+    if !key.ends_with(BINARY_SUFFIX) {
+        return Err(MetadataError::ExpectedAscii(key));
+    }
+    Ok((key, MetadataValue::Binary(value)))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataError {
+    InvalidKey(String),
+    InvalidValue(String),
+    ExpectedAscii(String),
+    ExpectedBinary(String),
+}
+
+impl std::fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidKey(key) => write!(f, "Invalid metadata key {:?}", key),
+            Self::InvalidValue(key) => write!(f, "Invalid ASCII metadata value for key {:?}", key),
+            Self::ExpectedAscii(key) => write!(f, "Binary metadata value for non-binary key {:?}", key),
+            Self::ExpectedBinary(key) => write!(f, "ASCII metadata value for binary key {:?}", key),
+        }
+    }
+}
+
+impl std::error::Error for MetadataError {}
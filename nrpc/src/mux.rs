@@ -0,0 +1,561 @@
+//! Many concurrent calls over one [wire](crate::wire) frame transport.
+//!
+//! Clients give each call its own stream id, starting from 1 and never 0, which is left for
+//! transports carrying a single call. Servers answer with frames of the same stream id.
+//! The frames of concurrent calls are interleaved, one whole frame at a time.
+//!
+//! Each direction of a call is closed separately: the client's end frame closes its input
+//! while the response carries on, and the server's trailer frame closes the call, after which
+//! the client stops sending input and either side drops any frames still arriving for it.
+//! A client which no longer wants the response sends an error frame to abort the call.
+//!
+//! Each side of a call only sends message frames while it has credit for them, so that a slow
+//! reader holds back the remote writer rather than buffering without limit.
+//! Both sides start with [INITIAL_WINDOW] bytes of credit, and may send a message whenever
+//! any credit is left. The receiving side grants more with window frames as its messages are read,
+//! keeping up to its configured window of credit outstanding, plus at most one message over.
+//! A peer which sends a message without credit for it breaks the transport, failing every call.
+//!
+//! Both ends are driven by a future which reads and writes the transport;
+//! run it on a task of its own, such as with `tokio::spawn`.
+
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use futures::channel::mpsc;
+use futures::stream::FuturesUnordered;
+use futures::{future, ready, Sink, SinkExt, Stream, StreamExt};
+
+use super::wire::{CallHeader, Frame, FrameBody, WireError, HEADER_LENGTH};
+use super::{CallContext, ServiceClientStream, ServiceError, ServiceRegistry, Status, Trailer};
+
+/// Credit in bytes which each side of a call starts with, and the default window
+pub const INITIAL_WINDOW: u32 = 64 * 1024;
+
+/// How many frames may be waiting to be written to the transport
+const WRITE_QUEUE_LENGTH: usize = 32;
+
+fn transport_closed() -> WireError {
+    WireError::Io(std::io::ErrorKind::BrokenPipe.into())
+}
+
+/// Credit used by a message frame
+fn message_cost(payload: &[u8]) -> i64 {
+    (HEADER_LENGTH + payload.len()) as i64
+}
+
+/// Received frames of a call, and the credit for sending frames of it.
+///
+/// Only message frames the peer had credit for are queued, so the queue is bounded by the window.
+struct StreamState {
+    sender: mpsc::UnboundedSender<Result<Frame, WireError>>,
+    credit: Arc<SendCredit>,
+    /// Credit the peer has left for sending message frames, shared with the call's [ReceiveWindow]
+    received_credit: Arc<AtomicI64>,
+    ended: bool,
+}
+
+impl StreamState {
+    fn new() -> (Self, mpsc::UnboundedReceiver<Result<Frame, WireError>>) {
+        let (sender, receiver) = mpsc::unbounded();
+        let state = Self {
+            sender,
+            credit: Arc::new(SendCredit {
+                state: Mutex::new((i64::from(INITIAL_WINDOW), None)),
+            }),
+            received_credit: Arc::new(AtomicI64::new(i64::from(INITIAL_WINDOW))),
+            ended: false,
+        };
+        (state, receiver)
+    }
+
+    /// Hand a received frame to the call, returning whether the call is finished.
+    ///
+    /// Fails if the peer broke the protocol, by sending without credit or after ending its side.
+    fn receive(&mut self, frame: Frame) -> Result<bool, WireError> {
+        match &frame.body {
+            FrameBody::Window(increment) => self.credit.grant(*increment),
+            FrameBody::Trailer(_) | FrameBody::Error(_) => {
+                let _ = self.sender.unbounded_send(Ok(frame));
+                return Ok(true);
+            }
+            FrameBody::Message(payload) => {
+                if self.ended {
+                    return Err(WireError::UnexpectedFrame);
+                }
+                // like the sending side, a message may be sent while any credit is left
+                if self.received_credit.fetch_sub(message_cost(payload), Ordering::SeqCst) <= 0 {
+                    return Err(WireError::WindowExceeded {
+                        stream_id: frame.stream_id,
+                    });
+                }
+                // the call may already have stopped reading
+                let _ = self.sender.unbounded_send(Ok(frame));
+            }
+            FrameBody::End if !self.ended => {
+                self.ended = true;
+                let _ = self.sender.unbounded_send(Ok(frame));
+            }
+            FrameBody::End | FrameBody::Call(_) => return Err(WireError::UnexpectedFrame),
+        }
+        Ok(false)
+    }
+}
+
+/// Bytes of message frames which may still be sent for a call
+struct SendCredit {
+    state: Mutex<(i64, Option<Waker>)>,
+}
+
+impl SendCredit {
+    fn grant(&self, increment: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.0 += i64::from(increment);
+        if let Some(waker) = state.1.take() {
+            waker.wake();
+        }
+    }
+
+    fn poll_take(&self, cx: &mut Context<'_>, cost: i64) -> Poll<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.0 > 0 {
+            state.0 -= cost;
+            Poll::Ready(())
+        } else {
+            state.1 = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Credit granted to the sending side of a call, replenished as its messages are read
+struct ReceiveWindow {
+    stream_id: u32,
+    window: i64,
+    unacknowledged: i64,
+    granted: Arc<AtomicI64>,
+    frames: mpsc::Sender<Frame>,
+}
+
+impl ReceiveWindow {
+    /// The window, and the window frame granting any credit beyond the initial window.
+    ///
+    /// Credit is added to `granted` as it is granted.
+    fn new(stream_id: u32, window: u32, granted: Arc<AtomicI64>, frames: mpsc::Sender<Frame>) -> (Self, Option<Frame>) {
+        let extra = i64::from(window) - i64::from(INITIAL_WINDOW);
+        let receive_window = Self {
+            stream_id,
+            window: i64::from(window),
+            // a smaller window holds back credit until the initial window has been used
+            unacknowledged: extra.min(0),
+            granted,
+            frames,
+        };
+        let grant = (extra > 0).then(|| {
+            receive_window.granted.fetch_add(extra, Ordering::SeqCst);
+            Frame::window(stream_id, extra as u32)
+        });
+        (receive_window, grant)
+    }
+
+    /// Account for a frame having been read, granting credit back once enough has built up
+    fn read(&mut self, frame: &Frame) {
+        if let FrameBody::Message(payload) = &frame.body {
+            self.unacknowledged += message_cost(payload);
+            if self.unacknowledged >= (self.window / 2).max(1) {
+                let increment = self.unacknowledged.min(i64::from(u32::MAX)) as u32;
+                self.unacknowledged -= i64::from(increment);
+                self.granted.fetch_add(i64::from(increment), Ordering::SeqCst);
+                // a new sender always has room for one frame
+                let _ = self
+                    .frames
+                    .clone()
+                    .try_send(Frame::window(self.stream_id, increment));
+            }
+        }
+    }
+}
+
+/// Sink for the frames of one call, which holds back message frames until there is credit for them
+struct StreamSink {
+    inner: mpsc::Sender<Frame>,
+    credit: Arc<SendCredit>,
+    pending: VecDeque<Frame>,
+    /// Window frame to send right after the call frame
+    after_call: Option<Frame>,
+}
+
+impl StreamSink {
+    fn new(inner: mpsc::Sender<Frame>, credit: Arc<SendCredit>, after_call: Option<Frame>) -> Self {
+        Self {
+            inner,
+            credit,
+            pending: VecDeque::new(),
+            after_call,
+        }
+    }
+
+    fn poll_send_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), WireError>> {
+        while let Some(frame) = self.pending.front() {
+            ready!(self.inner.poll_ready(cx)).map_err(|_| transport_closed())?;
+            if let FrameBody::Message(payload) = &frame.body {
+                ready!(self.credit.poll_take(cx, message_cost(payload)));
+            }
+            let frame = self.pending.pop_front().unwrap();
+            self.inner.start_send(frame).map_err(|_| transport_closed())?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Sink<Frame> for StreamSink {
+    type Error = WireError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_send_pending(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Frame) -> Result<(), Self::Error> {
+        let is_call = matches!(item.body, FrameBody::Call(_));
+        self.pending.push_back(item);
+        if is_call {
+            if let Some(window) = self.after_call.take() {
+                self.pending.push_back(window);
+            }
+        }
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.poll_send_pending(cx))?;
+        self.inner.poll_flush_unpin(cx).map_err(|_| transport_closed())
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.poll_send_pending(cx))?;
+        self.inner.poll_close_unpin(cx).map_err(|_| transport_closed())
+    }
+}
+
+/// Client handler which performs every call over one shared frame transport
+#[derive(Clone)]
+pub struct MultiplexClientHandler {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    /// The calls in flight, or `None` once the transport is closed
+    streams: Mutex<Option<Streams>>,
+    frames: mpsc::Sender<Frame>,
+    window: u32,
+}
+
+struct Streams {
+    next_stream_id: u32,
+    states: HashMap<u32, StreamState>,
+}
+
+impl MultiplexClientHandler {
+    /// Make calls over a frame transport, with the default window for responses.
+    ///
+    /// The returned future drives the transport and must be polled for calls to make progress.
+    /// It resolves once the server closes the transport, or once the handler and all its clones
+    /// have been dropped along with every call made with them.
+    pub fn new<K, R>(sink: K, frames: R) -> (Self, impl Future<Output = Result<(), WireError>>)
+    where
+        K: Sink<Frame, Error = WireError> + Unpin,
+        R: Stream<Item = Result<Frame, WireError>> + Unpin,
+    {
+        Self::with_window(sink, frames, INITIAL_WINDOW)
+    }
+
+    /// Make calls over a frame transport, letting servers send up to `window` bytes
+    /// of each response ahead of it being read
+    pub fn with_window<K, R>(sink: K, frames: R, window: u32) -> (Self, impl Future<Output = Result<(), WireError>>)
+    where
+        K: Sink<Frame, Error = WireError> + Unpin,
+        R: Stream<Item = Result<Frame, WireError>> + Unpin,
+    {
+        let (tx, rx) = mpsc::channel(WRITE_QUEUE_LENGTH);
+        let shared = Arc::new(Shared {
+            streams: Mutex::new(Some(Streams {
+                next_stream_id: 1,
+                states: HashMap::new(),
+            })),
+            frames: tx,
+            window,
+        });
+        let write = rx.map(Ok).forward(sink);
+        let read = demultiplex(frames, Arc::downgrade(&shared));
+        let drive = async move {
+            let (written, read) = future::join(write, read).await;
+            written.and(read)
+        };
+        (Self { shared }, drive)
+    }
+
+    /// Whether the transport has been closed, so that calls will fail
+    pub fn is_closed(&self) -> bool {
+        self.shared.streams.lock().unwrap().is_none()
+    }
+}
+
+/// Route received frames to the calls they belong to
+async fn demultiplex<R>(mut frames: R, shared: std::sync::Weak<Shared>) -> Result<(), WireError>
+where
+    R: Stream<Item = Result<Frame, WireError>> + Unpin,
+{
+    let result = loop {
+        let frame = match frames.next().await {
+            Some(Ok(frame)) => frame,
+            Some(Err(e)) => break Err(e),
+            None => break Ok(()),
+        };
+        // no one is left to receive the frame
+        let shared = match shared.upgrade() {
+            Some(shared) => shared,
+            None => return Ok(()),
+        };
+        let mut streams = shared.streams.lock().unwrap();
+        let states = match streams.as_mut() {
+            Some(streams) => &mut streams.states,
+            None => return Ok(()),
+        };
+        let stream_id = frame.stream_id;
+        // responses to calls which have been dropped are discarded
+        if let Some(state) = states.get_mut(&stream_id) {
+            match state.receive(frame) {
+                Ok(true) => {
+                    states.remove(&stream_id);
+                }
+                Ok(false) => {}
+                Err(e) => break Err(e),
+            }
+        }
+    };
+    if let Some(shared) = shared.upgrade() {
+        // dropping the senders fails every call in flight
+        *shared.streams.lock().unwrap() = None;
+    }
+    result
+}
+
+#[cfg_attr(feature = "client-send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "client-send"), async_trait::async_trait(?Send))]
+impl<'b> super::ClientHandler<'b> for MultiplexClientHandler {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, bytes::Bytes>,
+    ) -> Result<ServiceClientStream<'a, bytes::Bytes>, ServiceError> {
+        let (state, rx) = StreamState::new();
+        let credit = state.credit.clone();
+        let received_credit = state.received_credit.clone();
+        let stream_id = match self.shared.streams.lock().unwrap().as_mut() {
+            Some(streams) => {
+                let stream_id = loop {
+                    let stream_id = streams.next_stream_id;
+                    streams.next_stream_id = stream_id.wrapping_add(1);
+                    // after wrapping around, skip 0 and the ids of long running calls
+                    if stream_id != 0 && !streams.states.contains_key(&stream_id) {
+                        break stream_id;
+                    }
+                };
+                streams.states.insert(stream_id, state);
+                stream_id
+            }
+            None => {
+                let status = Status::unavailable("Transport closed");
+                ctx.complete(Trailer::new(status.clone(), ctx.take_trailing_metadata()));
+                return Err(status.into());
+            }
+        };
+        let (window, grant) = ReceiveWindow::new(stream_id, self.shared.window, received_credit, self.shared.frames.clone());
+        let frames = CallFrames {
+            stream_id,
+            inner: rx,
+            window,
+            shared: self.shared.clone(),
+            finished: false,
+        };
+        let sink = StreamSink::new(self.shared.frames.clone(), credit, grant);
+        Ok(Box::new(super::wire::client_call(
+            sink,
+            frames,
+            stream_id,
+            CallHeader::new(package, service, method).with_metadata(ctx.request_metadata()),
+            ctx.clone(),
+            input,
+        )))
+    }
+}
+
+/// Response frames of one call, which aborts the call if dropped before the response is complete
+struct CallFrames {
+    stream_id: u32,
+    inner: mpsc::UnboundedReceiver<Result<Frame, WireError>>,
+    window: ReceiveWindow,
+    shared: Arc<Shared>,
+    finished: bool,
+}
+
+impl Stream for CallFrames {
+    type Item = Result<Frame, WireError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = ready!(self.inner.poll_next_unpin(cx));
+        match &item {
+            Some(Ok(frame @ Frame { body: FrameBody::Message(_), .. })) => self.window.read(frame),
+            _ => self.finished = true,
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl Drop for CallFrames {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if let Some(streams) = self.shared.streams.lock().unwrap().as_mut() {
+            streams.states.remove(&self.stream_id);
+        }
+        // a new sender always has room for one frame
+        let _ = self
+            .shared
+            .frames
+            .clone()
+            .try_send(Frame::error(self.stream_id, "Call cancelled"));
+    }
+}
+
+/// Serve the calls made over a frame transport from a registry of server services, many at once,
+/// with the default window for call input.
+///
+/// Resolves once the client has closed the transport and every call has been answered.
+/// Calls still running when the transport closes are cancelled.
+pub async fn serve<K, R>(
+    registry: &ServiceRegistry<'_>,
+    sink: K,
+    frames: R,
+) -> Result<(), WireError>
+where
+    K: Sink<Frame, Error = WireError> + Unpin,
+    R: Stream<Item = Result<Frame, WireError>> + Unpin,
+{
+    serve_with_window(registry, sink, frames, INITIAL_WINDOW).await
+}
+
+/// Like [serve], but letting clients send up to `window` bytes of each call's input
+/// ahead of it being read
+pub async fn serve_with_window<K, R>(
+    registry: &ServiceRegistry<'_>,
+    sink: K,
+    frames: R,
+    window: u32,
+) -> Result<(), WireError>
+where
+    K: Sink<Frame, Error = WireError> + Unpin,
+    R: Stream<Item = Result<Frame, WireError>> + Unpin,
+{
+    let (tx, rx) = mpsc::channel(WRITE_QUEUE_LENGTH);
+    let mut tx = Some(tx);
+    let mut frames = frames.fuse();
+    let mut write = rx.map(Ok).forward(sink);
+    let mut streams: HashMap<u32, StreamState> = HashMap::new();
+    let mut calls = FuturesUnordered::new();
+    let mut result = Ok(());
+    loop {
+        futures::select! {
+            frame = frames.next() => match frame {
+                Some(Ok(Frame { stream_id, body: FrameBody::Call(header) })) if !streams.contains_key(&stream_id) => {
+                    if let Some(tx) = &tx {
+                        let (state, frames_rx) = StreamState::new();
+                        let (window, grant) =
+                            ReceiveWindow::new(stream_id, window, state.received_credit.clone(), tx.clone());
+                        if let Some(grant) = grant {
+                            // a new sender always has room for one frame
+                            let _ = tx.clone().try_send(grant);
+                        }
+                        let input = InputFrames { inner: frames_rx, window };
+                        let sink = StreamSink::new(tx.clone(), state.credit.clone(), None);
+                        streams.insert(stream_id, state);
+                        calls.push(serve_call(registry, stream_id, header, input, sink));
+                    }
+                }
+                Some(Ok(frame)) => {
+                    // frames of calls which have already been answered are dropped
+                    let stream_id = frame.stream_id;
+                    if let Some(state) = streams.get_mut(&stream_id) {
+                        // a client which breaks the protocol has its calls dropped with the transport
+                        if state.receive(frame)? {
+                            // the client aborted the call, nothing more is received for it
+                            streams.remove(&stream_id);
+                        }
+                    }
+                }
+                Some(Err(e)) => result = Err(e),
+                None => {
+                    // the client is gone, so ending the input of every call cancels it
+                    streams.clear();
+                    tx = None;
+                }
+            },
+            stream_id = calls.select_next_some() => {
+                streams.remove(&stream_id);
+            }
+            written = write => {
+                if let Err(e) = written {
+                    result = Err(e);
+                }
+            }
+            complete => return result,
+        }
+    }
+}
+
+async fn serve_call(
+    registry: &ServiceRegistry<'_>,
+    stream_id: u32,
+    header: CallHeader,
+    frames: InputFrames,
+    mut sink: StreamSink,
+) -> u32 {
+    let ctx = CallContext::from_metadata(header.metadata);
+    let (input, watch) = super::wire::server_input(frames, &ctx);
+    let serve = async {
+        let output = registry
+            .call(&header.package, &header.service, &header.method, &ctx, Box::new(input))
+            .await;
+        // failing to respond cancels the call, there is nothing else to do
+        let _ = super::wire::respond(&mut sink, stream_id, &ctx, output).await;
+    };
+    // if the client aborts the call first, there is no one to respond to
+    future::select(Box::pin(serve), Box::pin(watch)).await;
+    stream_id
+}
+
+/// Received frames of a call's input, which grant credit back to the client as they are read
+struct InputFrames {
+    inner: mpsc::UnboundedReceiver<Result<Frame, WireError>>,
+    window: ReceiveWindow,
+}
+
+impl Stream for InputFrames {
+    type Item = Result<Frame, WireError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let item = ready!(self.inner.poll_next_unpin(cx));
+        if let Some(Ok(frame)) = &item {
+            self.window.read(frame);
+        }
+        Poll::Ready(item)
+    }
+}
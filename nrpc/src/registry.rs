@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use futures::lock::Mutex;
+
+use super::{CallContext, MethodKind, ServerService, ServiceError, ServiceServerStream};
+
+#[cfg(feature = "server-send")]
+type BoxedServerService<'b> = Box<dyn ServerService<'b> + Send + 'b>;
+
+#[cfg(not(feature = "server-send"))]
+type BoxedServerService<'b> = Box<dyn ServerService<'b> + 'b>;
+
+#[cfg(feature = "server-send")]
+type BoxedServiceFactory<'b> = Box<dyn ServiceFactory<'b> + Send + Sync + 'b>;
+
+#[cfg(not(feature = "server-send"))]
+type BoxedServiceFactory<'b> = Box<dyn ServiceFactory<'b> + 'b>;
+
+/// Source of a new instance of a service for every call
+trait ServiceFactory<'b> {
+    fn method_kind(&self, method: &str) -> Option<MethodKind>;
+
+    fn instance(&self) -> BoxedServerService<'b>;
+}
+
+#[cfg(feature = "server-send")]
+impl<'b, S: ServerService<'b> + Clone + Send + 'b> ServiceFactory<'b> for std::sync::Mutex<S> {
+    fn method_kind(&self, method: &str) -> Option<MethodKind> {
+        self.lock().unwrap().method_kind(method)
+    }
+
+    fn instance(&self) -> BoxedServerService<'b> {
+        Box::new(self.lock().unwrap().clone())
+    }
+}
+
+#[cfg(not(feature = "server-send"))]
+impl<'b, S: ServerService<'b> + Clone + 'b> ServiceFactory<'b> for std::sync::Mutex<S> {
+    fn method_kind(&self, method: &str) -> Option<MethodKind> {
+        self.lock().unwrap().method_kind(method)
+    }
+
+    fn instance(&self) -> BoxedServerService<'b> {
+        Box::new(self.lock().unwrap().clone())
+    }
+}
+
+enum Registered<'b> {
+    /// One instance, which serves one call at a time
+    Shared(Mutex<BoxedServerService<'b>>),
+    /// A clone of the service for every call, so calls run concurrently
+    Cloned(BoxedServiceFactory<'b>),
+}
+
+/// Collection of server services, dispatched to by their "package.Service" descriptor.
+///
+/// Calls into a service added with [`register`](Self::register) wait for its previous call to finish,
+/// including reading all of that call's input.
+/// Services added with [`register_cloned`](Self::register_cloned) are cloned for every call instead,
+/// so that slow calls don't hold up the rest.
+#[derive(Default)]
+pub struct ServiceRegistry<'b> {
+    services: HashMap<&'static str, Registered<'b>>,
+}
+
+impl<'b> ServiceRegistry<'b> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a service to the registry, replacing any service with the same descriptor
+    #[cfg(feature = "server-send")]
+    pub fn register<S: ServerService<'b> + Send + 'b>(&mut self, service: S) -> &mut Self {
+        self.services
+            .insert(service.descriptor(), Registered::Shared(Mutex::new(Box::new(service))));
+        self
+    }
+
+    /// Add a service to the registry, replacing any service with the same descriptor
+    #[cfg(not(feature = "server-send"))]
+    pub fn register<S: ServerService<'b> + 'b>(&mut self, service: S) -> &mut Self {
+        self.services
+            .insert(service.descriptor(), Registered::Shared(Mutex::new(Box::new(service))));
+        self
+    }
+
+    /// Add a service to the registry (builder-style)
+    #[cfg(feature = "server-send")]
+    pub fn with<S: ServerService<'b> + Send + 'b>(mut self, service: S) -> Self {
+        self.register(service);
+        self
+    }
+
+    /// Add a service to the registry (builder-style)
+    #[cfg(not(feature = "server-send"))]
+    pub fn with<S: ServerService<'b> + 'b>(mut self, service: S) -> Self {
+        self.register(service);
+        self
+    }
+
+    /// Add a service to the registry which is cloned for every call,
+    /// replacing any service with the same descriptor
+    #[cfg(feature = "server-send")]
+    pub fn register_cloned<S: ServerService<'b> + Clone + Send + 'b>(&mut self, service: S) -> &mut Self {
+        self.services.insert(
+            service.descriptor(),
+            Registered::Cloned(Box::new(std::sync::Mutex::new(service))),
+        );
+        self
+    }
+
+    /// Add a service to the registry which is cloned for every call,
+    /// replacing any service with the same descriptor
+    #[cfg(not(feature = "server-send"))]
+    pub fn register_cloned<S: ServerService<'b> + Clone + 'b>(&mut self, service: S) -> &mut Self {
+        self.services.insert(
+            service.descriptor(),
+            Registered::Cloned(Box::new(std::sync::Mutex::new(service))),
+        );
+        self
+    }
+
+    /// Add a service to the registry which is cloned for every call (builder-style)
+    #[cfg(feature = "server-send")]
+    pub fn with_cloned<S: ServerService<'b> + Clone + Send + 'b>(mut self, service: S) -> Self {
+        self.register_cloned(service);
+        self
+    }
+
+    /// Add a service to the registry which is cloned for every call (builder-style)
+    #[cfg(not(feature = "server-send"))]
+    pub fn with_cloned<S: ServerService<'b> + Clone + 'b>(mut self, service: S) -> Self {
+        self.register_cloned(service);
+        self
+    }
+
+    /// Remove a service from the registry by its descriptor
+    pub fn unregister(&mut self, descriptor: &str) -> bool {
+        self.services.remove(descriptor).is_some()
+    }
+
+    /// Descriptors of all registered services
+    pub fn descriptors(&self) -> Vec<&'static str> {
+        self.services.keys().copied().collect()
+    }
+
+    /// Whether a service is registered for the package and service name
+    pub fn contains(&self, package: &str, service: &str) -> bool {
+        self.services.contains_key(descriptor(package, service).as_str())
+    }
+
+    /// Shape of a method of a registered service, if the service knows it.
+    ///
+    /// Waits for the current call of a service added with [`register`](Self::register).
+    pub async fn method_kind(&self, package: &str, service: &str, method: &str) -> Option<MethodKind> {
+        match self.services.get(descriptor(package, service).as_str())? {
+            Registered::Shared(service_impl) => service_impl.lock().await.method_kind(method),
+            Registered::Cloned(factory) => factory.method_kind(method),
+        }
+    }
+
+    /// Call a method of a registered service
+    pub async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceServerStream<'a, bytes::Bytes>,
+    ) -> Result<ServiceServerStream<'a, bytes::Bytes>, ServiceError> {
+        match self.services.get(descriptor(package, service).as_str()) {
+            Some(Registered::Shared(service_impl)) => service_impl.lock().await.call(method, ctx, input).await,
+            Some(Registered::Cloned(factory)) => factory.instance().call(method, ctx, input).await,
+            None => Err(ServiceError::ServiceNotFound),
+        }
+    }
+}
+
+fn descriptor(package: &str, service: &str) -> String {
+    format!("{}.{}", package, service)
+}
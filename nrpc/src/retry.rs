@@ -0,0 +1,317 @@
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures::future::{self, Either};
+use futures::{Future, Stream, StreamExt};
+
+use super::{
+    CallContext, ClientHandler, Code, Metadata, MethodKind, OnceStream, ServiceClientStream, ServiceError, Status,
+    Timer, Trailer,
+};
+
+/// Header telling the server how many times the call has already been tried
+pub const PREVIOUS_ATTEMPTS_KEY: &str = "grpc-previous-rpc-attempts";
+
+/// When and how often to retry a failed call
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+    retryable_codes: Vec<Code>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+            jitter: 0.2,
+            retryable_codes: vec![Code::Unavailable],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Retry calls which fail as `Unavailable` up to 3 attempts, waiting 100ms, then 200ms
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Total number of attempts, including the first one
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Wait `initial` before the first retry, multiplying the wait for each retry after that up to `max`
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Randomly vary each wait by up to this fraction of it, so clients do not retry in lockstep
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Status codes of failures worth retrying
+    pub fn with_retryable_codes(mut self, codes: impl IntoIterator<Item = Code>) -> Self {
+        self.retryable_codes = codes.into_iter().collect();
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn is_retryable(&self, code: Code) -> bool {
+        self.retryable_codes.contains(&code)
+    }
+
+    /// Time to wait before retry number `retry`, starting from 1
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        let backoff = backoff.min(self.max_backoff.as_secs_f64());
+        let jitter = 1.0 + self.jitter * (2.0 * random_fraction() - 1.0);
+        Duration::try_from_secs_f64(backoff * jitter).unwrap_or(self.max_backoff)
+    }
+}
+
+/// Random number in `[0, 1)`, good enough to spread out retries
+fn random_fraction() -> f64 {
+    // every RandomState has new keys, so hashing nothing still gives a different number each time
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Client handler which retries calls that fail with a retryable status.
+///
+/// Only unary and server streaming calls are retried, since their single request message can be sent again.
+/// Calls are told apart by the [method kind](CallContext::method_kind) generated clients set,
+/// and calls of unknown kind are not retried.
+/// A call is not retried once a response message has been received,
+/// nor when the wait before retrying would pass the call's deadline.
+pub struct RetryClientHandler<H, T> {
+    inner: H,
+    timer: T,
+    policy: RetryPolicy,
+}
+
+impl<H, T: Timer> RetryClientHandler<H, T> {
+    pub fn new(inner: H, timer: T) -> Self {
+        Self {
+            inner,
+            timer,
+            policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: RetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    pub fn into_inner(self) -> H {
+        self.inner
+    }
+}
+
+#[cfg(feature = "client-send")]
+#[async_trait::async_trait]
+impl<'b, H: ClientHandler<'b> + Sync, T: Timer + Sync> ClientHandler<'b> for RetryClientHandler<H, T> {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, Bytes>,
+    ) -> Result<ServiceClientStream<'a, Bytes>, ServiceError> {
+        self.call_with_retries(package, service, method, ctx, input).await
+    }
+}
+
+#[cfg(not(feature = "client-send"))]
+#[async_trait::async_trait(?Send)]
+impl<'b, H: ClientHandler<'b>, T: Timer> ClientHandler<'b> for RetryClientHandler<H, T> {
+    async fn call<'a: 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, Bytes>,
+    ) -> Result<ServiceClientStream<'a, Bytes>, ServiceError> {
+        self.call_with_retries(package, service, method, ctx, input).await
+    }
+}
+
+impl<H, T: Timer> RetryClientHandler<H, T> {
+    async fn call_with_retries<'a: 'b, 'b>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        mut input: ServiceClientStream<'a, Bytes>,
+    ) -> Result<ServiceClientStream<'a, Bytes>, ServiceError>
+    where
+        H: ClientHandler<'b>,
+    {
+        if !matches!(ctx.method_kind(), Some(MethodKind::Unary | MethodKind::ServerStreaming)) {
+            // the request cannot be replayed
+            return self.inner.call(package, service, method, ctx, input).await;
+        }
+        let request = match input.next().await {
+            Some(request) => request?,
+            None => return Err(ServiceError::StreamLength { want: 1, got: 0 }),
+        };
+        let mut attempt = 1;
+        loop {
+            // each attempt has its own trailer, and only the one which is returned completes the caller's call
+            let mut attempt_ctx = ctx.child();
+            if attempt > 1 {
+                attempt_ctx
+                    .metadata_mut()
+                    .insert(PREVIOUS_ATTEMPTS_KEY, (attempt - 1).to_string())
+                    .unwrap();
+            }
+            let call = self.inner.call(
+                package,
+                service,
+                method,
+                &attempt_ctx,
+                Box::new(OnceStream::once(Ok(request.clone()))),
+            );
+            let error = match until_cancelled(ctx, &attempt_ctx, call).await {
+                Ok(Ok(mut output)) => match until_cancelled(ctx, &attempt_ctx, output.next()).await {
+                    Ok(Some(Err(e))) => e,
+                    Ok(first) => {
+                        return Ok(Box::new(RetryStream {
+                            first: Some(first),
+                            inner: Some(output),
+                            attempt_ctx,
+                            ctx: ctx.clone(),
+                        }))
+                    }
+                    Err(e) => e,
+                },
+                Ok(Err(e)) | Err(e) => e,
+            };
+            if !self.policy.is_retryable(error.code()) || attempt >= self.policy.max_attempts {
+                return Err(forward_error(ctx, &attempt_ctx, error));
+            }
+            let backoff = self.policy.backoff(attempt);
+            let wake = Instant::now() + backoff;
+            if ctx.deadline().map(|deadline| wake >= deadline).unwrap_or(false) {
+                return Err(forward_error(ctx, &attempt_ctx, error));
+            }
+            let sleep = self.timer.sleep_until(wake);
+            if let Either::Right(_) = future::select(sleep, ctx.cancelled()).await {
+                return Err(forward_error(ctx, &attempt_ctx, Status::cancelled("Call cancelled").into()));
+            }
+            attempt += 1;
+        }
+    }
+}
+
+/// Run part of an attempt, cancelling the attempt if the caller cancels the call
+async fn until_cancelled<F: Future + Unpin>(
+    ctx: &CallContext,
+    attempt_ctx: &CallContext,
+    future: F,
+) -> Result<F::Output, ServiceError> {
+    match future::select(future, ctx.cancelled()).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => {
+            attempt_ctx.cancel();
+            Err(Status::cancelled("Call cancelled").into())
+        }
+    }
+}
+
+/// Complete the caller's call with how the last attempt failed
+fn forward_error(ctx: &CallContext, attempt_ctx: &CallContext, error: ServiceError) -> ServiceError {
+    let trailer = attempt_ctx
+        .try_trailer()
+        .unwrap_or_else(|| Trailer::new(error.status(), Metadata::new()));
+    ctx.complete(trailer);
+    error
+}
+
+/// Response of the attempt which was returned, passing its trailer and the caller's cancellation between the two contexts
+struct RetryStream<S> {
+    first: Option<Option<Result<Bytes, ServiceError>>>,
+    inner: Option<S>,
+    attempt_ctx: CallContext,
+    ctx: CallContext,
+}
+
+impl<S> RetryStream<S> {
+    fn forward_trailer(&self) {
+        let trailer = self
+            .attempt_ctx
+            .try_trailer()
+            .unwrap_or_else(|| Trailer::new(Status::cancelled("Call cancelled"), Metadata::new()));
+        self.ctx.complete(trailer);
+    }
+}
+
+impl<S: Stream<Item = Result<Bytes, ServiceError>> + Unpin> Stream for RetryStream<S> {
+    type Item = Result<Bytes, ServiceError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.ctx.poll_cancelled(cx).is_ready() {
+            self.attempt_ctx.cancel();
+        }
+        let item = match self.first.take() {
+            Some(first) => first,
+            None => match self.inner.as_mut() {
+                Some(inner) => futures::ready!(inner.poll_next_unpin(cx)),
+                None => None,
+            },
+        };
+        if !matches!(item, Some(Ok(_))) {
+            let trailer = self.attempt_ctx.try_trailer().or_else(|| match &item {
+                Some(Err(e)) => Some(Trailer::new(e.status(), Metadata::new())),
+                _ => None,
+            });
+            if let Some(trailer) = trailer {
+                self.ctx.complete(trailer);
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl<S> Drop for RetryStream<S> {
+    fn drop(&mut self) {
+        if self.ctx.is_cancelled() {
+            self.attempt_ctx.cancel();
+        }
+        // dropping the attempt's response completes it, so do that first
+        self.inner = None;
+        self.forward_trailer();
+    }
+}
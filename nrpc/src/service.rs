@@ -1,6 +1,8 @@
 use futures::Stream;
 use core::marker::Unpin;
 
+use super::{CallContext, Code, Metadata, Status};
+
 #[cfg(feature = "client-send")]
 pub type ServiceClientStream<'a, T> = Box<dyn Stream<Item=Result<T, ServiceError>> + Unpin + Send + 'a>;
 
@@ -18,9 +20,23 @@ pub type ServiceServerStream<'a, T> = Box<dyn Stream<Item=Result<T, ServiceError
 pub trait ServerService<'b> {
     fn descriptor(&self) -> &'static str;
 
+    /// Shape of a method, for transports which cannot carry every kind of call.
+    ///
+    /// `None` if there is no such method, or the service does not know its methods' shapes.
+    fn method_kind(&self, _method: &str) -> Option<MethodKind> {
+        None
+    }
+
+    /// Names of the methods whose shapes [method_kind](Self::method_kind) knows,
+    /// for wrappers which look them up without the service at hand
+    fn methods(&self) -> &'static [&'static str] {
+        &[]
+    }
+
     async fn call<'a: 'b>(
         &mut self,
         method: &str,
+        ctx: &CallContext,
         input: ServiceServerStream<'a, bytes::Bytes>,
     ) -> Result<ServiceServerStream<'a, bytes::Bytes>, ServiceError>;
 }
@@ -33,6 +49,7 @@ pub trait ClientHandler<'b> {
         package: &str,
         service: &str,
         method: &str,
+        ctx: &CallContext,
         input: ServiceClientStream<'a, bytes::Bytes>,
     ) -> Result<ServiceClientStream<'a, bytes::Bytes>, ServiceError>;
 }
@@ -41,6 +58,80 @@ pub trait ClientService {
     fn descriptor(&self) -> &'static str;
 }
 
+/// Shape of a method, by whether its request and response are streams
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MethodKind {
+    /// One request message, one response message
+    Unary,
+    /// Many request messages, one response message
+    ClientStreaming,
+    /// One request message, many response messages
+    ServerStreaming,
+    /// Many request messages, many response messages
+    BidiStreaming,
+}
+
+impl MethodKind {
+    pub fn new(client_streaming: bool, server_streaming: bool) -> Self {
+        match (client_streaming, server_streaming) {
+            (false, false) => Self::Unary,
+            (true, false) => Self::ClientStreaming,
+            (false, true) => Self::ServerStreaming,
+            (true, true) => Self::BidiStreaming,
+        }
+    }
+
+    pub fn is_client_streaming(&self) -> bool {
+        matches!(self, Self::ClientStreaming | Self::BidiStreaming)
+    }
+
+    pub fn is_server_streaming(&self) -> bool {
+        matches!(self, Self::ServerStreaming | Self::BidiStreaming)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unary => "unary",
+            Self::ClientStreaming => "client_streaming",
+            Self::ServerStreaming => "server_streaming",
+            Self::BidiStreaming => "bidi_streaming",
+        }
+    }
+}
+
+/// Final status and metadata of a call, delivered after the last item of the response stream
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Trailer {
+    pub status: Status,
+    pub metadata: Metadata,
+}
+
+impl Trailer {
+    pub fn new(status: Status, metadata: Metadata) -> Self {
+        Self {
+            status,
+            metadata,
+        }
+    }
+
+    pub fn ok(metadata: Metadata) -> Self {
+        Self::new(Status::ok(), metadata)
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status.is_ok()
+    }
+
+    /// The call result described by this trailer
+    pub fn result(&self) -> Result<(), ServiceError> {
+        if self.status.is_ok() {
+            Ok(())
+        } else {
+            Err(ServiceError::Status(self.status.clone()))
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ServiceError {
     Encode(prost::EncodeError),
@@ -51,6 +142,32 @@ pub enum ServiceError {
     StreamLength {
         want: u64,
         got: u64,
+    },
+    Wire(super::wire::WireError),
+    Remote(String),
+    Status(Status),
+}
+
+impl ServiceError {
+    /// The status to report to the other side of the call for this error
+    pub fn status(&self) -> Status {
+        match self {
+            Self::Encode(en) => Status::internal(en.to_string()),
+            Self::Decode(de) => Status::invalid_argument(de.to_string()),
+            Self::MethodNotFound => Status::unimplemented("Method not found"),
+            Self::ServiceNotFound => Status::unimplemented("Service not found"),
+            Self::Method(e) => Status::unknown(e.to_string()),
+            Self::StreamLength{ .. } => Status::invalid_argument(self.to_string()),
+            Self::Wire(super::wire::WireError::Io(e)) => Status::unavailable(e.to_string()),
+            Self::Wire(super::wire::WireError::UnexpectedEof) => Status::unavailable(self.to_string()),
+            Self::Wire(e) => Status::internal(e.to_string()),
+            Self::Remote(e) => Status::unknown(e.clone()),
+            Self::Status(status) => status.clone(),
+        }
+    }
+
+    pub fn code(&self) -> Code {
+        self.status().code()
     }
 }
 
@@ -63,6 +180,9 @@ impl std::fmt::Display for ServiceError {
             Self::ServiceNotFound => write!(f, "Service not found error"),
             Self::Method(e) => write!(f, "Method error: {}", e),
             Self::StreamLength{ want, got } => write!(f, "Stream length error: wanted {}, got {}", want, got),
+            Self::Wire(e) => write!(f, "Wire error: {}", e),
+            Self::Remote(e) => write!(f, "Remote error: {}", e),
+            Self::Status(status) => write!(f, "Status error: {}", status),
         }
     }
 }
@@ -79,9 +199,30 @@ impl std::convert::From<prost::DecodeError> for ServiceError {
     }
 }
 
+/// Method errors which are a [Status] or a [ServiceError] are unwrapped,
+/// so server methods can choose the status reported to the client
 impl std::convert::From<Box<dyn std::error::Error + Send>> for ServiceError {
     fn from(value: Box<dyn std::error::Error + Send>) -> Self {
-        Self::Method(value)
+        let value = match value.downcast::<Status>() {
+            Ok(status) => return Self::Status(*status),
+            Err(value) => value,
+        };
+        match value.downcast::<ServiceError>() {
+            Ok(e) => *e,
+            Err(value) => Self::Method(value),
+        }
+    }
+}
+
+impl std::convert::From<Status> for ServiceError {
+    fn from(value: Status) -> Self {
+        Self::Status(value)
+    }
+}
+
+impl std::convert::From<super::wire::WireError> for ServiceError {
+    fn from(value: super::wire::WireError) -> Self {
+        Self::Wire(value)
     }
 }
 
@@ -0,0 +1,250 @@
+use super::error_details::ErrorDetail;
+
+/// Canonical gRPC status codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum Code {
+    Ok = 0,
+    Cancelled = 1,
+    Unknown = 2,
+    InvalidArgument = 3,
+    DeadlineExceeded = 4,
+    NotFound = 5,
+    AlreadyExists = 6,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    FailedPrecondition = 9,
+    Aborted = 10,
+    OutOfRange = 11,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+    DataLoss = 15,
+    Unauthenticated = 16,
+}
+
+impl Code {
+    /// Convert from the numeric code, with unrecognised values becoming `Unknown`
+    pub fn from_i32(value: i32) -> Self {
+        match value {
+            0 => Self::Ok,
+            1 => Self::Cancelled,
+            2 => Self::Unknown,
+            3 => Self::InvalidArgument,
+            4 => Self::DeadlineExceeded,
+            5 => Self::NotFound,
+            6 => Self::AlreadyExists,
+            7 => Self::PermissionDenied,
+            8 => Self::ResourceExhausted,
+            9 => Self::FailedPrecondition,
+            10 => Self::Aborted,
+            11 => Self::OutOfRange,
+            12 => Self::Unimplemented,
+            13 => Self::Internal,
+            14 => Self::Unavailable,
+            15 => Self::DataLoss,
+            16 => Self::Unauthenticated,
+            _ => Self::Unknown,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Ok => "The operation completed successfully",
+            Self::Cancelled => "The operation was cancelled",
+            Self::Unknown => "Unknown error",
+            Self::InvalidArgument => "Client specified an invalid argument",
+            Self::DeadlineExceeded => "Deadline expired before operation could complete",
+            Self::NotFound => "Some requested entity was not found",
+            Self::AlreadyExists => "Some entity that we attempted to create already exists",
+            Self::PermissionDenied => "The caller does not have permission to execute the specified operation",
+            Self::ResourceExhausted => "Some resource has been exhausted",
+            Self::FailedPrecondition => "The system is not in a state required for the operation's execution",
+            Self::Aborted => "The operation was aborted",
+            Self::OutOfRange => "Operation was attempted past the valid range",
+            Self::Unimplemented => "Operation is not implemented or not supported",
+            Self::Internal => "Internal error",
+            Self::Unavailable => "The service is currently unavailable",
+            Self::DataLoss => "Unrecoverable data loss or corruption",
+            Self::Unauthenticated => "The request does not have valid authentication credentials",
+        }
+    }
+}
+
+impl std::fmt::Display for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl std::convert::From<i32> for Code {
+    fn from(value: i32) -> Self {
+        Self::from_i32(value)
+    }
+}
+
+impl std::convert::From<Code> for i32 {
+    fn from(value: Code) -> Self {
+        value as i32
+    }
+}
+
+/// Outcome of a call: a status code, a developer-facing message and optional error details.
+///
+/// Encodes as a protobuf `google.rpc.Status` message, so it can be sent by transports as-is.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Status {
+    #[prost(int32, tag = "1")]
+    code: i32,
+    #[prost(string, tag = "2")]
+    message: String,
+    #[prost(message, repeated, tag = "3")]
+    details: Vec<prost_types::Any>,
+}
+
+impl Status {
+    pub fn new(code: Code, message: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            message: message.into(),
+            details: Vec::new(),
+        }
+    }
+
+    pub fn ok() -> Self {
+        Self::new(Code::Ok, "")
+    }
+
+    pub fn cancelled(message: impl Into<String>) -> Self {
+        Self::new(Code::Cancelled, message)
+    }
+
+    pub fn unknown(message: impl Into<String>) -> Self {
+        Self::new(Code::Unknown, message)
+    }
+
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        Self::new(Code::InvalidArgument, message)
+    }
+
+    pub fn deadline_exceeded(message: impl Into<String>) -> Self {
+        Self::new(Code::DeadlineExceeded, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(Code::NotFound, message)
+    }
+
+    pub fn already_exists(message: impl Into<String>) -> Self {
+        Self::new(Code::AlreadyExists, message)
+    }
+
+    pub fn permission_denied(message: impl Into<String>) -> Self {
+        Self::new(Code::PermissionDenied, message)
+    }
+
+    pub fn resource_exhausted(message: impl Into<String>) -> Self {
+        Self::new(Code::ResourceExhausted, message)
+    }
+
+    pub fn failed_precondition(message: impl Into<String>) -> Self {
+        Self::new(Code::FailedPrecondition, message)
+    }
+
+    pub fn aborted(message: impl Into<String>) -> Self {
+        Self::new(Code::Aborted, message)
+    }
+
+    pub fn out_of_range(message: impl Into<String>) -> Self {
+        Self::new(Code::OutOfRange, message)
+    }
+
+    pub fn unimplemented(message: impl Into<String>) -> Self {
+        Self::new(Code::Unimplemented, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(Code::Internal, message)
+    }
+
+    pub fn unavailable(message: impl Into<String>) -> Self {
+        Self::new(Code::Unavailable, message)
+    }
+
+    pub fn data_loss(message: impl Into<String>) -> Self {
+        Self::new(Code::DataLoss, message)
+    }
+
+    pub fn unauthenticated(message: impl Into<String>) -> Self {
+        Self::new(Code::Unauthenticated, message)
+    }
+
+    pub fn code(&self) -> Code {
+        Code::from_i32(self.code)
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.code() == Code::Ok
+    }
+
+    /// Error details, usually messages from [error_details](super::error_details)
+    pub fn details(&self) -> &[prost_types::Any] {
+        &self.details
+    }
+
+    pub fn with_details(mut self, details: Vec<prost_types::Any>) -> Self {
+        self.details = details;
+        self
+    }
+
+    /// Pack a message into the error details
+    pub fn add_detail<D: ErrorDetail>(&mut self, detail: &D) {
+        self.details.push(detail.to_any());
+    }
+
+    pub fn with_detail<D: ErrorDetail>(mut self, detail: &D) -> Self {
+        self.add_detail(detail);
+        self
+    }
+
+    /// First error detail of type `D` which decodes successfully
+    pub fn get_detail<D: ErrorDetail>(&self) -> Option<D> {
+        self.details
+            .iter()
+            .find_map(|any| D::from_any(any).and_then(Result::ok))
+    }
+
+    /// All error details of type `D` which decode successfully
+    pub fn get_details<D: ErrorDetail>(&self) -> Vec<D> {
+        self.details
+            .iter()
+            .filter_map(|any| D::from_any(any).and_then(Result::ok))
+            .collect()
+    }
+
+    /// Encode as a protobuf `google.rpc.Status` message
+    pub fn to_bytes(&self) -> bytes::Bytes {
+        prost::Message::encode_to_vec(self).into()
+    }
+
+    /// Decode from a protobuf `google.rpc.Status` message
+    pub fn from_bytes(buf: bytes::Bytes) -> Result<Self, prost::DecodeError> {
+        prost::Message::decode(buf)
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "{:?}", self.code())
+        } else {
+            write!(f, "{:?}: {}", self.code(), self.message)
+        }
+    }
+}
+
+impl std::error::Error for Status {}
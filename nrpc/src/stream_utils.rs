@@ -3,11 +3,17 @@ use futures::Stream;
 use core::{pin::Pin, task::{Context, Poll}};
 use core::marker::{PhantomData, Unpin};
 
-#[derive(Default, Clone, Copy)]
+#[derive(Clone, Copy)]
 pub struct EmptyStream<T> {
     _idc: PhantomData<T>,
 }
 
+impl <T> Default for EmptyStream<T> {
+    fn default() -> Self {
+        Self { _idc: PhantomData }
+    }
+}
+
 impl <T> Stream for EmptyStream<T> {
     type Item = T;
 
@@ -59,6 +65,7 @@ pub struct VecStream<T: Unpin> {
 }
 
 impl <T: Unpin> VecStream<T> {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_iter(iter: impl Iterator<Item=T>) -> Self {
         Self { items: iter.collect() }
     }
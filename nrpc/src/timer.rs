@@ -0,0 +1,33 @@
+use core::future::Future;
+use core::pin::Pin;
+use std::time::Instant;
+
+#[cfg(feature = "client-send")]
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+#[cfg(not(feature = "client-send"))]
+pub type Sleep = Pin<Box<dyn Future<Output = ()> + 'static>>;
+
+/// Source of timers, so deadlines can be enforced with any async runtime
+pub trait Timer {
+    /// Future which completes once `deadline` has passed
+    fn sleep_until(&self, deadline: Instant) -> Sleep;
+}
+
+impl<T: Timer + ?Sized> Timer for std::sync::Arc<T> {
+    fn sleep_until(&self, deadline: Instant) -> Sleep {
+        (**self).sleep_until(deadline)
+    }
+}
+
+/// Timer using the tokio runtime
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioTimer;
+
+#[cfg(feature = "tokio")]
+impl Timer for TokioTimer {
+    fn sleep_until(&self, deadline: Instant) -> Sleep {
+        Box::pin(tokio::time::sleep_until(deadline.into()))
+    }
+}
@@ -0,0 +1,315 @@
+//! Adapters between nRPC client handlers and server services and `tower::Service`,
+//! so tower middleware (timeouts, concurrency limits, load shedding...) can be used around calls.
+
+use core::task::{Context, Poll};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::future::{poll_fn, BoxFuture};
+use futures::lock::Mutex;
+
+use super::{
+    CallContext, ClientHandler, ClientRequest, MethodKind, ServerRequest, ServerService, ServiceClientStream,
+    ServiceError, ServiceServerStream,
+};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A [ServiceError] made `Sync`, as tower middleware expects of errors
+#[derive(Debug)]
+pub struct SyncServiceError(std::sync::Mutex<ServiceError>);
+
+impl SyncServiceError {
+    pub fn new(error: ServiceError) -> Self {
+        Self(std::sync::Mutex::new(error))
+    }
+
+    pub fn into_inner(self) -> ServiceError {
+        self.0.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl std::fmt::Display for SyncServiceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0.lock() {
+            Ok(e) => write!(f, "{}", e),
+            Err(e) => write!(f, "{}", e.get_ref()),
+        }
+    }
+}
+
+impl std::error::Error for SyncServiceError {}
+
+fn box_error(error: ServiceError) -> BoxError {
+    match error {
+        ServiceError::Status(status) => Box::new(status),
+        error => Box::new(SyncServiceError::new(error)),
+    }
+}
+
+/// Convert a middleware error, unwrapping errors which are already a [ServiceError] or a [Status](super::Status)
+fn service_error(error: impl Into<BoxError>) -> ServiceError {
+    match error.into().downcast::<SyncServiceError>() {
+        Ok(error) => error.into_inner(),
+        Err(error) => {
+            let error: Box<dyn std::error::Error + Send> = error;
+            ServiceError::from(error)
+        }
+    }
+}
+
+/// Call a tower service, boxing the future outside of any async fn
+/// so the compiler can see it is `Send` for the concrete request type
+fn boxed_call<R, S>(service: &mut S, request: R) -> BoxFuture<'static, Result<S::Response, S::Error>>
+where
+    S: tower::Service<R>,
+    S::Future: Send + 'static,
+{
+    Box::pin(service.call(request))
+}
+
+/// Client handler as a `tower::Service`
+pub struct ClientTowerService<H> {
+    inner: Arc<H>,
+}
+
+impl<H> ClientTowerService<H> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            inner: Arc::new(handler),
+        }
+    }
+}
+
+impl<H> Clone for ClientTowerService<H> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<H: ClientHandler<'static> + Send + Sync + 'static> tower::Service<ClientRequest<'static>> for ClientTowerService<H> {
+    type Response = ServiceClientStream<'static, Bytes>;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ClientRequest<'static>) -> Self::Future {
+        let handler = self.inner.clone();
+        Box::pin(async move {
+            handler
+                .call(&request.package, &request.service, &request.method, &request.ctx, request.input)
+                .await
+                .map_err(box_error)
+        })
+    }
+}
+
+/// `tower::Service` as a client handler, so generated clients can call through tower middleware.
+///
+/// The service is cloned for each call, like tower clients usually are.
+pub struct TowerClientHandler<S> {
+    inner: S,
+}
+
+impl<S> TowerClientHandler<S> {
+    pub fn new(service: S) -> Self {
+        Self { inner: service }
+    }
+
+    /// Wrap a client handler in a tower layer
+    pub fn layered<H, L: tower::Layer<ClientTowerService<H>, Service = S>>(handler: H, layer: L) -> Self {
+        Self::new(layer.layer(ClientTowerService::new(handler)))
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> ClientHandler<'static> for TowerClientHandler<S>
+where
+    S: tower::Service<ClientRequest<'static>, Response = ServiceClientStream<'static, Bytes>> + Clone + Send + Sync,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    async fn call<'a: 'static>(
+        &self,
+        package: &str,
+        service: &str,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceClientStream<'a, Bytes>,
+    ) -> Result<ServiceClientStream<'a, Bytes>, ServiceError> {
+        let mut inner = self.inner.clone();
+        poll_fn(|cx| inner.poll_ready(cx)).await.map_err(service_error)?;
+        let request = ClientRequest {
+            package: package.to_owned(),
+            service: service.to_owned(),
+            method: method.to_owned(),
+            ctx: ctx.clone(),
+            input,
+        };
+        boxed_call(&mut inner, request).await.map_err(service_error)
+    }
+}
+
+/// Shapes of a server service's methods, kept so they can be looked up while it is busy with a call
+#[derive(Clone, Default)]
+struct MethodKinds {
+    methods: &'static [&'static str],
+    kinds: Arc<HashMap<&'static str, MethodKind>>,
+}
+
+impl MethodKinds {
+    fn of<'b, S: ServerService<'b>>(service: &S) -> Self {
+        let methods = service.methods();
+        let kinds = methods
+            .iter()
+            .filter_map(|method| Some((*method, service.method_kind(method)?)))
+            .collect();
+        Self {
+            methods,
+            kinds: Arc::new(kinds),
+        }
+    }
+
+    fn get(&self, method: &str) -> Option<MethodKind> {
+        self.kinds.get(method).copied()
+    }
+}
+
+/// Server service as a `tower::Service`.
+///
+/// Calls are made one at a time, since server services need exclusive access.
+pub struct ServerTowerService<S> {
+    inner: Arc<Mutex<S>>,
+    kinds: MethodKinds,
+}
+
+impl<S> ServerTowerService<S> {
+    pub fn new<'b>(service: S) -> Self
+    where
+        S: ServerService<'b>,
+    {
+        Self {
+            kinds: MethodKinds::of(&service),
+            inner: Arc::new(Mutex::new(service)),
+        }
+    }
+
+    /// Shape of a method of the wrapped service, as listed by its [ServerService::methods]
+    pub fn method_kind(&self, method: &str) -> Option<MethodKind> {
+        self.kinds.get(method)
+    }
+}
+
+impl<S> Clone for ServerTowerService<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            kinds: self.kinds.clone(),
+        }
+    }
+}
+
+impl<S: ServerService<'static> + Send + 'static> tower::Service<ServerRequest<'static>> for ServerTowerService<S> {
+    type Response = ServiceServerStream<'static, Bytes>;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ServerRequest<'static>) -> Self::Future {
+        let service = self.inner.clone();
+        Box::pin(async move {
+            service
+                .lock()
+                .await
+                .call(&request.method, &request.ctx, request.input)
+                .await
+                .map_err(box_error)
+        })
+    }
+}
+
+/// `tower::Service` as a server service, so calls can be handled through tower middleware
+pub struct TowerServerService<S> {
+    inner: S,
+    descriptor: &'static str,
+    kinds: MethodKinds,
+}
+
+impl<S> TowerServerService<S> {
+    /// Serve the "package.Service" `descriptor` with a tower service, whose methods' shapes are not known
+    pub fn new(descriptor: &'static str, service: S) -> Self {
+        Self {
+            inner: service,
+            descriptor,
+            kinds: MethodKinds::default(),
+        }
+    }
+
+    /// Wrap a server service in a tower layer, keeping the service's methods' shapes
+    pub fn layered<T, L>(service: T, layer: L) -> Self
+    where
+        T: ServerService<'static>,
+        L: tower::Layer<ServerTowerService<T>, Service = S>,
+    {
+        let descriptor = service.descriptor();
+        let service = ServerTowerService::new(service);
+        let kinds = service.kinds.clone();
+        Self {
+            kinds,
+            ..Self::new(descriptor, layer.layer(service))
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+#[async_trait::async_trait]
+impl<S> ServerService<'static> for TowerServerService<S>
+where
+    S: tower::Service<ServerRequest<'static>, Response = ServiceServerStream<'static, Bytes>> + Send,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    fn descriptor(&self) -> &'static str {
+        self.descriptor
+    }
+
+    fn method_kind(&self, method: &str) -> Option<MethodKind> {
+        self.kinds.get(method)
+    }
+
+    fn methods(&self) -> &'static [&'static str] {
+        self.kinds.methods
+    }
+
+    async fn call<'a: 'static>(
+        &mut self,
+        method: &str,
+        ctx: &CallContext,
+        input: ServiceServerStream<'a, Bytes>,
+    ) -> Result<ServiceServerStream<'a, Bytes>, ServiceError> {
+        poll_fn(|cx| self.inner.poll_ready(cx)).await.map_err(service_error)?;
+        let request = ServerRequest {
+            service: self.descriptor,
+            method: method.to_owned(),
+            ctx: ctx.clone(),
+            input,
+        };
+        boxed_call(&mut self.inner, request).await.map_err(service_error)
+    }
+}
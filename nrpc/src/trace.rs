@@ -0,0 +1,209 @@
+//! Call instrumentation used by generated code, which only does something when the `tracing` feature is enabled
+
+use core::future::Future;
+use core::marker::Unpin;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures::Stream;
+
+use super::{MethodKind, ServiceError};
+
+/// Span covering one call
+#[derive(Debug, Clone)]
+pub struct CallSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    client: bool,
+}
+
+impl CallSpan {
+    /// Span of a call made by a client
+    #[allow(unused_variables)]
+    pub fn client(package: &str, service: &str, method: &str, kind: MethodKind) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                "nrpc.client",
+                rpc.package = package,
+                rpc.service = service,
+                rpc.method = method,
+                rpc.kind = kind.as_str(),
+                rpc.outcome = tracing::field::Empty,
+                rpc.code = tracing::field::Empty,
+            ),
+            #[cfg(feature = "tracing")]
+            client: true,
+        }
+    }
+
+    /// Span of a call handled by a server
+    #[allow(unused_variables)]
+    pub fn server(package: &str, service: &str, method: &str, kind: MethodKind) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!(
+                "nrpc.server",
+                rpc.package = package,
+                rpc.service = service,
+                rpc.method = method,
+                rpc.kind = kind.as_str(),
+                rpc.outcome = tracing::field::Empty,
+                rpc.code = tracing::field::Empty,
+            ),
+            #[cfg(feature = "tracing")]
+            client: false,
+        }
+    }
+
+    /// Run the future which starts the call within the span, recording the outcome if it fails
+    pub fn instrument<F>(&self, future: F) -> InstrumentedCall<F> {
+        InstrumentedCall {
+            inner: future,
+            span: self.clone(),
+        }
+    }
+
+    /// Record an event for each message sent.
+    ///
+    /// For servers this is the response, so the outcome of the call is recorded when it ends.
+    pub fn sent<S>(&self, stream: S) -> TracedStream<S> {
+        TracedStream {
+            inner: stream,
+            #[cfg(feature = "tracing")]
+            state: TraceState {
+                span: self.clone(),
+                sent: true,
+                response: !self.client,
+                count: 0,
+                finished: false,
+            },
+        }
+    }
+
+    /// Record an event for each message received.
+    ///
+    /// For clients this is the response, so the outcome of the call is recorded when it ends.
+    pub fn received<S>(&self, stream: S) -> TracedStream<S> {
+        TracedStream {
+            inner: stream,
+            #[cfg(feature = "tracing")]
+            state: TraceState {
+                span: self.clone(),
+                sent: false,
+                response: self.client,
+                count: 0,
+                finished: false,
+            },
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    fn record_outcome(&self, error: Option<&ServiceError>) {
+        match error {
+            None => {
+                self.span.record("rpc.outcome", "ok");
+                self.span.record("rpc.code", "Ok");
+            }
+            Some(e) => {
+                let code = e.code();
+                self.span.record("rpc.outcome", "error");
+                self.span.record("rpc.code", tracing::field::debug(code));
+                tracing::debug!(parent: &self.span, error = %e, "call failed");
+            }
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// Future which starts a call, see [CallSpan::instrument]
+    pub struct InstrumentedCall<F> {
+        #[pin]
+        inner: F,
+        // empty without the `tracing` feature
+        span: CallSpan,
+    }
+}
+
+impl<T, F: Future<Output = Result<T, ServiceError>>> Future for InstrumentedCall<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        #[cfg(feature = "tracing")]
+        let _entered = this.span.span.enter();
+        let result = futures::ready!(this.inner.poll(cx));
+        #[cfg(feature = "tracing")]
+        if let Err(e) = &result {
+            this.span.record_outcome(Some(e));
+        }
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(feature = "tracing")]
+struct TraceState {
+    span: CallSpan,
+    sent: bool,
+    response: bool,
+    count: u64,
+    finished: bool,
+}
+
+/// Stream of call messages which records an event for each one, see [CallSpan::sent] and [CallSpan::received]
+pub struct TracedStream<S> {
+    inner: S,
+    #[cfg(feature = "tracing")]
+    state: TraceState,
+}
+
+impl<S: Stream<Item = Result<Bytes, ServiceError>> + Unpin> Stream for TracedStream<S> {
+    type Item = Result<Bytes, ServiceError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        #[cfg(feature = "tracing")]
+        let span = self.state.span.span.clone();
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        let item = futures::ready!(Pin::new(&mut self.inner).poll_next(cx));
+        #[cfg(feature = "tracing")]
+        {
+            let state = &mut self.state;
+            match &item {
+                Some(Ok(message)) => {
+                    state.count += 1;
+                    if state.sent {
+                        tracing::trace!(message.index = state.count, message.size = message.len(), "message sent");
+                    } else {
+                        tracing::trace!(message.index = state.count, message.size = message.len(), "message received");
+                    }
+                }
+                Some(Err(e)) if state.response && !state.finished => {
+                    state.finished = true;
+                    state.span.record_outcome(Some(e));
+                }
+                None if state.response && !state.finished => {
+                    state.finished = true;
+                    state.span.record_outcome(None);
+                }
+                _ => {}
+            }
+        }
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Drop for TraceState {
+    fn drop(&mut self) {
+        if self.response && !self.finished {
+            self.span.span.record("rpc.outcome", "cancelled");
+        }
+    }
+}
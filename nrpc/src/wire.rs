@@ -0,0 +1,782 @@
+//! Standard framing for carrying nRPC calls over byte-stream transports.
+//!
+//! Every frame is a 9 byte header followed by the frame payload:
+//!
+//! | size    | field     | description                                   |
+//! |---------|-----------|-----------------------------------------------|
+//! | 1       | kind      | frame kind, see below                         |
+//! | 4       | stream id | big-endian id of the call this frame belongs to |
+//! | 4       | length    | big-endian length of the payload              |
+//! | length  | payload   | frame kind specific                           |
+//!
+//! Frame kinds:
+//!
+//! - `0x01` call: first frame of a call, sent by the client.
+//!   The payload is the package, service and method names, in that order,
+//!   each as a big-endian u16 length followed by that many bytes of UTF-8.
+//!   Then the call metadata: a big-endian u16 count of entries, each entry being
+//!   the key (encoded like the names) followed by a big-endian u32 length and that many bytes of value.
+//!   Keys ending in `-bin` have binary values, all other values are ASCII.
+//! - `0x02` message: one message of the call, the payload is the encoded message.
+//! - `0x03` end: the sender will not send any more messages for the call. Empty payload.
+//! - `0x04` error: the sender aborted the call, the payload is a UTF-8 description of the error.
+//!   No more frames will be sent for the call by either side.
+//! - `0x05` trailer: last frame of a response, sent by the server.
+//!   The payload is the status of the call, as a big-endian u32 length followed by
+//!   that many bytes of protobuf encoded `google.rpc.Status`,
+//!   then the trailing metadata (encoded like the call metadata).
+//! - `0x06` window: the sender may send that many more bytes of message frames for the call,
+//!   header included. The payload is a big-endian u32 increment. Only used by [crate::mux].
+//!
+//! A call is the client sending a call frame, then zero or more message frames, then an end frame.
+//! The server responds with zero or more message frames, then a trailer frame.
+//! Transports which only carry one call at a time should use stream id 0.
+//! [crate::mux] interleaves the frames of many calls over one transport.
+
+use core::future::Future;
+use core::marker::Unpin;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::channel::mpsc;
+use futures::io::{AsyncRead, AsyncWrite};
+use futures::{ready, Sink, SinkExt, Stream, StreamExt};
+
+use super::{CallContext, Metadata, MetadataValue, ServiceError, Status, Trailer};
+
+/// Size of the header which precedes every frame payload
+pub const HEADER_LENGTH: usize = 9;
+
+/// Default maximum frame payload size accepted by decoders (16 MiB)
+pub const DEFAULT_MAX_FRAME_LENGTH: u32 = 16 * 1024 * 1024;
+
+const KIND_CALL: u8 = 0x01;
+const KIND_MESSAGE: u8 = 0x02;
+const KIND_END: u8 = 0x03;
+const KIND_ERROR: u8 = 0x04;
+const KIND_TRAILER: u8 = 0x05;
+const KIND_WINDOW: u8 = 0x06;
+
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+const WRITE_HIGH_WATER: usize = 64 * 1024;
+
+/// Identification of the method a call is for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallHeader {
+    pub package: String,
+    pub service: String,
+    pub method: String,
+    pub metadata: Metadata,
+}
+
+impl CallHeader {
+    pub fn new(package: impl Into<String>, service: impl Into<String>, method: impl Into<String>) -> Self {
+        Self {
+            package: package.into(),
+            service: service.into(),
+            method: method.into(),
+            metadata: Metadata::new(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameBody {
+    Call(CallHeader),
+    Message(Bytes),
+    End,
+    Error(String),
+    Trailer(Trailer),
+    Window(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub stream_id: u32,
+    pub body: FrameBody,
+}
+
+impl Frame {
+    pub fn call(stream_id: u32, header: CallHeader) -> Self {
+        Self { stream_id, body: FrameBody::Call(header) }
+    }
+
+    pub fn message(stream_id: u32, payload: Bytes) -> Self {
+        Self { stream_id, body: FrameBody::Message(payload) }
+    }
+
+    pub fn end(stream_id: u32) -> Self {
+        Self { stream_id, body: FrameBody::End }
+    }
+
+    pub fn error(stream_id: u32, message: impl Into<String>) -> Self {
+        Self { stream_id, body: FrameBody::Error(message.into()) }
+    }
+
+    pub fn trailer(stream_id: u32, trailer: Trailer) -> Self {
+        Self { stream_id, body: FrameBody::Trailer(trailer) }
+    }
+
+    pub fn window(stream_id: u32, increment: u32) -> Self {
+        Self { stream_id, body: FrameBody::Window(increment) }
+    }
+
+    /// Append the encoded frame to the buffer
+    pub fn encode(&self, buf: &mut BytesMut) -> Result<(), WireError> {
+        let start = buf.len();
+        let kind = match &self.body {
+            FrameBody::Call(_) => KIND_CALL,
+            FrameBody::Message(_) => KIND_MESSAGE,
+            FrameBody::End => KIND_END,
+            FrameBody::Error(_) => KIND_ERROR,
+            FrameBody::Trailer(_) => KIND_TRAILER,
+            FrameBody::Window(_) => KIND_WINDOW,
+        };
+        buf.put_u8(kind);
+        buf.put_u32(self.stream_id);
+        // length is filled in once the payload is written
+        buf.put_u32(0);
+        let result = self.encode_payload(buf);
+        let payload_len = buf.len() - start - HEADER_LENGTH;
+        let length = result.and_then(|_| {
+            u32::try_from(payload_len).map_err(|_| WireError::FrameTooLarge {
+                length: payload_len,
+                max: u32::MAX,
+            })
+        });
+        match length {
+            Ok(length) => {
+                buf[start + 5..start + HEADER_LENGTH].copy_from_slice(&length.to_be_bytes());
+                Ok(())
+            }
+            Err(e) => {
+                buf.truncate(start);
+                Err(e)
+            }
+        }
+    }
+
+    fn encode_payload(&self, buf: &mut BytesMut) -> Result<(), WireError> {
+        match &self.body {
+            FrameBody::Call(header) => {
+                put_str(buf, &header.package)?;
+                put_str(buf, &header.service)?;
+                put_str(buf, &header.method)?;
+                put_metadata(buf, &header.metadata)?;
+            }
+            FrameBody::Message(payload) => buf.put_slice(payload),
+            FrameBody::End => {}
+            FrameBody::Error(message) => buf.put_slice(message.as_bytes()),
+            FrameBody::Trailer(trailer) => {
+                let status = trailer.status.to_bytes();
+                let len = u32::try_from(status.len()).map_err(|_| WireError::Malformed("status too large"))?;
+                buf.put_u32(len);
+                buf.put_slice(&status);
+                put_metadata(buf, &trailer.metadata)?;
+            }
+            FrameBody::Window(increment) => buf.put_u32(*increment),
+        }
+        Ok(())
+    }
+
+    /// Encode the frame into a new buffer, for message-oriented transports
+    pub fn to_bytes(&self) -> Result<Bytes, WireError> {
+        let mut buf = BytesMut::new();
+        self.encode(&mut buf)?;
+        Ok(buf.freeze())
+    }
+
+    /// Decode one frame from the start of the buffer.
+    ///
+    /// Returns `Ok(None)` when the buffer does not yet contain a whole frame.
+    pub fn decode(buf: &mut BytesMut, max_length: u32) -> Result<Option<Self>, WireError> {
+        if buf.len() < HEADER_LENGTH {
+            return Ok(None);
+        }
+        let mut header = &buf[..HEADER_LENGTH];
+        let kind = header.get_u8();
+        let stream_id = header.get_u32();
+        let length = header.get_u32();
+        if length > max_length {
+            return Err(WireError::FrameTooLarge {
+                length: length as usize,
+                max: max_length,
+            });
+        }
+        let frame_len = HEADER_LENGTH + length as usize;
+        if buf.len() < frame_len {
+            buf.reserve(frame_len - buf.len());
+            return Ok(None);
+        }
+        buf.advance(HEADER_LENGTH);
+        let payload = buf.split_to(length as usize).freeze();
+        Self::from_parts(kind, stream_id, payload).map(Some)
+    }
+
+    /// Decode a buffer containing exactly one frame, for message-oriented transports
+    pub fn from_bytes(mut buf: Bytes) -> Result<Self, WireError> {
+        if buf.len() < HEADER_LENGTH {
+            return Err(WireError::Malformed("frame shorter than header"));
+        }
+        let kind = buf.get_u8();
+        let stream_id = buf.get_u32();
+        let length = buf.get_u32();
+        if buf.len() != length as usize {
+            return Err(WireError::Malformed("frame length does not match payload"));
+        }
+        Self::from_parts(kind, stream_id, buf)
+    }
+
+    fn from_parts(kind: u8, stream_id: u32, mut payload: Bytes) -> Result<Self, WireError> {
+        let body = match kind {
+            KIND_CALL => {
+                let package = get_str(&mut payload)?;
+                let service = get_str(&mut payload)?;
+                let method = get_str(&mut payload)?;
+                let metadata = get_metadata(&mut payload)?;
+                if payload.has_remaining() {
+                    return Err(WireError::Malformed("trailing bytes in call frame"));
+                }
+                FrameBody::Call(CallHeader { package, service, method, metadata })
+            }
+            KIND_MESSAGE => FrameBody::Message(payload),
+            KIND_END => FrameBody::End,
+            KIND_ERROR => FrameBody::Error(
+                String::from_utf8(payload.to_vec()).map_err(|_| WireError::InvalidUtf8)?,
+            ),
+            KIND_TRAILER => {
+                if payload.remaining() < 4 {
+                    return Err(WireError::Malformed("truncated status"));
+                }
+                let len = payload.get_u32() as usize;
+                if payload.remaining() < len {
+                    return Err(WireError::Malformed("truncated status"));
+                }
+                let status = Status::from_bytes(payload.split_to(len))
+                    .map_err(|_| WireError::Malformed("invalid status"))?;
+                let metadata = get_metadata(&mut payload)?;
+                if payload.has_remaining() {
+                    return Err(WireError::Malformed("trailing bytes in trailer frame"));
+                }
+                FrameBody::Trailer(Trailer::new(status, metadata))
+            }
+            KIND_WINDOW => {
+                if payload.len() != 4 {
+                    return Err(WireError::Malformed("window frame payload is not 4 bytes"));
+                }
+                FrameBody::Window(payload.get_u32())
+            }
+            unknown => return Err(WireError::UnknownFrameKind(unknown)),
+        };
+        Ok(Self { stream_id, body })
+    }
+}
+
+fn put_str(buf: &mut BytesMut, value: &str) -> Result<(), WireError> {
+    let len = u16::try_from(value.len()).map_err(|_| WireError::Malformed("name longer than 65535 bytes"))?;
+    buf.put_u16(len);
+    buf.put_slice(value.as_bytes());
+    Ok(())
+}
+
+fn get_str(buf: &mut Bytes) -> Result<String, WireError> {
+    if buf.remaining() < 2 {
+        return Err(WireError::Malformed("truncated name"));
+    }
+    let len = buf.get_u16() as usize;
+    if buf.remaining() < len {
+        return Err(WireError::Malformed("truncated name"));
+    }
+    String::from_utf8(buf.split_to(len).to_vec()).map_err(|_| WireError::InvalidUtf8)
+}
+
+fn put_metadata(buf: &mut BytesMut, metadata: &Metadata) -> Result<(), WireError> {
+    let count = u16::try_from(metadata.len()).map_err(|_| WireError::Malformed("more than 65535 metadata entries"))?;
+    buf.put_u16(count);
+    for (key, value) in metadata.iter() {
+        put_str(buf, key)?;
+        let value = value.as_bytes();
+        let len = u32::try_from(value.len()).map_err(|_| WireError::Malformed("metadata value too large"))?;
+        buf.put_u32(len);
+        buf.put_slice(value);
+    }
+    Ok(())
+}
+
+fn get_metadata(buf: &mut Bytes) -> Result<Metadata, WireError> {
+    if buf.remaining() < 2 {
+        return Err(WireError::Malformed("truncated metadata"));
+    }
+    let count = buf.get_u16();
+    let mut metadata = Metadata::new();
+    for _ in 0..count {
+        let key = get_str(buf)?;
+        if buf.remaining() < 4 {
+            return Err(WireError::Malformed("truncated metadata"));
+        }
+        let len = buf.get_u32() as usize;
+        if buf.remaining() < len {
+            return Err(WireError::Malformed("truncated metadata"));
+        }
+        let value = buf.split_to(len);
+        let value = if key.ends_with(super::metadata::BINARY_SUFFIX) {
+            MetadataValue::Binary(value)
+        } else {
+            MetadataValue::Ascii(String::from_utf8(value.to_vec()).map_err(|_| WireError::InvalidUtf8)?)
+        };
+        metadata
+            .append_value(key, value)
+            .map_err(|_| WireError::Malformed("invalid metadata entry"))?;
+    }
+    Ok(metadata)
+}
+
+#[derive(Debug)]
+pub enum WireError {
+    Io(std::io::Error),
+    UnknownFrameKind(u8),
+    FrameTooLarge {
+        length: usize,
+        max: u32,
+    },
+    InvalidUtf8,
+    Malformed(&'static str),
+    UnexpectedFrame,
+    UnexpectedEof,
+    /// The peer sent message frames of a stream without credit for them
+    WindowExceeded {
+        stream_id: u32,
+    },
+}
+
+impl std::fmt::Display for WireError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO error: {}", e),
+            Self::UnknownFrameKind(kind) => write!(f, "Unknown frame kind {:#04x}", kind),
+            Self::FrameTooLarge { length, max } => write!(f, "Frame too large: {} bytes, max {}", length, max),
+            Self::InvalidUtf8 => write!(f, "Invalid UTF-8 in frame"),
+            Self::Malformed(reason) => write!(f, "Malformed frame: {}", reason),
+            Self::UnexpectedFrame => write!(f, "Unexpected frame"),
+            Self::UnexpectedEof => write!(f, "Unexpected end of stream"),
+            Self::WindowExceeded { stream_id } => write!(f, "Stream {} sent messages beyond its window", stream_id),
+        }
+    }
+}
+
+impl std::convert::From<std::io::Error> for WireError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl std::error::Error for WireError {}
+
+/// Frame decoder over an `AsyncRead`
+pub struct FrameReader<R> {
+    inner: R,
+    buffer: BytesMut,
+    max_length: u32,
+    eof: bool,
+}
+
+impl<R: AsyncRead + Unpin> FrameReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_max_length(inner, DEFAULT_MAX_FRAME_LENGTH)
+    }
+
+    pub fn with_max_length(inner: R, max_length: u32) -> Self {
+        Self {
+            inner,
+            buffer: BytesMut::new(),
+            max_length,
+            eof: false,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for FrameReader<R> {
+    type Item = Result<Frame, WireError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            match Frame::decode(&mut this.buffer, this.max_length) {
+                Ok(Some(frame)) => return Poll::Ready(Some(Ok(frame))),
+                Ok(None) => {}
+                Err(e) => {
+                    this.eof = true;
+                    this.buffer.clear();
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+            if this.eof {
+                if this.buffer.is_empty() {
+                    return Poll::Ready(None);
+                }
+                this.buffer.clear();
+                return Poll::Ready(Some(Err(WireError::UnexpectedEof)));
+            }
+            let mut chunk = [0u8; READ_CHUNK_SIZE];
+            match ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk)) {
+                Ok(0) => this.eof = true,
+                Ok(n) => this.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) => {
+                    this.eof = true;
+                    this.buffer.clear();
+                    return Poll::Ready(Some(Err(e.into())));
+                }
+            }
+        }
+    }
+}
+
+/// Frame encoder over an `AsyncWrite`
+pub struct FrameWriter<W> {
+    inner: W,
+    buffer: BytesMut,
+}
+
+impl<W: AsyncWrite + Unpin> FrameWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buffer: BytesMut::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn poll_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), WireError>> {
+        while !self.buffer.is_empty() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.buffer))?;
+            if n == 0 {
+                return Poll::Ready(Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into()));
+            }
+            self.buffer.advance(n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> Sink<Frame> for FrameWriter<W> {
+    type Error = WireError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.buffer.len() >= WRITE_HIGH_WATER {
+            self.poll_write_buffer(cx)
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Frame) -> Result<(), Self::Error> {
+        item.encode(&mut self.buffer)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.poll_write_buffer(cx))?;
+        Poll::Ready(Ok(ready!(Pin::new(&mut self.inner).poll_flush(cx))?))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.poll_write_buffer(cx))?;
+        Poll::Ready(Ok(ready!(Pin::new(&mut self.inner).poll_close(cx))?))
+    }
+}
+
+/// Convert a stream of messages into message frames, terminated by an end or error frame
+pub fn encode_messages<S>(stream_id: u32, input: S) -> EncodeMessages<S> {
+    EncodeMessages {
+        stream_id,
+        inner: input,
+        done: false,
+    }
+}
+
+pub struct EncodeMessages<S> {
+    stream_id: u32,
+    inner: S,
+    done: bool,
+}
+
+impl<S: Stream<Item = Result<Bytes, ServiceError>> + Unpin> Stream for EncodeMessages<S> {
+    type Item = Frame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let stream_id = self.stream_id;
+        match ready!(self.inner.poll_next_unpin(cx)) {
+            Some(Ok(payload)) => Poll::Ready(Some(Frame::message(stream_id, payload))),
+            Some(Err(e)) => {
+                self.done = true;
+                Poll::Ready(Some(Frame::error(stream_id, e.to_string())))
+            }
+            None => {
+                self.done = true;
+                Poll::Ready(Some(Frame::end(stream_id)))
+            }
+        }
+    }
+}
+
+/// Convert the frames of one call into the stream of messages they carry.
+///
+/// The stream ends on an end or successful trailer frame, or yields an error on an error
+/// or failed trailer frame, or if the frames run out before the call is finished.
+pub fn decode_messages<S>(frames: S) -> DecodeMessages<S> {
+    DecodeMessages {
+        inner: frames,
+        ctx: None,
+        done: false,
+    }
+}
+
+/// Like [decode_messages], but also completes the call context with the received trailer
+pub fn decode_response<S>(frames: S, ctx: CallContext) -> DecodeMessages<S> {
+    DecodeMessages {
+        inner: frames,
+        ctx: Some(ctx),
+        done: false,
+    }
+}
+
+pub struct DecodeMessages<S> {
+    inner: S,
+    ctx: Option<CallContext>,
+    done: bool,
+}
+
+impl<S> DecodeMessages<S> {
+    fn finish(&mut self, error: Option<&ServiceError>) {
+        self.done = true;
+        if let Some(ctx) = self.ctx.take() {
+            ctx.complete(match error {
+                Some(e) => Trailer::new(e.status(), Metadata::new()),
+                None => Trailer::ok(Metadata::new()),
+            });
+        }
+    }
+}
+
+impl<S: Stream<Item = Result<Frame, WireError>> + Unpin> Stream for DecodeMessages<S> {
+    type Item = Result<Bytes, ServiceError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        if let Some(ctx) = &self.ctx {
+            if ctx.poll_cancelled(cx).is_ready() {
+                let e = ServiceError::from(Status::cancelled("Call cancelled"));
+                self.finish(Some(&e));
+                return Poll::Ready(Some(Err(e)));
+            }
+        }
+        let result = match ready!(self.inner.poll_next_unpin(cx)) {
+            Some(Ok(Frame { body: FrameBody::Message(payload), .. })) => return Poll::Ready(Some(Ok(payload))),
+            Some(Ok(Frame { body: FrameBody::End, .. })) => None,
+            Some(Ok(Frame { body: FrameBody::Error(message), .. })) => Some(Err(ServiceError::Remote(message))),
+            Some(Ok(Frame { body: FrameBody::Trailer(trailer), .. })) => {
+                let result = trailer.result().err().map(Err);
+                self.done = true;
+                if let Some(ctx) = self.ctx.take() {
+                    ctx.complete(trailer);
+                }
+                return Poll::Ready(result);
+            }
+            Some(Ok(Frame { body: FrameBody::Call(_) | FrameBody::Window(_), .. })) => {
+                Some(Err(WireError::UnexpectedFrame.into()))
+            }
+            Some(Err(e)) => Some(Err(e.into())),
+            None => Some(Err(WireError::UnexpectedEof.into())),
+        };
+        self.finish(result.as_ref().and_then(|r| r.as_ref().err()));
+        Poll::Ready(result)
+    }
+}
+
+impl<S> Drop for DecodeMessages<S> {
+    fn drop(&mut self) {
+        if let Some(ctx) = self.ctx.take() {
+            if ctx.complete(Trailer::new(Status::cancelled("Response dropped before completion"), Metadata::new())) {
+                ctx.cancel();
+            }
+        }
+    }
+}
+
+/// Split the frames of a call received by a server into its input messages
+/// and a future which watches for the client going away.
+///
+/// The future keeps reading frames after the input has ended, and resolves once the client
+/// aborts the call or the frames run out, cancelling the call context if it is not complete.
+/// Run it alongside the call, for example with `futures::future::select`.
+pub fn server_input<'a, R>(
+    frames: R,
+    ctx: &CallContext,
+) -> (DecodeMessages<mpsc::Receiver<Result<Frame, WireError>>>, impl Future<Output = ()> + 'a)
+where
+    R: Stream<Item = Result<Frame, WireError>> + Unpin + 'a,
+{
+    let (mut tx, rx) = mpsc::channel(1);
+    let ctx = ctx.clone();
+    let watch = async move {
+        let mut frames = frames;
+        while let Some(frame) = frames.next().await {
+            let aborted = !matches!(frame, Ok(Frame { body: FrameBody::Message(_) | FrameBody::End, .. }));
+            // the service may have stopped reading its input, keep watching regardless
+            let _ = tx.send(frame).await;
+            if aborted {
+                break;
+            }
+        }
+        if !ctx.is_complete() {
+            ctx.cancel();
+        }
+    };
+    (decode_messages(rx), watch)
+}
+
+/// Perform a call over a frame transport carrying only this call.
+///
+/// The returned stream sends the call and its input while yielding the response messages.
+/// The call context is completed with the trailer sent by the server.
+pub fn client_call<'a, K, R, S>(
+    mut sink: K,
+    frames: R,
+    stream_id: u32,
+    header: CallHeader,
+    ctx: CallContext,
+    input: S,
+) -> impl Stream<Item = Result<Bytes, ServiceError>> + Unpin + 'a
+where
+    K: Sink<Frame, Error = WireError> + Unpin + 'a,
+    R: Stream<Item = Result<Frame, WireError>> + Unpin + 'a,
+    S: Stream<Item = Result<Bytes, ServiceError>> + Unpin + 'a,
+{
+    let send = Box::pin(async move {
+        sink.feed(Frame::call(stream_id, header)).await?;
+        sink.send_all(&mut encode_messages(stream_id, input).map(Ok)).await
+    });
+    SendWhileReceiving {
+        send: Some(send),
+        recv: decode_response(frames, ctx),
+    }
+}
+
+/// Answer a call with the output of a server service, finishing with a trailer frame.
+///
+/// The trailer carries the trailing metadata of the call context, which is also completed with it.
+pub async fn respond<K, S>(
+    sink: &mut K,
+    stream_id: u32,
+    ctx: &CallContext,
+    output: Result<S, ServiceError>,
+) -> Result<(), WireError>
+where
+    K: Sink<Frame, Error = WireError> + Unpin,
+    S: Stream<Item = Result<Bytes, ServiceError>> + Unpin,
+{
+    let result = match output {
+        Ok(stream) => {
+            let mut frames = EncodeResponse {
+                stream_id,
+                inner: stream,
+                ctx,
+                done: false,
+            };
+            sink.send_all(&mut (&mut frames).map(Ok)).await
+        }
+        Err(e) => {
+            let trailer = Trailer::new(e.status(), ctx.take_trailing_metadata());
+            ctx.complete(trailer.clone());
+            sink.send(Frame::trailer(stream_id, trailer)).await
+        }
+    };
+    if result.is_err() {
+        // the client can no longer receive the response
+        ctx.cancel();
+    }
+    result
+}
+
+struct EncodeResponse<'c, S> {
+    stream_id: u32,
+    inner: S,
+    ctx: &'c CallContext,
+    done: bool,
+}
+
+impl<S: Stream<Item = Result<Bytes, ServiceError>> + Unpin> Stream for EncodeResponse<'_, S> {
+    type Item = Frame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        if self.ctx.poll_cancelled(cx).is_ready() {
+            self.done = true;
+            let trailer = Trailer::new(Status::cancelled("Call cancelled"), self.ctx.take_trailing_metadata());
+            self.ctx.complete(trailer.clone());
+            return Poll::Ready(Some(Frame::trailer(self.stream_id, trailer)));
+        }
+        let trailer = match ready!(self.inner.poll_next_unpin(cx)) {
+            Some(Ok(payload)) => return Poll::Ready(Some(Frame::message(self.stream_id, payload))),
+            Some(Err(e)) => Trailer::new(e.status(), self.ctx.take_trailing_metadata()),
+            None => Trailer::ok(self.ctx.take_trailing_metadata()),
+        };
+        self.done = true;
+        self.ctx.complete(trailer.clone());
+        Poll::Ready(Some(Frame::trailer(self.stream_id, trailer)))
+    }
+}
+
+struct SendWhileReceiving<F, S> {
+    send: Option<F>,
+    recv: DecodeMessages<S>,
+}
+
+impl<F, S> Stream for SendWhileReceiving<F, S>
+where
+    F: Future<Output = Result<(), WireError>> + Unpin,
+    S: Stream<Item = Result<Frame, WireError>> + Unpin,
+{
+    type Item = Result<Bytes, ServiceError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.recv.done {
+            return Poll::Ready(None);
+        }
+        if let Some(send) = self.send.as_mut() {
+            if let Poll::Ready(result) = Pin::new(send).poll(cx) {
+                self.send = None;
+                if let Err(e) = result {
+                    let e = ServiceError::from(e);
+                    self.recv.finish(Some(&e));
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+        let item = ready!(self.recv.poll_next_unpin(cx));
+        if self.recv.done {
+            // the response is complete, any unsent input is no longer wanted
+            self.send = None;
+        }
+        Poll::Ready(item)
+    }
+}